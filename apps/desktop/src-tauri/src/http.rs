@@ -3,15 +3,83 @@
 //! This module provides HTTP request functionality that supports SOCKS5, HTTP, and HTTPS proxies.
 //! It's designed to be called from the frontend via Tauri commands.
 
-use reqwest::{Client, Proxy};
+use crate::secrets::{self, SecretStore};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use reqwest::header::HeaderValue;
+use reqwest::{Client, Proxy, RequestBuilder};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tauri::{AppHandle, Emitter};
+use tauri::ipc::{Channel, JavaScriptChannelId, Response};
+use tauri::{AppHandle, Emitter, State, Webview};
+use tokio::sync::Notify;
 use futures_util::StreamExt;
 
+mod body;
+mod cache;
+mod client;
+mod coalesce;
+mod decode;
+mod diagnostics;
+mod egress;
+mod error;
+mod failover;
+mod pac;
+mod pinning;
+mod ratelimit;
+mod retry;
+mod routing;
+mod scheduler;
+mod sse;
+mod streams;
+mod system_proxy;
+mod timeout;
+mod timing;
+mod tls;
+
+use body::ResponseType;
+pub use body::SaveTargets;
+use cache::{CacheConfig, CachePolicy, CacheStats, CachedResponse};
+pub use cache::ResponseCache;
+pub use client::ClientPool;
+use coalesce::{ChunkQueue, CoalesceConfig};
+use decode::BodyDecoder;
+use diagnostics::ProxyReport;
+use egress::{AddressGuard, EgressConfig};
+pub use egress::EgressPolicy;
+pub use error::HttpError;
+use retry::{RetryNotice, RetryPolicy};
+use routing::{ProxyRoute, ProxyRule};
+use scheduler::{Priority, SchedulerConfig, SchedulerStatus, Ticket};
+pub use scheduler::Scheduler;
+use pinning::{PinSet, PinViolation};
+pub use pinning::PinStore;
+use ratelimit::{LaneStatus, RateLimitConfig};
+pub use ratelimit::RateLimiter;
+use sse::SseEvent;
+use streams::StreamInfo;
+use system_proxy::SystemProxy;
+pub use streams::StreamRegistry;
+use timeout::Deadlines;
+use timing::{Timer, TimingMetrics};
+use tls::{TlsConfig, TlsReport};
+
+/// Global event carrying stream chunks when no per-request channel is given
+const STREAM_EVENT: &str = "http-stream-chunk";
+
+/// Global event carrying retry notices for requests without a channel
+const RETRY_EVENT: &str = "http-retry";
+
+/// Global event carrying timing for `bytes` responses, which have no `HttpResponse` to hold it
+const TIMING_EVENT: &str = "http-timing";
+
+/// Global event carrying the queue position of requests without a channel while they wait for a slot
+const QUEUE_EVENT: &str = "http-queue";
+
 /// Proxy configuration from frontend
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct ProxyConfig {
     #[serde(rename = "type")]
     pub proxy_type: String,
@@ -19,6 +87,46 @@ pub struct ProxyConfig {
     pub port: u16,
     pub username: Option<String>,
     pub password: Option<String>,
+    /// PAC script URL or absolute path for the `pac` type
+    pub pac_url: Option<String>,
+    /// Per-host overrides, checked in order before these settings apply
+    #[serde(default)]
+    pub rules: Vec<ProxyRule>,
+    /// Profiles tried in order when connecting through these settings fails; a `none` one connects directly
+    #[serde(default)]
+    pub failover: Vec<ProxyConfig>,
+}
+
+impl ProxyConfig {
+    /// Whether these settings route through a proxy; disabled or incomplete ones mean a direct connection
+    fn is_enabled(&self) -> bool {
+        match self.proxy_type.as_str() {
+            "system" => true,
+            "pac" => self.pac_url.as_deref().is_some_and(|url| !url.trim().is_empty()),
+            "none" => false,
+            _ => !self.host.is_empty() && self.port > 0,
+        }
+    }
+
+    /// Proxies are detected from the environment and desktop settings; host and port are unused
+    fn is_system(&self) -> bool {
+        self.proxy_type == "system"
+    }
+
+    /// Proxies are picked per request by the script at `pac_url`; host and port are unused
+    fn is_pac(&self) -> bool {
+        self.proxy_type == "pac"
+    }
+}
+
+/// Check that every PAC script the settings may fetch, failover profiles included, is at an allowed host
+fn check_pac_locations(egress: &EgressPolicy, proxy: Option<&ProxyConfig>) -> Result<(), HttpError> {
+    failover::candidates(proxy)
+        .iter()
+        .flatten()
+        .filter(|p| p.is_pac())
+        .filter_map(|p| p.pac_url.as_deref())
+        .try_for_each(|url| egress.check_pac(url))
 }
 
 /// HTTP request configuration
@@ -26,13 +134,44 @@ pub struct ProxyConfig {
 pub struct HttpRequestConfig {
     pub url: String,
     pub method: String,
+    /// Header values may embed `{{secret:name}}` placeholders, resolved just before sending if the secret is bound to the URL's host
     pub headers: HashMap<String, String>,
     pub body: Option<String>,
     pub proxy: Option<ProxyConfig>,
+    /// Extra roots, client identity and minimum version; the system roots alone when absent
+    pub tls: Option<TlsConfig>,
+    /// Total deadline for the whole request, including reading the body; streams have none unless set
     pub timeout_ms: Option<u64>,
+    /// Deadline for establishing the connection, including any proxy handshake
+    pub connect_timeout_ms: Option<u64>,
+    /// Deadline for the response headers to arrive, including any retries
+    pub first_byte_timeout_ms: Option<u64>,
+    /// Longest allowed gap between body chunks of a stream
+    pub idle_timeout_ms: Option<u64>,
     #[allow(dead_code)]
     pub stream: Option<bool>,
     pub request_id: Option<String>,
+    /// Parse the response as Server-Sent Events and emit one event per message
+    pub sse: Option<bool>,
+    /// Headers whose values are read from the secret store, as header name -> secret name; bound to hosts like placeholders
+    pub secret_headers: Option<HashMap<String, String>>,
+    /// Retry 429/5xx responses with exponential backoff; no retries when absent
+    pub retry: Option<RetryPolicy>,
+    /// How `http_request` returns the body; defaults to text
+    pub response_type: Option<ResponseType>,
+    /// Destination when `response_type` is `file`: inside a download folder, or picked via `http_choose_save_path`
+    pub save_path: Option<String>,
+    /// Batch stream chunks before emitting them; each network chunk is emitted on its own when absent
+    pub coalesce: Option<CoalesceConfig>,
+    /// Scheduling class when concurrency limits make requests wait; `bidding` when absent
+    pub priority: Option<Priority>,
+    /// Discussion the request belongs to, so busy discussions can't starve the others
+    pub session_id: Option<String>,
+    /// Answer from and store in the response cache; never cached when absent
+    pub cache: Option<CachePolicy>,
+    /// Pattern of the proxy rule that picked `proxy`, set by `apply_proxy_rules`
+    #[serde(skip)]
+    matched_rule: Option<String>,
 }
 
 /// HTTP response returned to frontend
@@ -41,16 +180,175 @@ pub struct HttpResponse {
     pub status: u16,
     pub headers: HashMap<String, String>,
     pub body: String,
-    pub error: Option<String>,
+    pub error: Option<HttpError>,
+    pub timing: TimingMetrics,
+    /// Route the request took, which may be a failover profile; absent when served from the cache
+    pub route: Option<ProxyRoute>,
+    /// Whether the response came from the response cache instead of the network
+    pub cached: bool,
+}
+
+/// Timing of a `bytes` response, sent on the `http-timing` event
+#[derive(Debug, Clone, Serialize)]
+struct TimingNotice<'a> {
+    request_id: &'a str,
+    timing: TimingMetrics,
+    /// Absent when the response came from the cache
+    route: Option<ProxyRoute>,
+}
+
+/// Place of a waiting request in the scheduler queue, sent on the `http-queue` event
+#[derive(Debug, Clone, Serialize)]
+struct QueueNotice<'a> {
+    request_id: &'a str,
+    /// 1 for the next request to get a slot
+    position: usize,
 }
 
 /// Stream chunk event sent to frontend
 #[derive(Debug, Clone, Serialize)]
 pub struct StreamChunk {
     pub request_id: String,
+    /// Position in the stream, counting from 0; pass it to `http_stream_attach` to resume
+    pub offset: u64,
     pub chunk: String,
     pub done: bool,
-    pub error: Option<String>,
+    /// Set on the final chunk when the stream failed, timed out or was cancelled
+    pub error: Option<HttpError>,
+    /// Parsed SSE message, only present when the request was made with `sse: true`
+    pub event: Option<SseEvent>,
+    /// SSE messages of a coalesced batch, in order; used instead of `event` when coalescing
+    pub events: Option<Vec<SseEvent>>,
+    /// How long network reading was paused waiting for space in the backend delivery queue, in ms;
+    /// not a measure of how far behind the webview is
+    pub queue_lag_ms: Option<u64>,
+    /// Retry about to happen; only sent on per-request channels
+    pub retry: Option<RetryNotice>,
+    /// Where the request's time went, set on the final chunk
+    pub timing: Option<TimingMetrics>,
+    /// Route the request took, set on the final chunk once it connected
+    pub route: Option<ProxyRoute>,
+    /// Place in the scheduler queue, 1 for next; sent while waiting for a slot whenever it changes
+    pub queue_position: Option<usize>,
+}
+
+impl StreamChunk {
+    fn data(request_id: &str, chunk: String) -> Self {
+        Self {
+            request_id: request_id.to_string(),
+            offset: 0,
+            chunk,
+            done: false,
+            error: None,
+            event: None,
+            events: None,
+            queue_lag_ms: None,
+            retry: None,
+            timing: None,
+            route: None,
+            queue_position: None,
+        }
+    }
+
+    fn sse(request_id: &str, event: SseEvent) -> Self {
+        Self {
+            event: Some(event),
+            ..Self::data(request_id, String::new())
+        }
+    }
+
+    fn retry(notice: RetryNotice) -> Self {
+        let mut chunk = Self::data(&notice.request_id, String::new());
+        chunk.retry = Some(notice);
+        chunk
+    }
+
+    fn queued(request_id: &str, position: usize) -> Self {
+        Self {
+            queue_position: Some(position),
+            ..Self::data(request_id, String::new())
+        }
+    }
+
+    fn done(request_id: &str) -> Self {
+        Self {
+            done: true,
+            ..Self::data(request_id, String::new())
+        }
+    }
+
+    fn error(request_id: &str, error: HttpError) -> Self {
+        Self {
+            error: Some(error),
+            ..Self::done(request_id)
+        }
+    }
+}
+
+/// Where a stream's events are delivered
+#[derive(Clone)]
+enum StreamSink {
+    /// Per-request IPC channel: ordered and delivered only to the caller
+    Channel(Channel<StreamChunk>),
+    /// Compatibility mode: broadcast on the global `http-stream-chunk` event
+    Event(AppHandle),
+}
+
+impl StreamSink {
+    fn new(app: AppHandle, webview: Webview, channel: Option<JavaScriptChannelId>) -> Self {
+        match channel {
+            Some(id) => StreamSink::Channel(id.channel_on(webview)),
+            None => StreamSink::Event(app),
+        }
+    }
+
+    fn send(&self, chunk: StreamChunk) {
+        let _ = match self {
+            StreamSink::Channel(channel) => channel.send(chunk),
+            // Retry notices keep their own global event in compatibility mode
+            StreamSink::Event(app) => match chunk.retry {
+                Some(notice) => app.emit(RETRY_EVENT, notice),
+                None => app.emit(STREAM_EVENT, chunk),
+            },
+        };
+    }
+}
+
+/// Registry of in-flight streaming requests, held in Tauri managed state
+#[derive(Default)]
+pub struct ActiveRequests {
+    requests: Mutex<HashMap<String, Arc<Notify>>>,
+}
+
+impl ActiveRequests {
+    /// Register a request and return the handle it should wait on for cancellation
+    fn register(&self, request_id: &str) -> Arc<Notify> {
+        let notify = Arc::new(Notify::new());
+        self.requests
+            .lock()
+            .unwrap()
+            .insert(request_id.to_string(), notify.clone());
+        notify
+    }
+
+    /// Remove a finished request, unless its id has since been reused by another one
+    fn unregister(&self, request_id: &str, notify: &Arc<Notify>) {
+        let mut requests = self.requests.lock().unwrap();
+        if requests.get(request_id).is_some_and(|n| Arc::ptr_eq(n, notify)) {
+            requests.remove(request_id);
+        }
+    }
+
+    /// Signal cancellation; returns false if no such request is running
+    fn cancel(&self, request_id: &str) -> bool {
+        match self.requests.lock().unwrap().remove(request_id) {
+            Some(notify) => {
+                notify.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
 }
 
 /// Build proxy URL from config
@@ -64,38 +362,76 @@ fn build_proxy_url(config: &ProxyConfig) -> String {
     format!("{}://{}{}:{}", config.proxy_type, auth, config.host, config.port)
 }
 
-/// Build HTTP client with optional proxy
-fn build_client(proxy_config: Option<&ProxyConfig>, timeout_ms: u64) -> Result<Client, String> {
+/// Build HTTP client with optional proxy; use `ClientPool` rather than calling this per request.
+/// `system` is the detected system proxy setup, used when the proxy type is `system`. Loading
+/// TLS files blocks, so this runs on the blocking pool.
+fn build_client(
+    proxy_config: Option<&ProxyConfig>,
+    tls_config: Option<&TlsConfig>,
+    connect_timeout: Option<Duration>,
+    system: &SystemProxy,
+    guard: Arc<AddressGuard>,
+) -> Result<Client, HttpError> {
+    let system = proxy_config.is_some_and(ProxyConfig::is_system).then_some(system);
+    // Every other name the client resolves, including after redirects and on reconnects, is filtered
+    let proxy_hosts = match (&system, proxy_config) {
+        (Some(system), _) => system.proxy_hosts(),
+        (None, Some(proxy)) if proxy.is_enabled() => vec![proxy.host.trim_matches(['[', ']']).to_string()],
+        _ => Vec::new(),
+    };
+
     let mut builder = Client::builder()
-        .timeout(Duration::from_millis(timeout_ms))
-        .danger_accept_invalid_certs(false);
+        .danger_accept_invalid_certs(false)
+        .dns_resolver(Arc::new(timing::TimedResolver::new(guard, proxy_hosts)))
+        .connector_layer(timing::TimedConnectLayer)
+        .redirect(egress::redirect_policy());
+
+    if let Some(timeout) = connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+
+    // Always our own rustls setup, so the TLS handshake can be timed apart from the connect
+    let mut tls = tls::client_config(tls_config.filter(|t| t.is_custom()))?;
+    tls.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    builder = builder.use_preconfigured_tls(tls);
 
-    if let Some(proxy) = proxy_config {
-        if proxy.proxy_type != "none" && !proxy.host.is_empty() && proxy.port > 0 {
+    // PAC settings never get here: failover resolves them to the routes the script picks
+    if let Some(system) = system {
+        builder = system.apply(builder)?;
+    } else if let Some(proxy) = proxy_config {
+        if proxy.is_enabled() {
             let proxy_url = build_proxy_url(proxy);
 
             let proxy = match proxy.proxy_type.as_str() {
-                "socks5" | "socks5h" => {
-                    Proxy::all(&proxy_url).map_err(|e| format!("Failed to create SOCKS5 proxy: {}", e))?
+                "socks5" | "socks5h" => Proxy::all(&proxy_url).map_err(|e| {
+                    HttpError::InvalidConfig(format!("Failed to create SOCKS5 proxy: {}", e))
+                })?,
+                "http" | "https" => Proxy::all(&proxy_url).map_err(|e| {
+                    HttpError::InvalidConfig(format!("Failed to create HTTP proxy: {}", e))
+                })?,
+                _ => {
+                    return Err(HttpError::InvalidConfig(format!(
+                        "Unsupported proxy type: {}",
+                        proxy.proxy_type
+                    )))
                 }
-                "http" | "https" => {
-                    Proxy::all(&proxy_url).map_err(|e| format!("Failed to create HTTP proxy: {}", e))?
-                }
-                _ => return Err(format!("Unsupported proxy type: {}", proxy.proxy_type)),
             };
 
             builder = builder.proxy(proxy);
         }
     }
 
-    builder.build().map_err(|e| format!("Failed to build HTTP client: {}", e))
+    builder
+        .build()
+        .map_err(|e| HttpError::InvalidConfig(format!("Failed to build HTTP client: {}", e)))
 }
 
-/// Make a non-streaming HTTP request
-#[tauri::command]
-pub async fn http_request(config: HttpRequestConfig) -> Result<HttpResponse, String> {
-    let client = build_client(config.proxy.as_ref(), config.timeout_ms.unwrap_or(120000))?;
-
+/// Build a request from the frontend config, resolving secret-backed headers
+fn build_request(
+    client: &Client,
+    config: &HttpRequestConfig,
+    secrets: &SecretStore,
+) -> Result<RequestBuilder, HttpError> {
     let method = config.method.to_uppercase();
     let mut request = match method.as_str() {
         "GET" => client.get(&config.url),
@@ -103,29 +439,191 @@ pub async fn http_request(config: HttpRequestConfig) -> Result<HttpResponse, Str
         "PUT" => client.put(&config.url),
         "DELETE" => client.delete(&config.url),
         "PATCH" => client.patch(&config.url),
-        _ => return Err(format!("Unsupported HTTP method: {}", method)),
+        _ => {
+            return Err(HttpError::InvalidConfig(format!(
+                "Unsupported HTTP method: {}",
+                method
+            )))
+        }
     };
 
-    // Add headers
+    // Secrets are only filled in for the hosts they are bound to
+    let host = url::Url::parse(&config.url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+
+    // Add headers, filling in secret placeholders
     for (key, value) in &config.headers {
-        request = request.header(key, value);
+        if secrets::is_template(value) {
+            let value = secrets.render(value, &host).map_err(HttpError::InvalidConfig)?;
+            request = request.header(key, secret_header_value(&value)?);
+        } else {
+            request = request.header(key, value);
+        }
+    }
+
+    // Add headers whose values never leave the backend
+    for (key, secret_name) in config.secret_headers.iter().flatten() {
+        let value = secrets.require(secret_name, &host).map_err(HttpError::InvalidConfig)?;
+        request = request.header(key, secret_header_value(&value)?);
     }
 
     // Add body if present
-    if let Some(body) = config.body {
-        request = request.body(body);
+    if let Some(body) = &config.body {
+        request = request.body(body.clone());
     }
 
-    // Send request
-    let response = request.send().await.map_err(|e| {
-        if e.is_connect() {
-            format!("Connection failed (check proxy settings): {}", e)
-        } else if e.is_timeout() {
-            format!("Request timed out: {}", e)
-        } else {
-            format!("Request failed: {}", e)
-        }
+    Ok(request)
+}
+
+/// Header value holding a credential, marked sensitive so it is redacted from debug output
+fn secret_header_value(value: &str) -> Result<HeaderValue, HttpError> {
+    let mut value = HeaderValue::from_str(value).map_err(|_| {
+        HttpError::InvalidConfig("Secret contains characters not allowed in a header".to_string())
     })?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+impl HttpRequestConfig {
+    /// Replace `proxy` with the settings its rules pick for this URL
+    fn apply_proxy_rules(&mut self) -> Result<(), HttpError> {
+        let (rule, proxy) = routing::resolve(self.proxy.as_ref(), &self.url)?;
+        self.matched_rule = rule;
+        self.proxy = proxy;
+        Ok(())
+    }
+
+    /// Whether the request may connect to its destination without a proxy, including on failover
+    fn is_direct(&self) -> bool {
+        failover::candidates(self.proxy.as_ref()).contains(&None)
+    }
+
+    fn ticket(&self) -> Ticket {
+        Ticket {
+            request_id: self.request_id.clone(),
+            host: failover::host_key(&self.url),
+            priority: self.priority.unwrap_or_default(),
+            session_id: self.session_id.clone(),
+        }
+    }
+
+    /// Response cache key, when the request opted in, the cache is on and the request allows storing.
+    /// Secret headers always count, by the secret's name, so the key never depends on a secret's value.
+    fn cache_key(&self, response_type: ResponseType, cache: &ResponseCache) -> Option<String> {
+        let policy = self.cache.as_ref()?;
+        if response_type == ResponseType::File || !cache.is_enabled() || !cache::request_allows(&self.headers).1 {
+            return None;
+        }
+        let mut headers = self.headers.clone();
+        let mut vary = policy.vary.clone();
+        for (header, secret) in self.secret_headers.iter().flatten() {
+            headers.insert(header.clone(), format!("{{{{secret:{}}}}}", secret));
+            vary.push(header.clone());
+        }
+        Some(cache::key(&self.method, &self.url, &headers, self.body.as_deref(), &vary))
+    }
+
+    fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout_ms.map(Duration::from_millis)
+    }
+
+    fn deadlines(&self) -> Deadlines {
+        Deadlines::new(
+            Some(Duration::from_millis(self.timeout_ms.unwrap_or(120000))),
+            self.first_byte_timeout_ms.map(Duration::from_millis),
+            self.idle_timeout_ms.map(Duration::from_millis),
+        )
+    }
+
+    /// A long but healthy generation shouldn't hit a total deadline, so unless the caller sets
+    /// one a stream is bounded by the first-byte and idle deadlines alone
+    fn stream_deadlines(&self) -> Deadlines {
+        Deadlines::new(
+            self.timeout_ms.map(Duration::from_millis),
+            Some(Duration::from_millis(self.first_byte_timeout_ms.unwrap_or(120000))),
+            Some(Duration::from_millis(self.idle_timeout_ms.unwrap_or(120000))),
+        )
+    }
+}
+
+/// Send a request under the config's retry policy, reporting each retry through `on_retry`
+async fn send(
+    request: RequestBuilder,
+    config: &HttpRequestConfig,
+    request_id: &str,
+    timer: &mut Timer,
+    on_retry: impl Fn(RetryNotice),
+) -> Result<reqwest::Response, HttpError> {
+    let bytes = config.body.as_ref().map_or(0, |body| body.len() as u64);
+    timer
+        .send(bytes, retry::send_with_retry(request, &config.method, config.retry.as_ref(), request_id, on_retry))
+        .await
+        .map_err(|e| HttpError::from_reqwest(&e))
+}
+
+/// Id for requests the frontend sent without one
+fn new_request_id() -> String {
+    format!("req_{:016x}", rand::random::<u64>())
+}
+
+/// Make a non-streaming HTTP request.
+///
+/// Resolves to an `HttpResponse` object, or to the raw body bytes when `response_type` is `bytes`;
+/// the timing of those is sent on the `http-timing` event, keyed by `request_id`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn http_request(
+    app: AppHandle,
+    pool: State<'_, ClientPool>,
+    secrets: State<'_, SecretStore>,
+    egress: State<'_, EgressPolicy>,
+    scheduler: State<'_, Scheduler>,
+    limiter: State<'_, RateLimiter>,
+    cache: State<'_, ResponseCache>,
+    targets: State<'_, SaveTargets>,
+    pins: State<'_, PinStore>,
+    mut config: HttpRequestConfig,
+) -> Result<Response, HttpError> {
+    let mut timer = Timer::start();
+    pins.apply(&mut config.tls);
+    let response_type = config.response_type.unwrap_or_default();
+    let save_path = match response_type {
+        ResponseType::File => Some(targets.resolve(config.save_path.as_deref())?),
+        _ => None,
+    };
+    config.apply_proxy_rules()?;
+    check_pac_locations(&egress, config.proxy.as_ref())?;
+    egress.check(&config.url, config.is_direct()).await?;
+
+    // Retry and timing events carry the id, so requests without one get their own
+    let request_id = config.request_id.get_or_insert_with(new_request_id).clone();
+    let request_id = request_id.as_str();
+
+    // Looked up before queueing, so hits don't wait for a slot or rate limit tokens
+    let cache_key = config.cache_key(response_type, &cache);
+    if let Some(hit) = cache_key.as_deref().filter(|_| cache::request_allows(&config.headers).0).and_then(|key| cache.get(key)) {
+        return cached_response(&app, request_id, hit, response_type, timer);
+    }
+
+    // The slot is held until the body is read
+    let tokens = ratelimit::estimate_tokens(config.body.as_deref());
+    let (_slot, permit) = scheduler
+        .admit(&limiter, config.ticket(), &config.url, tokens, |position| {
+            let _ = app.emit(QUEUE_EVENT, QueueNotice { request_id, position });
+        })
+        .await;
+    // Time spent queueing doesn't count against the request's own deadlines
+    let deadlines = config.deadlines();
+
+    // Send request, falling back to the next proxy profile when one can't connect
+    let (response, route) = deadlines
+        .first_byte(failover::send_with_failover(&pool, &egress, &secrets, &config, request_id, &mut timer, |notice| {
+            let _ = app.emit(RETRY_EVENT, notice);
+        }))
+        .await??;
+    permit.observe(response.headers());
 
     let status = response.status().as_u16();
     let mut headers = HashMap::new();
@@ -135,115 +633,442 @@ pub async fn http_request(config: HttpRequestConfig) -> Result<HttpResponse, Str
         }
     }
 
-    let body = response.text().await.map_err(|e| format!("Failed to read response body: {}", e))?;
+    let ttl = config
+        .cache
+        .as_ref()
+        .filter(|_| response.status().is_success())
+        .and_then(|policy| cache::ttl(policy, response.headers()));
+    let store = |body: &[u8]| {
+        if let (Some(key), Some(ttl)) = (cache_key.as_deref(), ttl) {
+            let response = CachedResponse {
+                status,
+                headers: headers.clone(),
+                body: body.to_vec(),
+            };
+            cache.put(key, &response, ttl);
+        }
+    };
 
-    Ok(HttpResponse {
+    // Binary deliveries have nowhere to put an error body, so failures become errors
+    if matches!(response_type, ResponseType::Bytes | ResponseType::File) && !response.status().is_success() {
+        let body = deadlines.total(response.text()).await?.unwrap_or_default();
+        return Err(HttpError::HttpStatus { status, body });
+    }
+
+    let decode_error = |e: reqwest::Error| HttpError::Decode(e.to_string());
+    let body = match response_type {
+        ResponseType::Text => {
+            let text = deadlines.total(response.text()).await?.map_err(decode_error)?;
+            timer.chunk(text.len());
+            store(text.as_bytes());
+            text
+        }
+        ResponseType::Base64 => {
+            let bytes = deadlines.total(response.bytes()).await?.map_err(decode_error)?;
+            timer.chunk(bytes.len());
+            store(&bytes);
+            BASE64.encode(bytes)
+        }
+        ResponseType::Bytes => {
+            let bytes = deadlines.total(response.bytes()).await?.map_err(decode_error)?;
+            timer.chunk(bytes.len());
+            store(&bytes);
+            let notice = TimingNotice {
+                request_id,
+                timing: timer.finish(),
+                route: Some(route),
+            };
+            let _ = app.emit(TIMING_EVENT, notice);
+            return Ok(Response::new(bytes.to_vec()));
+        }
+        ResponseType::File => {
+            let path = save_path.expect("validated above");
+            let written = body::save_to_file(response, &path, &deadlines).await?;
+            timer.chunk(written as usize);
+            String::new()
+        }
+    };
+
+    let response = HttpResponse {
         status,
         headers,
         body,
         error: None,
-    })
+        timing: timer.finish(),
+        route: Some(route),
+        cached: false,
+    };
+    let json = serde_json::to_string(&response).map_err(|e| HttpError::Decode(e.to_string()))?;
+    Ok(Response::new(json))
+}
+
+/// Deliver a cache hit the way `http_request` would have delivered the network response
+fn cached_response(
+    app: &AppHandle,
+    request_id: &str,
+    hit: CachedResponse,
+    response_type: ResponseType,
+    mut timer: Timer,
+) -> Result<Response, HttpError> {
+    timer.chunk(hit.body.len());
+    let body = match response_type {
+        ResponseType::Bytes => {
+            let notice = TimingNotice {
+                request_id,
+                timing: timer.finish(),
+                route: None,
+            };
+            let _ = app.emit(TIMING_EVENT, notice);
+            return Ok(Response::new(hit.body));
+        }
+        ResponseType::Base64 => BASE64.encode(&hit.body),
+        _ => String::from_utf8(hit.body).map_err(|e| HttpError::Decode(e.to_string()))?,
+    };
+    let response = HttpResponse {
+        status: hit.status,
+        headers: hit.headers,
+        body,
+        error: None,
+        timing: timer.finish(),
+        route: None,
+        cached: true,
+    };
+    let json = serde_json::to_string(&response).map_err(|e| HttpError::Decode(e.to_string()))?;
+    Ok(Response::new(json))
 }
 
-/// Make a streaming HTTP request - emits chunks via events
+/// Make a streaming HTTP request.
+///
+/// Chunks go to the `on_event` channel when one is passed, otherwise they are broadcast
+/// on the global `http-stream-chunk` event.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn http_request_stream(
     app: AppHandle,
-    config: HttpRequestConfig,
-) -> Result<(), String> {
-    let request_id = config.request_id.clone().unwrap_or_else(|| "default".to_string());
-    let client = build_client(config.proxy.as_ref(), config.timeout_ms.unwrap_or(120000))?;
+    webview: Webview,
+    active: State<'_, ActiveRequests>,
+    streams: State<'_, StreamRegistry>,
+    pool: State<'_, ClientPool>,
+    secrets: State<'_, SecretStore>,
+    egress: State<'_, EgressPolicy>,
+    scheduler: State<'_, Scheduler>,
+    limiter: State<'_, RateLimiter>,
+    pins: State<'_, PinStore>,
+    mut config: HttpRequestConfig,
+    on_event: Option<JavaScriptChannelId>,
+) -> Result<(), HttpError> {
+    pins.apply(&mut config.tls);
+    // Streams without an id would share one replay buffer and cancel handle, so they get their own
+    let request_id = config.request_id.get_or_insert_with(new_request_id).clone();
+    // Chunks are buffered by the registry so a reloaded webview can reattach
+    streams.start(&request_id, &config.url, StreamSink::new(app, webview, on_event));
+    let mut queue = ChunkQueue::spawn(config.coalesce.as_ref(), {
+        let streams = streams.inner().clone();
+        let request_id = request_id.clone();
+        move |chunk| streams.publish(&request_id, chunk)
+    });
+    let mut timer = Timer::start();
+    let cancel = active.register(&request_id);
+    let mut route = None;
 
-    let method = config.method.to_uppercase();
-    let mut request = match method.as_str() {
-        "GET" => client.get(&config.url),
-        "POST" => client.post(&config.url),
-        "PUT" => client.put(&config.url),
-        "DELETE" => client.delete(&config.url),
-        "PATCH" => client.patch(&config.url),
-        _ => return Err(format!("Unsupported HTTP method: {}", method)),
+    let checked = async {
+        config.apply_proxy_rules()?;
+        check_pac_locations(&egress, config.proxy.as_ref())?;
+        egress.check(&config.url, config.is_direct()).await?;
+        Ok(())
     };
 
-    // Add headers
-    for (key, value) in &config.headers {
-        request = request.header(key, value);
-    }
+    // Dropping the streaming future aborts the underlying reqwest request
+    let result = match checked.await {
+        Err(e) => Err(e),
+        Ok(()) => tokio::select! {
+            result = stream_request(&streams, &mut queue, &mut timer, &mut route, &pool, &egress, &secrets, &scheduler, &limiter, &request_id, config) => result,
+            _ = cancel.notified() => Err(HttpError::Cancelled),
+        },
+    };
 
-    // Add body if present
-    if let Some(body) = config.body {
-        request = request.body(body);
-    }
+    active.unregister(&request_id, &cancel);
 
-    // Send request and stream response
-    let response = request.send().await.map_err(|e| {
-        let error_msg = if e.is_connect() {
-            format!("Connection failed (check proxy settings): {}", e)
-        } else if e.is_timeout() {
-            format!("Request timed out: {}", e)
-        } else {
-            format!("Request failed: {}", e)
-        };
+    // Send the terminal event
+    let mut last = match &result {
+        Ok(()) => StreamChunk::done(&request_id),
+        Err(e) => StreamChunk::error(&request_id, e.clone()),
+    };
+    last.timing = Some(timer.finish());
+    last.route = route;
+    queue.send(last).await;
+    queue.close().await;
 
-        // Emit error event
-        let _ = app.emit("http-stream-chunk", StreamChunk {
-            request_id: request_id.clone(),
-            chunk: String::new(),
-            done: true,
-            error: Some(error_msg.clone()),
-        });
+    result
+}
 
-        error_msg
-    })?;
+/// Reattach to a stream, e.g. after a webview reload, replaying chunks from `from_offset` on.
+/// The stream's previous listener stops receiving chunks.
+#[tauri::command]
+pub fn http_stream_attach(
+    app: AppHandle,
+    webview: Webview,
+    streams: State<'_, StreamRegistry>,
+    request_id: String,
+    from_offset: Option<u64>,
+    on_event: JavaScriptChannelId,
+) -> Result<StreamInfo, HttpError> {
+    let sink = StreamSink::new(app, webview, Some(on_event));
+    streams
+        .attach(&request_id, from_offset.unwrap_or(0), sink)
+        .ok_or_else(|| HttpError::InvalidConfig(format!("No buffered stream with id '{}'", request_id)))
+}
 
-    if !response.status().is_success() {
-        let status = response.status().as_u16();
-        let body = response.text().await.unwrap_or_default();
-        let error_msg = format!("HTTP {}: {}", status, body);
+/// Streams still running or recently finished, oldest first
+#[tauri::command]
+pub fn http_stream_list(streams: State<'_, StreamRegistry>) -> Vec<StreamInfo> {
+    streams.list()
+}
 
-        let _ = app.emit("http-stream-chunk", StreamChunk {
-            request_id,
-            chunk: String::new(),
-            done: true,
-            error: Some(error_msg.clone()),
-        });
+/// Cancel an in-flight streaming request by id
+#[tauri::command]
+pub fn http_cancel(active: State<'_, ActiveRequests>, request_id: String) -> bool {
+    active.cancel(&request_id)
+}
+
+/// Report the proxy settings the `system` proxy type would use, without passwords
+#[tauri::command]
+pub async fn proxy_detect() -> SystemProxy {
+    // Detection may spawn `gsettings`, so keep it off the async workers
+    tokio::task::spawn_blocking(|| system_proxy::detect().redacted())
+        .await
+        .unwrap_or_default()
+}
+
+/// Preview which route, direct or through which proxy, a request to `url` would take
+#[tauri::command]
+pub fn proxy_resolve(proxy: Option<ProxyConfig>, url: String) -> Result<ProxyRoute, HttpError> {
+    routing::preview(proxy.as_ref(), &url)
+}
 
-        return Err(error_msg);
+/// Check each step of reaching `url` through the proxy settings, e.g. after "Connection failed"
+#[tauri::command]
+pub async fn proxy_test(
+    egress: State<'_, EgressPolicy>,
+    pins: State<'_, PinStore>,
+    proxy: Option<ProxyConfig>,
+    mut tls: Option<TlsConfig>,
+    url: String,
+) -> Result<ProxyReport, HttpError> {
+    pins.apply(&mut tls);
+    let (_, routed) = routing::resolve(proxy.as_ref(), &url)?;
+    check_pac_locations(&egress, routed.as_ref())?;
+    egress.check(&url, !routed.is_some_and(|p| p.is_enabled())).await?;
+    diagnostics::run(proxy.as_ref(), tls.as_ref(), &url, &egress).await
+}
+
+/// Report the certificate chain `url` presents and whether the TLS settings trust it
+#[tauri::command]
+pub async fn tls_inspect(
+    egress: State<'_, EgressPolicy>,
+    pins: State<'_, PinStore>,
+    proxy: Option<ProxyConfig>,
+    mut tls: Option<TlsConfig>,
+    url: String,
+) -> Result<TlsReport, HttpError> {
+    pins.apply(&mut tls);
+    let (_, routed) = routing::resolve(proxy.as_ref(), &url)?;
+    check_pac_locations(&egress, routed.as_ref())?;
+    egress.check(&url, !routed.is_some_and(|p| p.is_enabled())).await?;
+    tls::inspect(proxy.as_ref(), tls.as_ref(), &url, &egress).await
+}
+
+/// Recent certificate pin mismatches, oldest first, including those let through by report-only pin sets
+#[tauri::command]
+pub fn tls_pin_reports() -> Vec<PinViolation> {
+    pinning::recent()
+}
+
+/// Replace the certificate pin sets. Added pins apply at once; a change that lets a host present
+/// other keys or only reports its mismatches applies only once the user allows it in a native dialog.
+#[tauri::command]
+pub async fn tls_set_pins(app: AppHandle, store: State<'_, PinStore>, pins: Vec<PinSet>) -> Result<(), HttpError> {
+    use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+    let confirmed = match store.loosening(&pins) {
+        None => true,
+        Some(relaxed) => tokio::task::spawn_blocking(move || {
+            app.dialog()
+                .message(format!("Certificate pinning would be relaxed for:\n\n{}", relaxed))
+                .title("Relax certificate pins?")
+                .kind(MessageDialogKind::Warning)
+                .buttons(MessageDialogButtons::OkCancelCustom("Relax".into(), "Cancel".into()))
+                .blocking_show()
+        })
+        .await
+        .map_err(|e| HttpError::Request(e.to_string()))?,
+    };
+    store.set(pins, confirmed)
+}
+
+/// Replace the global and per-host concurrency limits
+#[tauri::command]
+pub fn scheduler_configure(scheduler: State<'_, Scheduler>, config: SchedulerConfig) {
+    scheduler.configure(config);
+}
+
+/// Requests running and waiting for a slot, in the order they will get one
+#[tauri::command]
+pub fn scheduler_status(scheduler: State<'_, Scheduler>) -> SchedulerStatus {
+    scheduler.status()
+}
+
+/// Replace the per-host request and token limits from the settings
+#[tauri::command]
+pub fn rate_limit_configure(limiter: State<'_, RateLimiter>, limits: Vec<RateLimitConfig>) {
+    limiter.configure(limits);
+}
+
+/// Current bucket levels and queue lengths per host
+#[tauri::command]
+pub fn rate_limit_status(limiter: State<'_, RateLimiter>) -> Vec<LaneStatus> {
+    limiter.status()
+}
+
+/// Turn the response cache on or off and set its size limit
+#[tauri::command]
+pub fn cache_configure(cache: State<'_, ResponseCache>, config: CacheConfig) {
+    cache.configure(config);
+}
+
+/// Entries, size and hit counts of the response cache
+#[tauri::command]
+pub fn cache_stats(cache: State<'_, ResponseCache>) -> CacheStats {
+    cache.stats()
+}
+
+/// Delete every stored response, e.g. to see fresh search results
+#[tauri::command]
+pub fn cache_clear(cache: State<'_, ResponseCache>) {
+    cache.clear();
+}
+
+/// Replace the egress allowlist, e.g. after provider base URLs or MCP servers change.
+/// Narrowing applies at once; widening applies only once the user allows it in a native dialog.
+#[tauri::command]
+pub async fn egress_set_policy(
+    app: AppHandle,
+    egress: State<'_, EgressPolicy>,
+    policy: EgressConfig,
+) -> Result<(), HttpError> {
+    use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+    let confirmed = match egress.widening(&policy)? {
+        None => true,
+        Some(added) => tokio::task::spawn_blocking(move || {
+            app.dialog()
+                .message(format!("The app wants to connect to:\n\n{}", added))
+                .title("Allow network access?")
+                .kind(MessageDialogKind::Warning)
+                .buttons(MessageDialogButtons::OkCancelCustom("Allow".into(), "Cancel".into()))
+                .blocking_show()
+        })
+        .await
+        .map_err(|e| HttpError::Request(e.to_string()))?,
+    };
+    egress.set(&policy, confirmed)
+}
+
+/// Ask the user where to save a download; the picked path becomes a valid `save_path` for one request
+#[tauri::command]
+pub async fn http_choose_save_path(
+    app: AppHandle,
+    targets: State<'_, SaveTargets>,
+    file_name: Option<String>,
+) -> Result<Option<String>, HttpError> {
+    use tauri_plugin_dialog::DialogExt;
+
+    let picked = tokio::task::spawn_blocking(move || {
+        let mut dialog = app.dialog().file();
+        if let Some(name) = file_name {
+            dialog = dialog.set_file_name(name);
+        }
+        dialog.blocking_save_file()
+    })
+    .await
+    .map_err(|e| HttpError::Request(e.to_string()))?;
+
+    let Some(path) = picked.and_then(|p| p.into_path().ok()) else {
+        return Ok(None);
+    };
+    let display = path.to_string_lossy().into_owned();
+    targets.grant(path);
+    Ok(Some(display))
+}
+
+/// Drop pooled connections and remembered failover routes, e.g. after proxy settings change or the network switches
+#[tauri::command]
+pub fn http_reset_clients(pool: State<'_, ClientPool>) {
+    pool.clear();
+}
+
+/// Send the request and forward the response body as chunk events
+#[allow(clippy::too_many_arguments)]
+async fn stream_request(
+    streams: &StreamRegistry,
+    queue: &mut ChunkQueue,
+    timer: &mut Timer,
+    route: &mut Option<ProxyRoute>,
+    pool: &ClientPool,
+    egress: &EgressPolicy,
+    secrets: &SecretStore,
+    scheduler: &Scheduler,
+    limiter: &RateLimiter,
+    request_id: &str,
+    config: HttpRequestConfig,
+) -> Result<(), HttpError> {
+    let mut decoder = BodyDecoder::new(config.sse.unwrap_or(false));
+    // The slot is held until the stream ends
+    let tokens = ratelimit::estimate_tokens(config.body.as_deref());
+    let (_slot, permit) = scheduler
+        .admit(limiter, config.ticket(), &config.url, tokens, |position| {
+            streams.publish(request_id, StreamChunk::queued(request_id, position))
+        })
+        .await;
+    let deadlines = config.stream_deadlines();
+
+    // Send request, falling back to the next proxy profile when one can't connect
+    let (response, taken) = deadlines
+        .first_byte(failover::send_with_failover(pool, egress, secrets, &config, request_id, timer, |notice| {
+            streams.publish(request_id, StreamChunk::retry(notice))
+        }))
+        .await??;
+    *route = Some(taken);
+    permit.observe(response.headers());
+
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let body = deadlines
+            .total(response.text())
+            .await
+            .ok()
+            .and_then(Result::ok)
+            .unwrap_or_default();
+        return Err(HttpError::HttpStatus { status, body });
     }
 
     // Stream the response body
     let mut stream = response.bytes_stream();
 
-    while let Some(chunk_result) = stream.next().await {
-        match chunk_result {
-            Ok(bytes) => {
-                if let Ok(text) = String::from_utf8(bytes.to_vec()) {
-                    let _ = app.emit("http-stream-chunk", StreamChunk {
-                        request_id: request_id.clone(),
-                        chunk: text,
-                        done: false,
-                        error: None,
-                    });
-                }
-            }
-            Err(e) => {
-                let _ = app.emit("http-stream-chunk", StreamChunk {
-                    request_id: request_id.clone(),
-                    chunk: String::new(),
-                    done: true,
-                    error: Some(format!("Stream error: {}", e)),
-                });
-                return Err(format!("Stream error: {}", e));
-            }
+    while let Some(chunk_result) = deadlines.next_chunk(stream.next()).await? {
+        let bytes = chunk_result.map_err(|e| HttpError::from_reqwest(&e))?;
+        timer.chunk(bytes.len());
+        for chunk in decoder.push(request_id, &bytes) {
+            queue.send(chunk).await;
+        }
+        if decoder.is_finished() {
+            break;
         }
     }
 
-    // Send completion event
-    let _ = app.emit("http-stream-chunk", StreamChunk {
-        request_id,
-        chunk: String::new(),
-        done: true,
-        error: None,
-    });
+    for chunk in decoder.finish(request_id) {
+        queue.send(chunk).await;
+    }
 
     Ok(())
 }
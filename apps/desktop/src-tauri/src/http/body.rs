@@ -0,0 +1,204 @@
+//! Response body delivery for `http_request`
+//!
+//! Text is the default, but fonts, images and audio need to reach the frontend
+//! losslessly, either encoded, as raw IPC bytes, or written straight to disk.
+
+use super::timeout::Deadlines;
+use super::HttpError;
+use futures_util::StreamExt;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use tokio::io::AsyncWriteExt;
+
+/// How `http_request` delivers the response body
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseType {
+    /// UTF-8 text in `HttpResponse::body`
+    #[default]
+    Text,
+    /// Base64-encoded bytes in `HttpResponse::body`
+    Base64,
+    /// Raw bytes as the IPC response (an `ArrayBuffer` in JS); non-2xx statuses become errors
+    Bytes,
+    /// Streamed to `save_path`; `HttpResponse::body` is left empty
+    File,
+}
+
+/// Places `file` responses may be written to
+///
+/// The webview can't name arbitrary paths: targets must sit inside one of the download
+/// roots, or be a path the user picked in a save dialog opened by the backend.
+#[derive(Debug, Default)]
+pub struct SaveTargets {
+    roots: Vec<PathBuf>,
+    /// Paths picked in a save dialog; each grant is used up by one download
+    picked: Mutex<HashSet<PathBuf>>,
+}
+
+impl SaveTargets {
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        Self {
+            roots,
+            picked: Mutex::default(),
+        }
+    }
+
+    /// Allow one download to `path`, which the user chose in a save dialog
+    pub fn grant(&self, path: PathBuf) {
+        self.picked.lock().unwrap().insert(path);
+    }
+
+    /// Validate the target of a `file` response before any request is sent
+    pub fn resolve(&self, path: Option<&str>) -> Result<PathBuf, HttpError> {
+        let path = path
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+            .ok_or_else(|| HttpError::InvalidConfig("response_type 'file' requires save_path".to_string()))?;
+        let invalid = |reason: &str| HttpError::InvalidConfig(format!("save_path {}: {}", reason, path.display()));
+
+        if !path.is_absolute() {
+            return Err(invalid("must be absolute"));
+        }
+        if path.components().any(|c| matches!(c, Component::ParentDir)) {
+            return Err(invalid("must not contain '..'"));
+        }
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Err(invalid("must name a file"));
+        };
+        if std::fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink()) {
+            return Err(invalid("must not be a symlink"));
+        }
+
+        if self.picked.lock().unwrap().remove(&path) {
+            return Ok(path);
+        }
+
+        // Resolving symlinked directories keeps links inside a root from pointing out of it
+        let parent = parent.canonicalize().map_err(|_| invalid("has no existing parent directory"))?;
+        let inside_root = self
+            .roots
+            .iter()
+            .filter_map(|root| root.canonicalize().ok())
+            .any(|root| parent.starts_with(root));
+        if !inside_root {
+            return Err(invalid("is outside the download folders; pick it in a save dialog instead"));
+        }
+        Ok(parent.join(name))
+    }
+}
+
+/// Stream the body to `path` via a temporary file, so a failed download never leaves a partial file.
+/// Returns the number of bytes written.
+pub async fn save_to_file(
+    response: reqwest::Response,
+    path: &Path,
+    deadlines: &Deadlines,
+) -> Result<u64, HttpError> {
+    let partial = partial_path(path);
+    let result = write_body(response, &partial, deadlines).await;
+
+    match result {
+        Ok(written) => {
+            tokio::fs::rename(&partial, path)
+                .await
+                .map_err(|e| HttpError::Decode(format!("Failed to save {}: {}", path.display(), e)))?;
+            Ok(written)
+        }
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&partial).await;
+            Err(e)
+        }
+    }
+}
+
+/// `report.pdf` downloads to `report.pdf.part`, so it can't clobber a sibling `report.part`
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+async fn write_body(
+    response: reqwest::Response,
+    partial: &Path,
+    deadlines: &Deadlines,
+) -> Result<u64, HttpError> {
+    let io_error = |e: std::io::Error| HttpError::Decode(format!("Failed to write {}: {}", partial.display(), e));
+
+    // A leftover partial file, or a link planted in its place, is replaced rather than followed
+    let _ = tokio::fs::remove_file(partial).await;
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(partial)
+        .await
+        .map_err(io_error)?;
+    let mut stream = response.bytes_stream();
+    let mut written = 0u64;
+
+    while let Some(chunk) = deadlines.next_chunk(stream.next()).await? {
+        let bytes = chunk.map_err(|e| HttpError::from_reqwest(&e))?;
+        file.write_all(&bytes).await.map_err(io_error)?;
+        written += bytes.len() as u64;
+    }
+
+    file.flush().await.map_err(io_error)?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(targets: &SaveTargets, path: &Path) -> Result<PathBuf, HttpError> {
+        targets.resolve(path.to_str())
+    }
+
+    #[test]
+    fn keeps_downloads_inside_roots() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let targets = SaveTargets::new(vec![root.path().to_path_buf()]);
+
+        let inside = root.path().canonicalize().unwrap().join("font.woff2");
+        assert_eq!(resolve(&targets, &inside).unwrap(), inside);
+        assert!(resolve(&targets, &outside.path().join("font.woff2")).is_err());
+        assert!(resolve(&targets, &root.path().join("../font.woff2")).is_err());
+        assert!(targets.resolve(Some("font.woff2")).is_err());
+        assert!(targets.resolve(None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_out_of_roots() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let targets = SaveTargets::new(vec![root.path().to_path_buf()]);
+
+        std::os::unix::fs::symlink(outside.path(), root.path().join("escape")).unwrap();
+        assert!(resolve(&targets, &root.path().join("escape/font.woff2")).is_err());
+
+        std::os::unix::fs::symlink(outside.path().join("x"), root.path().join("link.bin")).unwrap();
+        assert!(resolve(&targets, &root.path().join("link.bin")).is_err());
+    }
+
+    #[test]
+    fn picked_paths_are_allowed_once() {
+        let outside = tempfile::tempdir().unwrap();
+        let targets = SaveTargets::new(Vec::new());
+        let path = outside.path().join("export.pdf");
+
+        targets.grant(path.clone());
+        assert_eq!(resolve(&targets, &path).unwrap(), path);
+        assert!(resolve(&targets, &path).is_err());
+    }
+
+    #[test]
+    fn partial_file_keeps_the_extension() {
+        assert_eq!(partial_path(Path::new("/tmp/report.pdf")), Path::new("/tmp/report.pdf.part"));
+        assert_eq!(partial_path(Path::new("/tmp/README")), Path::new("/tmp/README.part"));
+    }
+}
@@ -0,0 +1,481 @@
+//! On-disk response cache
+//!
+//! Replaying a debate repeats its oracle lookups, and re-verifying claims repeats the same
+//! calls. Requests that opt in with a `cache` policy are answered from disk while a stored
+//! response is fresh. Entries are keyed by a SHA-256 of the method, URL, credential headers,
+//! the headers the policy names and the body, so different API keys or prompts never share an
+//! entry, whether or not the policy names the header carrying the key.
+//! `Cache-Control` is honoured both ways: `no-store` and `no-cache` keep responses out and
+//! `max-age` caps how long they stay. The cache is off until `cache_configure` enables it,
+//! and beyond its size limit the least recently used entries go first.
+
+use reqwest::header::{HeaderMap, CACHE_CONTROL};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const INDEX_FILE: &str = "index.json";
+
+/// Headers carrying credentials; always part of the key, named in the policy or not
+const CREDENTIAL_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "x-api-key",
+    "api-key",
+    "x-goog-api-key",
+];
+
+/// Freshness of responses whose policy and headers don't say
+const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+
+/// Cache settings from frontend
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Total size of stored bodies; a single body may take up to a quarter of it
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+}
+
+fn default_max_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bytes: default_max_bytes(),
+        }
+    }
+}
+
+/// Per-request opt-in; only requests carrying one are looked up or stored
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CachePolicy {
+    /// How long the response stays fresh, capped by its `max-age`; an hour when absent
+    pub ttl_ms: Option<u64>,
+    /// Request headers that distinguish responses besides credential and secret headers, which always do
+    #[serde(default)]
+    pub vary: Vec<String>,
+}
+
+/// Stored response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedResponse {
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// Counters reported by `cache_stats`; hits and misses count since the app started
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub enabled: bool,
+    pub entries: usize,
+    pub bytes: u64,
+    pub max_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    status: u16,
+    headers: HashMap<String, String>,
+    size: u64,
+    /// Unix timestamp in seconds
+    expires_at: u64,
+    /// Value of the use counter at the last hit or store, for LRU eviction
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    config: CacheConfig,
+    entries: HashMap<String, IndexEntry>,
+    bytes: u64,
+    uses: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl Inner {
+    fn remove(&mut self, dir: &Path, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.bytes -= entry.size;
+            let _ = fs::remove_file(dir.join(key));
+        }
+    }
+
+    /// Drop expired entries, then least recently used ones until the total fits
+    fn evict(&mut self, dir: &Path) {
+        let now = unix_now();
+        let expired: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.expires_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.remove(dir, &key);
+        }
+
+        while self.bytes > self.config.max_bytes {
+            let Some(oldest) = self.entries.iter().min_by_key(|(_, e)| e.last_used).map(|(k, _)| k.clone()) else {
+                break;
+            };
+            self.remove(dir, &oldest);
+            self.evictions += 1;
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// Response cache held in Tauri managed state
+#[derive(Debug)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+impl ResponseCache {
+    /// Open the cache in `dir`, keeping entries from earlier runs whose bodies are still there
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let entries: HashMap<String, IndexEntry> = fs::read(dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|raw| serde_json::from_slice(&raw).ok())
+            .unwrap_or_default();
+
+        let mut inner = Inner::default();
+        for (key, entry) in entries {
+            if dir.join(&key).is_file() {
+                inner.bytes += entry.size;
+                inner.uses = inner.uses.max(entry.last_used);
+                inner.entries.insert(key, entry);
+            }
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            inner: Mutex::new(inner),
+        })
+    }
+
+    fn save_index(&self, inner: &Inner) {
+        // Losing the index only loses the cache, so failures aren't worth failing a request over
+        if let Ok(raw) = serde_json::to_vec(&inner.entries) {
+            let tmp = self.dir.join(format!("{}.tmp", INDEX_FILE));
+            if fs::write(&tmp, raw).is_ok() {
+                let _ = fs::rename(&tmp, self.dir.join(INDEX_FILE));
+            }
+        }
+    }
+
+    /// Replace the settings, evicting entries if the limit shrank
+    pub fn configure(&self, config: CacheConfig) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.config = config;
+        inner.evict(&self.dir);
+        self.save_index(&inner);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.lock().unwrap().config.enabled
+    }
+
+    /// Fresh response stored under `key`
+    pub fn get(&self, key: &str) -> Option<CachedResponse> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.config.enabled {
+            return None;
+        }
+        let fresh = inner.entries.get(key).is_some_and(|entry| entry.expires_at > unix_now());
+        let body = fresh.then(|| fs::read(self.dir.join(key)).ok()).flatten();
+        let Some(body) = body else {
+            inner.remove(&self.dir, key);
+            inner.misses += 1;
+            return None;
+        };
+
+        inner.uses += 1;
+        inner.hits += 1;
+        let uses = inner.uses;
+        let entry = inner.entries.get_mut(key).expect("checked above");
+        entry.last_used = uses;
+        Some(CachedResponse {
+            status: entry.status,
+            headers: entry.headers.clone(),
+            body,
+        })
+    }
+
+    /// Store a response for `ttl`, unless the body is too large for the cache
+    pub fn put(&self, key: &str, response: &CachedResponse, ttl: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        let size = response.body.len() as u64;
+        if !inner.config.enabled || size > inner.config.max_bytes / 4 {
+            return;
+        }
+        inner.remove(&self.dir, key);
+        if fs::write(self.dir.join(key), &response.body).is_err() {
+            return;
+        }
+
+        inner.uses += 1;
+        let entry = IndexEntry {
+            status: response.status,
+            headers: response.headers.clone(),
+            size,
+            expires_at: unix_now() + ttl.as_secs(),
+            last_used: inner.uses,
+        };
+        inner.bytes += size;
+        inner.entries.insert(key.to_string(), entry);
+        inner.evict(&self.dir);
+        self.save_index(&inner);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            enabled: inner.config.enabled,
+            entries: inner.entries.len(),
+            bytes: inner.bytes,
+            max_bytes: inner.config.max_bytes,
+            hits: inner.hits,
+            misses: inner.misses,
+            evictions: inner.evictions,
+        }
+    }
+
+    /// Delete every stored response
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        let keys: Vec<_> = inner.entries.keys().cloned().collect();
+        for key in keys {
+            inner.remove(&self.dir, &key);
+        }
+        self.save_index(&inner);
+    }
+}
+
+/// Cache key of a request: hex SHA-256 over the method, URL, credential headers, the `vary` headers
+/// and the body's hash. Header values are taken as sent, so secret placeholders count by name, never by value.
+pub fn key(
+    method: &str,
+    url: &str,
+    headers: &HashMap<String, String>,
+    body: Option<&str>,
+    vary: &[String],
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.to_ascii_uppercase());
+    hasher.update("\n");
+    hasher.update(url);
+    hasher.update("\n");
+
+    let mut varied: Vec<_> = vary
+        .iter()
+        .map(|name| name.trim().to_ascii_lowercase())
+        .chain(CREDENTIAL_HEADERS.iter().map(|name| name.to_string()))
+        .collect();
+    varied.sort();
+    varied.dedup();
+    for name in varied {
+        let value = headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&name))
+            .map(|(_, value)| value.as_str());
+        hasher.update(format!("{}: {}\n", name, value.unwrap_or_default()));
+    }
+
+    hasher.update(Sha256::digest(body.unwrap_or_default().as_bytes()));
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Lowercase `Cache-Control` directives with their values
+fn directives<'a>(values: impl Iterator<Item = &'a str>) -> Vec<(String, Option<String>)> {
+    values
+        .flat_map(|value| value.split(','))
+        .filter_map(|directive| {
+            let mut parts = directive.trim().splitn(2, '=');
+            let name = parts.next()?.trim().to_ascii_lowercase();
+            let value = parts.next().map(|v| v.trim().trim_matches('"').to_string());
+            (!name.is_empty()).then_some((name, value))
+        })
+        .collect()
+}
+
+/// Whether the request's own `Cache-Control` allows answering it from the cache, and storing its response
+pub fn request_allows(headers: &HashMap<String, String>) -> (bool, bool) {
+    let values = headers
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case(CACHE_CONTROL.as_str()))
+        .map(|(_, value)| value.as_str());
+    let directives = directives(values);
+    let has = |name: &str| directives.iter().any(|(n, _)| n == name);
+    (!has("no-cache") && !has("no-store"), !has("no-store"))
+}
+
+/// How long a response may be stored under the policy; `None` when its headers forbid it
+pub fn ttl(policy: &CachePolicy, headers: &HeaderMap) -> Option<Duration> {
+    let directives = directives(headers.get_all(CACHE_CONTROL).iter().filter_map(|v| v.to_str().ok()));
+    if directives.iter().any(|(name, _)| name == "no-store" || name == "no-cache") {
+        return None;
+    }
+    let max_age = directives
+        .iter()
+        .find(|(name, _)| name == "max-age")
+        .and_then(|(_, value)| value.as_deref()?.parse::<u64>().ok())
+        .map(Duration::from_secs);
+
+    let ttl = policy.ttl_ms.map(Duration::from_millis).unwrap_or(DEFAULT_TTL);
+    let ttl = max_age.map_or(ttl, |max_age| ttl.min(max_age));
+    (ttl >= Duration::from_secs(1)).then_some(ttl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn response(body: &str) -> CachedResponse {
+        CachedResponse {
+            status: 200,
+            headers: HashMap::from([("content-type".to_string(), "application/json".to_string())]),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn open(dir: &Path, max_bytes: u64) -> ResponseCache {
+        let cache = ResponseCache::open(dir).unwrap();
+        cache.configure(CacheConfig {
+            enabled: true,
+            max_bytes,
+        });
+        cache
+    }
+
+    #[test]
+    fn keys_by_method_url_varied_headers_and_body() {
+        let url = "https://api.duckduckgo.com/?q=socrates";
+        let headers = HashMap::from([
+            ("X-Api-Key".to_string(), "{{secret:anthropic}}".to_string()),
+            ("user-agent".to_string(), "a".to_string()),
+        ]);
+        let vary = vec!["x-api-key".to_string()];
+        let base = key("get", url, &headers, None, &vary);
+
+        assert_eq!(base, key("GET", url, &headers, None, &vary));
+        assert_eq!(base.len(), 64);
+        let other_agent = HashMap::from([
+            ("x-api-key".to_string(), "{{secret:anthropic}}".to_string()),
+            ("user-agent".to_string(), "b".to_string()),
+        ]);
+        assert_eq!(base, key("GET", url, &other_agent, None, &vary));
+
+        let other_key = HashMap::from([("x-api-key".to_string(), "{{secret:openai}}".to_string())]);
+        assert_ne!(base, key("GET", url, &other_key, None, &vary));
+        assert_ne!(base, key("POST", url, &headers, None, &vary));
+        assert_ne!(base, key("GET", url, &headers, Some("{}"), &vary));
+        assert_ne!(base, key("GET", "https://api.duckduckgo.com/?q=plato", &headers, None, &vary));
+
+        // Credential headers count without being named
+        let bearer = |token: &str| HashMap::from([("Authorization".to_string(), format!("Bearer {}", token))]);
+        assert_ne!(key("GET", url, &bearer("a"), None, &[]), key("GET", url, &bearer("b"), None, &[]));
+        assert_eq!(key("GET", url, &bearer("a"), None, &[]), key("GET", url, &bearer("a"), None, &[]));
+    }
+
+    #[test]
+    fn follows_cache_control() {
+        let policy = CachePolicy {
+            ttl_ms: Some(600_000),
+            vary: Vec::new(),
+        };
+        let mut headers = HeaderMap::new();
+        assert_eq!(ttl(&policy, &headers), Some(Duration::from_secs(600)));
+        assert_eq!(ttl(&CachePolicy::default(), &headers), Some(DEFAULT_TTL));
+
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("public, max-age=60"));
+        assert_eq!(ttl(&policy, &headers), Some(Duration::from_secs(60)));
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("max-age=0"));
+        assert_eq!(ttl(&policy, &headers), None);
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("private, no-store"));
+        assert_eq!(ttl(&policy, &headers), None);
+
+        let request = |value: &str| HashMap::from([("Cache-Control".to_string(), value.to_string())]);
+        assert_eq!(request_allows(&HashMap::new()), (true, true));
+        assert_eq!(request_allows(&request("no-cache")), (false, true));
+        assert_eq!(request_allows(&request("no-store")), (false, false));
+    }
+
+    #[test]
+    fn stores_until_expiry_and_survives_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = open(dir.path(), 1024);
+        assert_eq!(cache.get("a"), None);
+
+        cache.put("a", &response("answer"), Duration::from_secs(60));
+        assert_eq!(cache.get("a"), Some(response("answer")));
+
+        let reopened = open(dir.path(), 1024);
+        assert_eq!(reopened.get("a"), Some(response("answer")));
+
+        // Entries stop being served once they expire
+        reopened.inner.lock().unwrap().entries.get_mut("a").unwrap().expires_at = unix_now();
+        assert_eq!(reopened.get("a"), None);
+        assert!(!dir.path().join("a").exists());
+
+        let stats = reopened.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.hits, stats.misses), (0, 0, 1, 1));
+    }
+
+    #[test]
+    fn evicts_least_recently_used_beyond_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = open(dir.path(), 40);
+        let ttl = Duration::from_secs(60);
+
+        cache.put("a", &response("0123456789"), ttl);
+        cache.put("b", &response("0123456789"), ttl);
+        cache.put("c", &response("0123456789"), ttl);
+        assert!(cache.get("a").is_some());
+        cache.put("d", &response("0123456789"), ttl);
+        cache.put("e", &response("0123456789"), ttl);
+
+        // `a` was read after `b` was stored, so `b` is the least recently used
+        assert_eq!(cache.get("b"), None);
+        assert!(["a", "c", "d", "e"].iter().all(|key| cache.get(key).is_some()));
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.evictions), (4, 40, 1));
+
+        // Bodies over a quarter of the limit aren't stored at all
+        cache.put("big", &response("0123456789x"), ttl);
+        assert_eq!(cache.get("big"), None);
+
+        cache.clear();
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn does_nothing_until_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::open(dir.path()).unwrap();
+        cache.put("a", &response("answer"), Duration::from_secs(60));
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
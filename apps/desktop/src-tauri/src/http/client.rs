@@ -0,0 +1,284 @@
+//! Shared HTTP clients
+//!
+//! A `reqwest::Client` owns a connection pool, so building one per request throws away
+//! keep-alive connections, TLS session reuse and HTTP/2 multiplexing. Clients are cached
+//! per effective proxy and TLS setup and reused by every command. PAC scripts and the
+//! detected system proxy settings are kept here too, until the pool is cleared.
+
+use super::egress::AddressGuard;
+use super::failover::RouteMemory;
+use super::pac::PacResolver;
+use super::system_proxy::{self, SystemProxy};
+use super::tls::TlsConfig;
+use super::{build_client, HttpError, ProxyConfig};
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bound on cached clients; the least recently used one is dropped beyond this
+const MAX_CLIENTS: usize = 8;
+
+/// Everything that affects how a client is built
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ClientKey {
+    proxy: Option<ProxyConfig>,
+    tls: Option<TlsConfig>,
+    connect_timeout: Option<Duration>,
+}
+
+impl ClientKey {
+    fn new(proxy: Option<&ProxyConfig>, tls: Option<&TlsConfig>, connect_timeout: Option<Duration>) -> Self {
+        // Disabled or incomplete proxy settings mean a direct connection
+        let proxy = proxy.filter(|p| p.is_enabled()).cloned();
+        let tls = tls.filter(|t| t.is_custom()).cloned();
+        Self {
+            proxy,
+            tls,
+            connect_timeout,
+        }
+    }
+}
+
+/// Client cache held in Tauri managed state
+#[derive(Default)]
+pub struct ClientPool {
+    /// Ordered from least to most recently used
+    clients: Mutex<Vec<(ClientKey, Client)>>,
+    /// Failover route that last worked per host
+    routes: RouteMemory,
+    /// Loaded PAC scripts per location
+    scripts: Mutex<HashMap<String, Arc<PacResolver>>>,
+    /// System proxy settings, detected on first use
+    system: Mutex<Option<SystemProxy>>,
+    /// Egress address filter built into every client's resolver
+    guard: Arc<AddressGuard>,
+}
+
+impl ClientPool {
+    pub fn new(guard: Arc<AddressGuard>) -> Self {
+        Self {
+            guard,
+            ..Self::default()
+        }
+    }
+
+    /// Return the cached client for this proxy and TLS setup, building it on first use.
+    /// Building may read TLS files, so it runs on the blocking pool without the cache lock
+    /// held; other setups are served meanwhile.
+    pub async fn get(
+        &self,
+        proxy: Option<&ProxyConfig>,
+        tls: Option<&TlsConfig>,
+        connect_timeout: Option<Duration>,
+    ) -> Result<Client, HttpError> {
+        let key = ClientKey::new(proxy, tls, connect_timeout);
+        if let Some(client) = self.lookup(&key) {
+            return Ok(client);
+        }
+
+        // The same detection failover expands automatic configuration from
+        let system = match &key.proxy {
+            Some(proxy) if proxy.is_system() => self.system_proxy().await,
+            _ => SystemProxy::default(),
+        };
+        let client = tokio::task::spawn_blocking({
+            let key = key.clone();
+            let guard = self.guard.clone();
+            move || build_client(key.proxy.as_ref(), key.tls.as_ref(), key.connect_timeout, &system, guard)
+        })
+        .await
+        .map_err(|e| HttpError::InvalidConfig(format!("Failed to build HTTP client: {}", e)))??;
+
+        // Another request may have built the same client meanwhile; keep the one already shared
+        if let Some(client) = self.lookup(&key) {
+            return Ok(client);
+        }
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= MAX_CLIENTS {
+            clients.remove(0);
+        }
+        clients.push((key, client.clone()));
+        Ok(client)
+    }
+
+    /// Cached client for `key`, marked as most recently used
+    fn lookup(&self, key: &ClientKey) -> Option<Client> {
+        let mut clients = self.clients.lock().unwrap();
+        let index = clients.iter().position(|(k, _)| k == key)?;
+        let entry = clients.remove(index);
+        let client = entry.1.clone();
+        clients.push(entry);
+        Some(client)
+    }
+
+    /// Return the PAC script at `location`, downloading and compiling it on first use
+    pub async fn pac_script(&self, location: &str) -> Result<Arc<PacResolver>, HttpError> {
+        let location = location.trim();
+        if let Some(script) = self.scripts.lock().unwrap().get(location) {
+            return Ok(script.clone());
+        }
+        let script = Arc::new(PacResolver::load(location).await?);
+        // Keep the script another request may have loaded meanwhile, so answers share one cache
+        Ok(self.scripts.lock().unwrap().entry(location.to_string()).or_insert(script).clone())
+    }
+
+    /// Proxy settings of this machine, detected on the blocking pool on first use
+    pub async fn system_proxy(&self) -> SystemProxy {
+        if let Some(system) = self.system.lock().unwrap().clone() {
+            return system;
+        }
+        let system = tokio::task::spawn_blocking(system_proxy::detect).await.unwrap_or_default();
+        self.system.lock().unwrap().get_or_insert(system).clone()
+    }
+
+    pub fn routes(&self) -> &RouteMemory {
+        &self.routes
+    }
+
+    /// Drop every cached client so the next request opens fresh connections on the primary route,
+    /// and reload PAC scripts and system proxy settings
+    pub fn clear(&self) {
+        self.clients.lock().unwrap().clear();
+        self.routes.clear();
+        self.scripts.lock().unwrap().clear();
+        *self.system.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Keep-alive HTTP/1.1 server that counts accepted connections
+    async fn serve() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let counter = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    let mut read = [0u8; 1024];
+                    loop {
+                        let Ok(n) = socket.read(&mut read).await else { return };
+                        if n == 0 {
+                            return;
+                        }
+                        buf.extend_from_slice(&read[..n]);
+                        while let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                            buf.drain(..end + 4);
+                            let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                            if socket.write_all(response.as_bytes()).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        (url, connections)
+    }
+
+    #[tokio::test]
+    async fn reuses_client_per_proxy_setup() {
+        let pool = ClientPool::default();
+        let socks = ProxyConfig {
+            proxy_type: "socks5".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 1080,
+            username: None,
+            password: None,
+            pac_url: None,
+            rules: Vec::new(),
+            failover: Vec::new(),
+        };
+        let disabled = ProxyConfig {
+            proxy_type: "none".to_string(),
+            ..socks.clone()
+        };
+
+        pool.get(None, None, None).await.unwrap();
+        pool.get(Some(&disabled), None, None).await.unwrap();
+        pool.get(Some(&socks), None, None).await.unwrap();
+        assert_eq!(pool.clients.lock().unwrap().len(), 2);
+
+        pool.clear();
+        assert!(pool.clients.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn pooled_client_keeps_connections_alive() {
+        let (url, connections) = serve().await;
+        let pool = ClientPool::default();
+
+        for _ in 0..5 {
+            let client = pool.get(None, None, None).await.unwrap();
+            client.get(&url).send().await.unwrap().text().await.unwrap();
+        }
+
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn evicts_least_recently_used_client_beyond_limit() {
+        let pool = ClientPool::default();
+        let timeout = |secs: u64| Some(Duration::from_secs(secs));
+        let keys = |pool: &ClientPool| -> Vec<_> {
+            pool.clients.lock().unwrap().iter().map(|(key, _)| key.connect_timeout).collect()
+        };
+
+        for secs in 0..MAX_CLIENTS as u64 {
+            pool.get(None, None, timeout(secs)).await.unwrap();
+        }
+        // A hit moves the client to the back instead of building another one
+        pool.get(None, None, timeout(0)).await.unwrap();
+        assert_eq!(keys(&pool).len(), MAX_CLIENTS);
+        assert_eq!(keys(&pool).last(), Some(&timeout(0)));
+
+        pool.get(None, None, timeout(99)).await.unwrap();
+        let keys = keys(&pool);
+        assert_eq!(keys.len(), MAX_CLIENTS);
+        assert!(!keys.contains(&timeout(1)), "least recently used client should be dropped");
+        assert!(keys.contains(&timeout(0)));
+        assert_eq!(keys.last(), Some(&timeout(99)));
+    }
+
+    #[tokio::test]
+    async fn slow_pac_script_does_not_block_other_setups() {
+        // PAC server that takes a while to answer
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let pac_url = format!("http://{}/proxy.pac", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = socket.read(&mut buf).await;
+            tokio::time::sleep(Duration::from_millis(1500)).await;
+            let script = "function FindProxyForURL() { return \"DIRECT\"; }";
+            let response = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}", script.len(), script);
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+
+        let pool = Arc::new(ClientPool::default());
+        let slow = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.pac_script(&pac_url).await.map(drop) }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let started = std::time::Instant::now();
+        pool.get(None, None, None).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(500), "took {:?}", started.elapsed());
+
+        slow.await.unwrap().unwrap();
+        assert_eq!(pool.scripts.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,289 @@
+//! Chunk coalescing and bounded delivery for streaming requests
+//!
+//! Fast models produce hundreds of tiny chunks per second, and emitting each one floods
+//! the IPC bridge. Decoded chunks pass through a bounded queue to a delivery task that
+//! batches them by size and latency; while the queue is full, reading from the network
+//! pauses and the time spent waiting is reported on the next chunk.
+//!
+//! That wait only covers the backend queue. Channel sends return once the message is
+//! handed to the IPC layer, so a webview that is slow to process chunks doesn't show
+//! up as queue lag.
+
+use super::StreamChunk;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+const DEFAULT_MAX_LATENCY_MS: u64 = 16;
+const DEFAULT_MAX_BYTES: usize = 16 * 1024;
+const DEFAULT_MAX_PENDING: usize = 64;
+
+/// Chunk batching configuration from frontend; every field falls back to a default
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CoalesceConfig {
+    /// Longest a chunk is held back waiting for more data; 0 disables batching
+    pub max_latency_ms: Option<u64>,
+    /// Flush as soon as this many bytes are pending
+    pub max_bytes: Option<usize>,
+    /// Chunks queued for delivery before reading from the network pauses
+    pub max_pending: Option<usize>,
+}
+
+/// Merges consecutive data chunks into one until `max_bytes` is reached
+#[derive(Debug)]
+struct Batcher {
+    max_bytes: usize,
+    pending: Option<StreamChunk>,
+    bytes: usize,
+}
+
+impl Batcher {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes: max_bytes.max(1),
+            pending: None,
+            bytes: 0,
+        }
+    }
+
+    /// Add a data chunk, returning the batch if it is now full
+    fn push(&mut self, mut chunk: StreamChunk) -> Option<StreamChunk> {
+        self.bytes += payload_len(&chunk);
+
+        // SSE messages are collected into `events` so a batch keeps every message
+        let event = chunk.event.take();
+        let batch = self
+            .pending
+            .get_or_insert_with(|| StreamChunk::data(&chunk.request_id, String::new()));
+        batch.chunk.push_str(&chunk.chunk);
+        if let Some(event) = event {
+            batch.events.get_or_insert_with(Vec::new).push(event);
+        }
+        if let Some(lag) = chunk.queue_lag_ms {
+            *batch.queue_lag_ms.get_or_insert(0) += lag;
+        }
+
+        if self.bytes >= self.max_bytes {
+            self.take()
+        } else {
+            None
+        }
+    }
+
+    /// Remove the pending batch, if any
+    fn take(&mut self) -> Option<StreamChunk> {
+        self.bytes = 0;
+        self.pending.take()
+    }
+}
+
+fn payload_len(chunk: &StreamChunk) -> usize {
+    chunk.chunk.len() + chunk.event.as_ref().map_or(0, |e| e.data.len())
+}
+
+/// Whether a chunk carries body data, as opposed to a retry notice or terminal event
+fn is_data(chunk: &StreamChunk) -> bool {
+    !chunk.done && chunk.error.is_none() && chunk.retry.is_none()
+}
+
+/// Bounded queue feeding a delivery task
+pub struct ChunkQueue {
+    tx: mpsc::Sender<StreamChunk>,
+    task: JoinHandle<()>,
+    /// Time spent waiting for queue space since the last chunk was queued
+    lag: Duration,
+}
+
+impl ChunkQueue {
+    /// Start delivering queued chunks through `deliver`, batching them when `config` is given
+    pub fn spawn<F>(config: Option<&CoalesceConfig>, deliver: F) -> Self
+    where
+        F: Fn(StreamChunk) + Send + 'static,
+    {
+        let config = config.cloned();
+        let max_pending = config
+            .as_ref()
+            .and_then(|c| c.max_pending)
+            .unwrap_or(DEFAULT_MAX_PENDING)
+            .max(1);
+        let (tx, rx) = mpsc::channel(max_pending);
+
+        let task = tokio::spawn(async move {
+            match config {
+                Some(config) => {
+                    let max_latency = Duration::from_millis(config.max_latency_ms.unwrap_or(DEFAULT_MAX_LATENCY_MS));
+                    let max_bytes = config.max_bytes.unwrap_or(DEFAULT_MAX_BYTES);
+                    if max_latency.is_zero() {
+                        deliver_each(rx, deliver).await
+                    } else {
+                        deliver_batched(rx, max_latency, max_bytes, deliver).await
+                    }
+                }
+                None => deliver_each(rx, deliver).await,
+            }
+        });
+
+        Self {
+            tx,
+            task,
+            lag: Duration::ZERO,
+        }
+    }
+
+    /// Queue a chunk, waiting for space if the delivery task has fallen behind
+    pub async fn send(&mut self, mut chunk: StreamChunk) {
+        if !self.lag.is_zero() && is_data(&chunk) {
+            chunk.queue_lag_ms = Some(self.lag.as_millis() as u64);
+            self.lag = Duration::ZERO;
+        }
+
+        match self.tx.try_send(chunk) {
+            Ok(()) | Err(TrySendError::Closed(_)) => {}
+            Err(TrySendError::Full(chunk)) => {
+                let started = Instant::now();
+                let _ = self.tx.send(chunk).await;
+                self.lag += started.elapsed();
+            }
+        }
+    }
+
+    /// Stop accepting chunks and wait until everything queued has been delivered
+    pub async fn close(self) {
+        drop(self.tx);
+        let _ = self.task.await;
+    }
+}
+
+async fn deliver_each(mut rx: mpsc::Receiver<StreamChunk>, deliver: impl Fn(StreamChunk)) {
+    while let Some(chunk) = rx.recv().await {
+        deliver(chunk);
+    }
+}
+
+async fn deliver_batched(
+    mut rx: mpsc::Receiver<StreamChunk>,
+    max_latency: Duration,
+    max_bytes: usize,
+    deliver: impl Fn(StreamChunk),
+) {
+    let mut batcher = Batcher::new(max_bytes);
+    let mut flush_at: Option<Instant> = None;
+
+    loop {
+        let received = match flush_at {
+            Some(at) => match tokio::time::timeout_at(at, rx.recv()).await {
+                Ok(received) => received,
+                Err(_) => {
+                    // Latency budget of the oldest pending chunk is used up
+                    if let Some(batch) = batcher.take() {
+                        deliver(batch);
+                    }
+                    flush_at = None;
+                    continue;
+                }
+            },
+            None => rx.recv().await,
+        };
+        let Some(chunk) = received else { break };
+
+        if !is_data(&chunk) {
+            // Keep ordering: anything pending goes out before a notice or terminal event
+            if let Some(batch) = batcher.take() {
+                deliver(batch);
+            }
+            flush_at = None;
+            deliver(chunk);
+            continue;
+        }
+
+        if let Some(batch) = batcher.push(chunk) {
+            deliver(batch);
+            flush_at = None;
+        } else if flush_at.is_none() {
+            flush_at = Some(Instant::now() + max_latency);
+        }
+    }
+
+    if let Some(batch) = batcher.take() {
+        deliver(batch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn collector() -> (Arc<Mutex<Vec<StreamChunk>>>, impl Fn(StreamChunk) + Send + 'static) {
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let sink = delivered.clone();
+        (delivered, move |chunk| sink.lock().unwrap().push(chunk))
+    }
+
+    fn config(max_latency_ms: u64, max_bytes: usize) -> CoalesceConfig {
+        CoalesceConfig {
+            max_latency_ms: Some(max_latency_ms),
+            max_bytes: Some(max_bytes),
+            max_pending: None,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batches_by_latency_and_size() {
+        let (delivered, deliver) = collector();
+        let mut queue = ChunkQueue::spawn(Some(&config(50, 8)), deliver);
+
+        for part in ["a", "b", "c"] {
+            queue.send(StreamChunk::data("req", part.to_string())).await;
+        }
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        queue.send(StreamChunk::data("req", "0123456789".to_string())).await;
+        queue.send(StreamChunk::data("req", "z".to_string())).await;
+        queue.send(StreamChunk::done("req")).await;
+        queue.close().await;
+
+        let delivered = delivered.lock().unwrap();
+        let chunks: Vec<(&str, bool)> = delivered.iter().map(|c| (c.chunk.as_str(), c.done)).collect();
+        assert_eq!(chunks, vec![("abc", false), ("0123456789", false), ("z", false), ("", true)]);
+    }
+
+    #[tokio::test]
+    async fn delivers_each_chunk_without_config() {
+        let (delivered, deliver) = collector();
+        let mut queue = ChunkQueue::spawn(None, deliver);
+
+        for part in ["a", "b", "c"] {
+            queue.send(StreamChunk::data("req", part.to_string())).await;
+        }
+        queue.close().await;
+
+        assert_eq!(delivered.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn reports_queue_lag_when_delivery_falls_behind() {
+        let (delivered, deliver) = collector();
+        let slow = move |chunk| {
+            std::thread::sleep(Duration::from_millis(20));
+            deliver(chunk)
+        };
+        let config = CoalesceConfig {
+            max_latency_ms: Some(0),
+            max_bytes: Some(1),
+            max_pending: Some(1),
+        };
+        let mut queue = ChunkQueue::spawn(Some(&config), slow);
+
+        for _ in 0..5 {
+            queue.send(StreamChunk::data("req", "x".to_string())).await;
+        }
+        queue.close().await;
+
+        let delivered = delivered.lock().unwrap();
+        assert_eq!(delivered.len(), 5);
+        assert!(delivered.iter().any(|c| c.queue_lag_ms.is_some()));
+    }
+}
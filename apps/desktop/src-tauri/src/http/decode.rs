@@ -0,0 +1,197 @@
+//! Response body decoding for streaming requests
+//!
+//! Network chunks can split a multi-byte UTF-8 character, so bytes are decoded
+//! incrementally and any incomplete trailing sequence is carried into the next chunk.
+
+use super::sse::SseParser;
+use super::StreamChunk;
+
+/// Incremental UTF-8 decoder that never drops data
+#[derive(Debug, Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    /// Decode as much of `bytes` as possible, holding back an incomplete trailing character.
+    /// Invalid sequences are replaced with U+FFFD rather than discarded.
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        let mut input = std::mem::take(&mut self.pending);
+        input.extend_from_slice(bytes);
+
+        let mut text = String::with_capacity(input.len());
+        let mut rest = input.as_slice();
+
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    // `valid_up_to` guarantees this prefix is valid UTF-8
+                    text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        None => {
+                            self.pending = after.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        text
+    }
+
+    /// Flush any bytes still held back once the stream has ended
+    pub fn finish(&mut self) -> String {
+        let pending = std::mem::take(&mut self.pending);
+        String::from_utf8_lossy(&pending).into_owned()
+    }
+}
+
+/// Turns raw body bytes into the chunk events sent to the frontend
+#[derive(Debug)]
+pub struct BodyDecoder {
+    utf8: Utf8Decoder,
+    sse: Option<SseParser>,
+    finished: bool,
+}
+
+impl BodyDecoder {
+    pub fn new(sse: bool) -> Self {
+        Self {
+            utf8: Utf8Decoder::default(),
+            sse: sse.then(SseParser::default),
+            finished: false,
+        }
+    }
+
+    /// Whether the SSE `[DONE]` sentinel has been seen and reading can stop
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Decode one network chunk into zero or more stream events
+    pub fn push(&mut self, request_id: &str, bytes: &[u8]) -> Vec<StreamChunk> {
+        let text = self.utf8.decode(bytes);
+        self.emit(request_id, &text, false)
+    }
+
+    /// Flush buffered bytes and any unterminated SSE message at end of stream
+    pub fn finish(&mut self, request_id: &str) -> Vec<StreamChunk> {
+        let text = self.utf8.finish();
+        self.emit(request_id, &text, true)
+    }
+
+    fn emit(&mut self, request_id: &str, text: &str, last: bool) -> Vec<StreamChunk> {
+        if self.finished {
+            return Vec::new();
+        }
+
+        let Some(parser) = self.sse.as_mut() else {
+            if text.is_empty() {
+                return Vec::new();
+            }
+            return vec![StreamChunk::data(request_id, text.to_string())];
+        };
+
+        let mut events = parser.push(text);
+        if last {
+            events.extend(parser.finish());
+        }
+
+        let mut chunks = Vec::with_capacity(events.len());
+        for event in events {
+            if event.is_done() {
+                self.finished = true;
+                break;
+            }
+            chunks.push(StreamChunk::sse(request_id, event));
+        }
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect_text(decoder: &mut BodyDecoder, parts: &[&[u8]]) -> String {
+        let mut chunks = Vec::new();
+        for part in parts {
+            chunks.extend(decoder.push("req", part));
+        }
+        chunks.extend(decoder.finish("req"));
+        chunks.into_iter().map(|c| c.chunk).collect()
+    }
+
+    #[test]
+    fn carries_split_code_points_across_chunks() {
+        let text = "辩论 🦉 débat";
+        let bytes = text.as_bytes();
+
+        // Split at every possible offset, including inside each multi-byte character
+        for split in 0..=bytes.len() {
+            let mut decoder = BodyDecoder::new(false);
+            let (a, b) = bytes.split_at(split);
+            assert_eq!(collect_text(&mut decoder, &[a, b]), text, "split at {split}");
+        }
+    }
+
+    #[test]
+    fn decodes_one_byte_at_a_time() {
+        let text = "深度求索 → Kimi 😀";
+        let parts: Vec<&[u8]> = text.as_bytes().chunks(1).collect();
+        let mut decoder = BodyDecoder::new(false);
+        assert_eq!(collect_text(&mut decoder, &parts), text);
+    }
+
+    #[test]
+    fn incomplete_character_emits_nothing_until_completed() {
+        let emoji = "🦉".as_bytes();
+        let mut decoder = BodyDecoder::new(false);
+        assert!(decoder.push("req", &emoji[..2]).is_empty());
+        let chunks = decoder.push("req", &emoji[2..]);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].chunk, "🦉");
+    }
+
+    #[test]
+    fn replaces_invalid_bytes_instead_of_dropping_chunk() {
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(b"ok \xFF ok"), "ok \u{FFFD} ok");
+    }
+
+    #[test]
+    fn truncated_stream_is_flushed_lossily() {
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(&"é".as_bytes()[..1]), "");
+        assert_eq!(decoder.finish(), "\u{FFFD}");
+    }
+
+    #[test]
+    fn sse_data_with_split_code_points() {
+        let body = "data: {\"text\":\"你好\"}\n\ndata: [DONE]\n\n";
+        let parts: Vec<&[u8]> = body.as_bytes().chunks(5).collect();
+        let mut decoder = BodyDecoder::new(true);
+
+        let mut chunks = Vec::new();
+        for part in parts {
+            chunks.extend(decoder.push("req", part));
+            if decoder.is_finished() {
+                break;
+            }
+        }
+
+        assert!(decoder.is_finished());
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].event.as_ref().unwrap().data, "{\"text\":\"你好\"}");
+    }
+}
@@ -0,0 +1,530 @@
+//! Egress policy for the HTTP commands
+//!
+//! The webview runs without a CSP, so an injected script could otherwise use the backend,
+//! with the user's proxy credentials, as a relay into localhost or the LAN. Every URL is
+//! checked against an allowlist of provider, MCP and oracle hosts before a client is
+//! picked, and private addresses need an explicit opt-in.
+//!
+//! For the same reason the webview can't widen the policy on its own: new destinations
+//! and the private-address opt-in take effect only once the user confirms them in a
+//! native dialog. The confirmed policy is saved, so the next launch doesn't ask again.
+
+use super::{pac, HttpError};
+use crate::secrets;
+use reqwest::redirect;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use url::Host;
+
+/// Oracle backend, always reachable over HTTPS like the provider endpoints
+const ORACLE_HOSTS: &[&str] = &["api.duckduckgo.com"];
+
+/// Policy configuration from frontend, derived from the configured providers and MCP servers
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct EgressConfig {
+    /// Extra allowed destinations: `https://host[:port]`, `http://host[:port]` to also permit
+    /// plain HTTP, a bare `host`, or `*.domain` for its subdomains
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// PAC scripts the proxy settings may read from disk: absolute paths or `file://` URLs
+    #[serde(default)]
+    pub pac_files: Vec<String>,
+    /// Permit loopback, private and link-local addresses
+    #[serde(default)]
+    pub allow_private: bool,
+}
+
+/// One allowed destination
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HostRule {
+    /// Lowercase host, or the parent domain when `subdomains` is set
+    host: String,
+    subdomains: bool,
+    /// Any port when absent
+    port: Option<u16>,
+    allow_http: bool,
+}
+
+impl HostRule {
+    fn parse(entry: &str) -> Result<Self, HttpError> {
+        let entry = entry.trim();
+        let invalid = || HttpError::InvalidConfig(format!("Invalid egress entry: '{}'", entry));
+
+        let (host, port, allow_http) = if entry.contains("://") {
+            let url = Url::parse(entry).map_err(|_| invalid())?;
+            let allow_http = match url.scheme() {
+                "https" => false,
+                "http" => true,
+                _ => return Err(invalid()),
+            };
+            let host = url.host_str().ok_or_else(invalid)?.to_string();
+            (host, url.port(), allow_http)
+        } else {
+            (entry.to_string(), None, false)
+        };
+
+        let host = host.to_ascii_lowercase();
+        let (host, subdomains) = match host.strip_prefix("*.") {
+            Some(parent) => (parent.to_string(), true),
+            None => (host, false),
+        };
+        if host.is_empty() || host.contains(['/', '*']) {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            host,
+            subdomains,
+            port,
+            allow_http,
+        })
+    }
+
+    fn matches(&self, host: &str, port: Option<u16>) -> bool {
+        let host_matches = if self.subdomains {
+            host.strip_suffix(self.host.as_str())
+                .is_some_and(|prefix| prefix.ends_with('.'))
+        } else {
+            host == self.host
+        };
+        host_matches && self.port.is_none_or(|p| Some(p) == port)
+    }
+}
+
+#[derive(Debug)]
+struct Rules {
+    hosts: Vec<HostRule>,
+    pac_files: Vec<PathBuf>,
+    allow_private: bool,
+    /// The configuration these rules were built from, without the built-in hosts
+    config: EgressConfig,
+}
+
+impl Rules {
+    fn from_config(config: EgressConfig) -> Result<Self, HttpError> {
+        let mut rules = Rules::default();
+        for entry in &config.allow {
+            rules.hosts.push(HostRule::parse(entry)?);
+        }
+        for entry in &config.pac_files {
+            let path = pac::local_path(entry)
+                .ok_or_else(|| HttpError::InvalidConfig(format!("PAC file must be an absolute path: '{}'", entry)))?;
+            rules.pac_files.push(path.to_path_buf());
+        }
+        rules.allow_private = config.allow_private;
+        rules.config = config;
+        Ok(rules)
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        // The hosts provider keys are bound to, so the two lists can't drift apart
+        let hosts = secrets::PROVIDER_HOSTS
+            .iter()
+            .flat_map(|(_, hosts)| hosts.iter())
+            .chain(ORACLE_HOSTS)
+            .map(|host| HostRule {
+                host: host.to_string(),
+                subdomains: false,
+                port: None,
+                allow_http: false,
+            })
+            .collect();
+        Self {
+            hosts,
+            pac_files: Vec::new(),
+            allow_private: false,
+            config: EgressConfig::default(),
+        }
+    }
+}
+
+/// Private-address filter shared with the DNS resolvers of the pooled clients, so every
+/// connection they open is checked on the addresses it actually uses
+#[derive(Debug, Default)]
+pub struct AddressGuard {
+    allow_private: AtomicBool,
+}
+
+impl AddressGuard {
+    pub fn new(allow_private: bool) -> Self {
+        Self {
+            allow_private: AtomicBool::new(allow_private),
+        }
+    }
+
+    /// Refuse the resolved addresses of `host` when one is private and the policy doesn't permit it
+    pub fn filter(&self, host: &str, addrs: Vec<SocketAddr>) -> Result<Vec<SocketAddr>, String> {
+        if self.allow_private.load(Ordering::Relaxed) {
+            return Ok(addrs);
+        }
+        match addrs.iter().find(|addr| is_private(addr.ip())) {
+            Some(addr) => Err(format!("egress: {} resolves to private address {}", host, addr.ip())),
+            None => Ok(addrs),
+        }
+    }
+}
+
+/// Allowlist held in Tauri managed state
+#[derive(Debug, Default)]
+pub struct EgressPolicy {
+    rules: RwLock<Rules>,
+    guard: Arc<AddressGuard>,
+    /// Where the confirmed policy is saved; nothing is saved when absent
+    path: Option<PathBuf>,
+}
+
+impl EgressPolicy {
+    /// Load the policy confirmed in an earlier session from `dir`; built-in hosts only when there is none
+    pub fn open(dir: &Path) -> Self {
+        let path = dir.join("egress.json");
+        let rules = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .and_then(|config| Rules::from_config(config).ok())
+            .unwrap_or_default();
+        Self {
+            guard: Arc::new(AddressGuard::new(rules.allow_private)),
+            rules: RwLock::new(rules),
+            path: Some(path),
+        }
+    }
+
+    /// Address filter that follows this policy, for the resolvers of the HTTP clients
+    pub fn guard(&self) -> Arc<AddressGuard> {
+        self.guard.clone()
+    }
+
+    /// What `config` would allow that the current policy doesn't, as a line per destination
+    /// for the user to confirm; `None` when it only keeps or narrows the policy
+    pub fn widening(&self, config: &EgressConfig) -> Result<Option<String>, HttpError> {
+        let config = normalize(config);
+        Rules::from_config(config.clone())?;
+
+        let rules = self.rules.read().unwrap();
+        let mut lines: Vec<String> = config
+            .allow
+            .iter()
+            .filter(|entry| !rules.config.allow.contains(entry))
+            .cloned()
+            .collect();
+        lines.extend(
+            config
+                .pac_files
+                .iter()
+                .filter(|entry| !rules.config.pac_files.contains(entry))
+                .map(|entry| format!("PAC script file {}", entry)),
+        );
+        if config.allow_private && !rules.allow_private {
+            lines.push("Private networks (localhost and LAN addresses)".to_string());
+        }
+        Ok((!lines.is_empty()).then(|| lines.join("\n")))
+    }
+
+    /// Replace the frontend-provided part of the policy; built-in hosts stay allowed.
+    /// Without `confirmed`, only the parts already allowed are kept and the rest is refused.
+    pub fn set(&self, config: &EgressConfig, confirmed: bool) -> Result<(), HttpError> {
+        let mut config = normalize(config);
+        let refused = if confirmed { None } else { self.widening(&config)? };
+        if refused.is_some() {
+            let rules = self.rules.read().unwrap();
+            config.allow.retain(|entry| rules.config.allow.contains(entry));
+            config.pac_files.retain(|entry| rules.config.pac_files.contains(entry));
+            config.allow_private &= rules.allow_private;
+        }
+
+        let rules = Rules::from_config(config)?;
+        if let Some(path) = &self.path {
+            if let Ok(data) = serde_json::to_vec(&rules.config) {
+                let _ = std::fs::write(path, data);
+            }
+        }
+        self.guard.allow_private.store(rules.allow_private, Ordering::Relaxed);
+        *self.rules.write().unwrap() = rules;
+
+        match refused {
+            Some(refused) => Err(HttpError::EgressDenied(format!("Not confirmed: {}", refused.replace('\n', ", ")))),
+            None => Ok(()),
+        }
+    }
+
+    /// Check a PAC script location, from the proxy settings or the system's automatic
+    /// configuration, before it is loaded. Only the allowlist applies to URLs: PAC servers
+    /// usually sit on the local network. Files must be listed in `pac_files`.
+    pub fn check_pac(&self, location: &str) -> Result<(), HttpError> {
+        let location = location.trim();
+        let rules = self.rules.read().unwrap();
+        if let Some(path) = pac::local_path(location) {
+            return match rules.pac_files.iter().any(|allowed| allowed == path) {
+                true => Ok(()),
+                false => Err(HttpError::EgressDenied(format!("PAC script file {} is not allowed", path.display()))),
+            };
+        }
+
+        let url = Url::parse(location)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or_else(|| HttpError::EgressDenied("PAC location must be an http(s) URL or an allowed file".to_string()))?;
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        match rules.hosts.iter().find(|r| r.matches(&host, url.port_or_known_default())) {
+            Some(rule) if url.scheme() == "https" || rule.allow_http => Ok(()),
+            _ => Err(HttpError::EgressDenied(format!("PAC script at {} is not an allowed destination", host))),
+        }
+    }
+
+    /// Check `url` before anything is sent. With `direct` set the host is also resolved, so
+    /// public names pointing at private addresses are caught; through a proxy the proxy resolves.
+    pub async fn check(&self, url: &str, direct: bool) -> Result<(), HttpError> {
+        let url = Url::parse(url).map_err(|e| HttpError::InvalidConfig(format!("Invalid URL '{}': {}", url, e)))?;
+        let denied = |reason: String| Err(HttpError::EgressDenied(reason));
+
+        // IPv6 hosts keep their brackets, as in allowlist entries
+        let Some(host) = url.host_str().map(str::to_ascii_lowercase) else {
+            return denied(format!("{} has no host", url));
+        };
+
+        let allow_private = {
+            let rules = self.rules.read().unwrap();
+            let Some(rule) = rules.hosts.iter().find(|r| r.matches(&host, url.port_or_known_default())) else {
+                return denied(format!("{} is not an allowed destination", host));
+            };
+            match url.scheme() {
+                "https" => {}
+                "http" if rule.allow_http => {}
+                scheme => return denied(format!("{}:// is not allowed for {}", scheme, host)),
+            }
+            rules.allow_private
+        };
+        if allow_private {
+            return Ok(());
+        }
+
+        let literal = match url.host() {
+            Some(Host::Ipv4(ip)) => Some(IpAddr::V4(ip)),
+            Some(Host::Ipv6(ip)) => Some(IpAddr::V6(ip)),
+            _ => None,
+        };
+        if let Some(ip) = literal {
+            if is_private(ip) {
+                return denied(format!("{} is a private address", ip));
+            }
+            return Ok(());
+        }
+        if host == "localhost" || host.ends_with(".localhost") {
+            return denied(format!("{} is a private address", host));
+        }
+
+        if direct {
+            let port = url.port_or_known_default().unwrap_or(443);
+            // Resolution failures are left for the request itself to report
+            if let Ok(addrs) = tokio::net::lookup_host((host.as_str(), port)).await {
+                if let Some(addr) = addrs.into_iter().find(|addr| is_private(addr.ip())) {
+                    return denied(format!("{} resolves to private address {}", host, addr.ip()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Trimmed, non-empty entries without duplicates
+fn normalize(config: &EgressConfig) -> EgressConfig {
+    let entries = |list: &[String]| {
+        let mut kept: Vec<String> = Vec::new();
+        for entry in list.iter().map(|e| e.trim()).filter(|e| !e.is_empty()) {
+            if !kept.iter().any(|e| e == entry) {
+                kept.push(entry.to_string());
+            }
+        }
+        kept
+    };
+    EgressConfig {
+        allow: entries(&config.allow),
+        pac_files: entries(&config.pac_files),
+        allow_private: config.allow_private,
+    }
+}
+
+/// Whether an address is outside the public internet
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_private_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_private_v4(v4),
+            None => is_private_v6(ip),
+        },
+    }
+}
+
+fn is_private_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        // Carrier-grade NAT, 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+}
+
+fn is_private_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        // Unique local, fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        // Link-local, fe80::/10
+        || (first & 0xffc0) == 0xfe80
+}
+
+/// Follow redirects only within the same origin, so an allowed host can't bounce a request elsewhere
+pub fn redirect_policy() -> redirect::Policy {
+    redirect::Policy::custom(|attempt| {
+        let same_origin = attempt
+            .previous()
+            .first()
+            .is_some_and(|first| first.origin() == attempt.url().origin());
+        if attempt.previous().len() > 10 {
+            attempt.error("too many redirects")
+        } else if same_origin {
+            attempt.follow()
+        } else {
+            attempt.stop()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(allow: &[&str], allow_private: bool) -> EgressConfig {
+        EgressConfig {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            pac_files: Vec::new(),
+            allow_private,
+        }
+    }
+
+    fn policy(allow: &[&str], allow_private: bool) -> EgressPolicy {
+        let policy = EgressPolicy::default();
+        policy.set(&config(allow, allow_private), true).unwrap();
+        policy
+    }
+
+    fn kind(result: Result<(), HttpError>) -> &'static str {
+        result.err().map_or("ok", |e| e.kind())
+    }
+
+    #[tokio::test]
+    async fn allows_configured_hosts_only() {
+        let policy = policy(&["*.example.com", "https://mcp.internal.dev:8443"], false);
+
+        assert_eq!(kind(policy.check("https://api.openai.com/v1/responses", false).await), "ok");
+        assert_eq!(kind(policy.check("https://api.moonshot.ai/v1/chat/completions", false).await), "ok");
+        assert_eq!(kind(policy.check("https://eu.example.com/v1", false).await), "ok");
+        assert_eq!(kind(policy.check("https://example.com/v1", false).await), "egress_denied");
+        assert_eq!(kind(policy.check("https://badexample.com/v1", false).await), "egress_denied");
+        assert_eq!(kind(policy.check("https://mcp.internal.dev:8443/rpc", false).await), "ok");
+        assert_eq!(kind(policy.check("https://mcp.internal.dev/rpc", false).await), "egress_denied");
+        assert_eq!(kind(policy.check("http://api.openai.com/v1", false).await), "egress_denied");
+        assert_eq!(kind(policy.check("file:///etc/passwd", false).await), "egress_denied");
+    }
+
+    #[tokio::test]
+    async fn private_addresses_need_opt_in() {
+        let allow = ["http://localhost:11434", "http://127.0.0.1:8080", "http://[::1]:8080", "10.0.0.5"];
+        let strict = policy(&allow, false);
+        for url in ["http://localhost:11434/api", "http://127.0.0.1:8080/", "http://[::1]:8080/", "https://10.0.0.5/"] {
+            assert_eq!(kind(strict.check(url, true).await), "egress_denied", "{url}");
+        }
+
+        let opted_in = policy(&allow, true);
+        for url in ["http://localhost:11434/api", "http://127.0.0.1:8080/", "http://[::1]:8080/", "https://10.0.0.5/"] {
+            assert_eq!(kind(opted_in.check(url, true).await), "ok", "{url}");
+        }
+    }
+
+    #[test]
+    fn classifies_private_ranges() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "::1", "fd00::1", "fe80::1", "::ffff:192.168.0.1"] {
+            assert!(is_private(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["8.8.8.8", "1.1.1.1", "2606:4700::1111"] {
+            assert!(!is_private(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_entries() {
+        let policy = EgressPolicy::default();
+        for entry in ["ftp://example.com", "*", "https://", "example.com/path"] {
+            let config = EgressConfig {
+                allow: vec![entry.to_string()],
+                ..EgressConfig::default()
+            };
+            assert!(policy.set(&config, true).is_err(), "{entry}");
+        }
+    }
+
+    #[test]
+    fn widening_needs_confirmation() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = EgressPolicy::open(dir.path());
+        policy.set(&config(&["https://a.example"], false), true).unwrap();
+
+        let wider = config(&["https://a.example", "https://b.example"], true);
+        let widening = policy.widening(&wider).unwrap().unwrap();
+        assert!(widening.contains("b.example") && widening.contains("Private networks"), "{widening}");
+        assert!(!widening.contains("a.example"), "{widening}");
+
+        // Refused additions are dropped, while what was already allowed stays
+        assert_eq!(kind(policy.set(&wider, false)), "egress_denied");
+        assert_eq!(policy.widening(&config(&["https://a.example"], false)).unwrap(), None);
+        assert!(policy.widening(&wider).unwrap().is_some());
+
+        // Narrowing goes through unconfirmed, and the result outlives a restart
+        assert_eq!(kind(policy.set(&config(&[], false), false)), "ok");
+        assert!(EgressPolicy::open(dir.path()).widening(&config(&["https://a.example"], false)).unwrap().is_some());
+    }
+
+    #[test]
+    fn pac_locations_must_be_allowed() {
+        let policy = policy(&["http://wpad.corp.example"], false);
+        assert_eq!(kind(policy.check_pac("http://wpad.corp.example/proxy.pac")), "ok");
+        assert_eq!(kind(policy.check_pac("http://169.254.169.254/latest")), "egress_denied");
+        assert_eq!(kind(policy.check_pac("/etc/passwd")), "egress_denied");
+        assert_eq!(kind(policy.check_pac("file:///etc/passwd")), "egress_denied");
+        assert_eq!(kind(policy.check_pac("proxy.pac")), "egress_denied");
+        assert_eq!(kind(policy.check_pac("ftp://wpad.corp.example/proxy.pac")), "egress_denied");
+
+        let listed = EgressConfig {
+            pac_files: vec!["file:///etc/proxy.pac".to_string()],
+            ..EgressConfig::default()
+        };
+        assert!(policy.widening(&listed).unwrap().unwrap().contains("/etc/proxy.pac"));
+        policy.set(&listed, true).unwrap();
+        assert_eq!(kind(policy.check_pac("/etc/proxy.pac")), "ok");
+        assert_eq!(kind(policy.check_pac("file:///etc/proxy.pac")), "ok");
+        assert_eq!(kind(policy.check_pac("/etc/passwd")), "egress_denied");
+    }
+
+    #[test]
+    fn refuses_private_addresses_of_public_hosts() {
+        let public: SocketAddr = "8.8.8.8:443".parse().unwrap();
+        let private: SocketAddr = "10.0.0.1:443".parse().unwrap();
+        let strict = policy(&[], false);
+        assert!(strict.guard().filter("api.example", vec![public]).is_ok());
+        assert!(strict.guard().filter("api.example", vec![public, private]).is_err());
+
+        // The guard handed out earlier follows the opt-in
+        let guard = strict.guard();
+        strict.set(&config(&[], true), true).unwrap();
+        assert!(guard.filter("api.example", vec![public, private]).is_ok());
+    }
+}
@@ -0,0 +1,205 @@
+//! Structured errors for the HTTP commands
+//!
+//! Errors cross the IPC boundary as `{ kind, message, ... }` objects so the frontend
+//! can branch on `kind` instead of matching substrings of the message.
+
+use super::timeout::TimeoutKind;
+use serde::Serialize;
+use std::error::Error as _;
+use std::fmt;
+
+/// Failure of an HTTP command or stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpError {
+    /// Could not reach the target or proxy
+    Connect(String),
+    /// Host name did not resolve
+    Dns(String),
+    /// TLS handshake or certificate verification failed
+    Tls(String),
+    /// The server's certificate chain carries none of the keys pinned for its host
+    PinMismatch(String),
+    /// The proxy rejected the configured credentials
+    ProxyAuth(String),
+    /// A request deadline expired
+    Timeout(TimeoutKind),
+    /// Provider answered with a non-success status
+    HttpStatus { status: u16, body: String },
+    /// The response body could not be read or decoded
+    Decode(String),
+    /// Stopped via `http_cancel`
+    Cancelled,
+    /// The destination is not allowed by the egress policy
+    EgressDenied(String),
+    /// The request could not be built from the given config
+    InvalidConfig(String),
+    /// Any other transport failure
+    Request(String),
+}
+
+impl HttpError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            HttpError::Connect(_) => "connect",
+            HttpError::Dns(_) => "dns",
+            HttpError::Tls(_) => "tls",
+            HttpError::PinMismatch(_) => "pin_mismatch",
+            HttpError::ProxyAuth(_) => "proxy_auth",
+            HttpError::Timeout(_) => "timeout",
+            HttpError::HttpStatus { .. } => "http_status",
+            HttpError::Decode(_) => "decode",
+            HttpError::Cancelled => "cancelled",
+            HttpError::EgressDenied(_) => "egress_denied",
+            HttpError::InvalidConfig(_) => "invalid_config",
+            HttpError::Request(_) => "request",
+        }
+    }
+
+    /// Classify a reqwest failure by walking its source chain
+    pub fn from_reqwest(e: &reqwest::Error) -> Self {
+        if e.is_timeout() {
+            let phase = if e.is_connect() {
+                TimeoutKind::Connect
+            } else {
+                TimeoutKind::Total
+            };
+            return HttpError::Timeout(phase);
+        }
+        if e.is_decode() || e.is_body() {
+            return HttpError::Decode(e.to_string());
+        }
+        if e.is_builder() {
+            return HttpError::InvalidConfig(e.to_string());
+        }
+
+        let chain = error_chain(e);
+        let lower = chain.to_lowercase();
+        if lower.contains("proxy authorization required") || lower.contains("credentials not accepted") {
+            HttpError::ProxyAuth(chain)
+        } else if let Some(at) = chain.find("egress: ") {
+            HttpError::EgressDenied(chain[at + "egress: ".len()..].to_string())
+        } else if lower.contains("dns error") || lower.contains("failed to lookup address") {
+            HttpError::Dns(chain)
+        } else if lower.contains("certificate pin mismatch") {
+            HttpError::PinMismatch(chain)
+        } else if lower.contains("certificate") || lower.contains("tls") || lower.contains("ssl") {
+            HttpError::Tls(chain)
+        } else if e.is_connect() {
+            HttpError::Connect(chain)
+        } else {
+            HttpError::Request(chain)
+        }
+    }
+}
+
+/// Join an error with all of its sources, since reqwest's top-level message is generic
+fn error_chain(e: &reqwest::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(inner) = source {
+        message.push_str(": ");
+        message.push_str(&inner.to_string());
+        source = inner.source();
+    }
+    message
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Connect(e) => write!(f, "Connection failed (check proxy settings): {}", e),
+            HttpError::Dns(e) => write!(f, "Could not resolve host: {}", e),
+            HttpError::Tls(e) => write!(f, "TLS error: {}", e),
+            HttpError::PinMismatch(e) => write!(f, "Certificate does not match pinned keys: {}", e),
+            HttpError::ProxyAuth(e) => write!(f, "Proxy rejected credentials: {}", e),
+            HttpError::Timeout(kind) => f.write_str(kind.message()),
+            HttpError::HttpStatus { status, body } => write!(f, "HTTP {}: {}", status, body),
+            HttpError::Decode(e) => write!(f, "Failed to read response body: {}", e),
+            HttpError::Cancelled => f.write_str("Request cancelled"),
+            HttpError::EgressDenied(e) => write!(f, "Blocked by network policy: {}", e),
+            HttpError::InvalidConfig(e) => write!(f, "Invalid request: {}", e),
+            HttpError::Request(e) => write!(f, "Request failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl From<TimeoutKind> for HttpError {
+    fn from(kind: TimeoutKind) -> Self {
+        HttpError::Timeout(kind)
+    }
+}
+
+impl Serialize for HttpError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Repr<'a> {
+            kind: &'static str,
+            message: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            phase: Option<TimeoutKind>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            status: Option<u16>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            body: Option<&'a str>,
+        }
+
+        let (phase, status, body) = match self {
+            HttpError::Timeout(kind) => (Some(*kind), None, None),
+            HttpError::HttpStatus { status, body } => (None, Some(*status), Some(body.as_str())),
+            _ => (None, None, None),
+        };
+
+        Repr {
+            kind: self.kind(),
+            message: self.to_string(),
+            phase,
+            status,
+            body,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn serializes_with_kind_and_message() {
+        assert_eq!(
+            serde_json::to_value(HttpError::Timeout(TimeoutKind::Idle)).unwrap(),
+            json!({ "kind": "timeout", "phase": "idle", "message": "Timed out waiting for more data" })
+        );
+        assert_eq!(
+            serde_json::to_value(HttpError::HttpStatus { status: 529, body: "overloaded".into() }).unwrap(),
+            json!({ "kind": "http_status", "status": 529, "body": "overloaded", "message": "HTTP 529: overloaded" })
+        );
+        assert_eq!(
+            serde_json::to_value(HttpError::Cancelled).unwrap(),
+            json!({ "kind": "cancelled", "message": "Request cancelled" })
+        );
+    }
+
+    #[tokio::test]
+    async fn classifies_refused_connection() {
+        // Bind and drop a listener to get a port that refuses connections
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let e = reqwest::Client::builder()
+            .no_proxy()
+            .build()
+            .unwrap()
+            .get(format!("http://127.0.0.1:{}/", port))
+            .send()
+            .await
+            .unwrap_err();
+
+        assert_eq!(HttpError::from_reqwest(&e).kind(), "connect");
+    }
+}
@@ -0,0 +1,369 @@
+//! Proxy failover
+//!
+//! Laptops move between VPN and office networks, and a proxy that works in one refuses
+//! connections in the other. A `ProxyConfig` may list `failover` profiles that are tried in
+//! order when connecting through it fails. The route that got through is remembered per host
+//! for a cooldown period, so later requests don't wait on the broken proxy first.
+//!
+//! PAC settings, and system settings set to automatic configuration, stand for the routes the
+//! script picks for the request, tried in the script's order. A script that fails to load or
+//! run skips to the next profile, and fails the request when there is none: it never goes direct.
+
+use super::routing::ProxyRoute;
+use super::timeout::TimeoutKind;
+use super::timing::Timer;
+use super::pac::PacRoute;
+use super::{build_request, send, ClientPool, EgressPolicy, HttpError, HttpRequestConfig, ProxyConfig, RetryNotice};
+use crate::secrets::SecretStore;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long the route that last worked for a host is tried first
+const COOLDOWN: Duration = Duration::from_secs(300);
+
+/// Routes to try for a request, primary first and without duplicates; `None` is a direct connection
+pub fn candidates(proxy: Option<&ProxyConfig>) -> Vec<Option<ProxyConfig>> {
+    let Some(primary) = proxy else {
+        return vec![None];
+    };
+
+    let mut routes = Vec::new();
+    for profile in std::iter::once(primary).chain(&primary.failover) {
+        let route = profile.is_enabled().then(|| ProxyConfig {
+            rules: Vec::new(),
+            failover: Vec::new(),
+            ..profile.clone()
+        });
+        if !routes.contains(&route) {
+            routes.push(route);
+        }
+    }
+    routes
+}
+
+/// Replace a PAC route with the routes its script picks for `url`; other routes stand for themselves.
+/// The script's location has to pass the egress policy, whether it was configured or detected.
+async fn expand(
+    pool: &ClientPool,
+    egress: &EgressPolicy,
+    route: Option<ProxyConfig>,
+    url: &str,
+) -> Result<Vec<Option<ProxyConfig>>, HttpError> {
+    let Some(proxy) = route else {
+        return Ok(vec![None]);
+    };
+    let (location, username, password) = if proxy.is_pac() {
+        (proxy.pac_url.clone().unwrap_or_default(), proxy.username.clone(), proxy.password.clone())
+    } else if proxy.is_system() {
+        match pool.system_proxy().await.auto_config() {
+            Some(location) => (location.to_string(), None, None),
+            None => return Ok(vec![Some(proxy)]),
+        }
+    } else {
+        return Ok(vec![Some(proxy)]);
+    };
+
+    egress.check_pac(&location)?;
+    let url = Url::parse(url).map_err(|e| HttpError::InvalidConfig(format!("Invalid URL '{}': {}", url, e)))?;
+    let script = pool.pac_script(&location).await?;
+    let routes = script.routes(url).await?;
+    Ok(routes
+        .into_iter()
+        .map(|route| match route {
+            PacRoute::Direct => None,
+            PacRoute::Proxy(proxy) => Some(ProxyConfig {
+                proxy_type: proxy.scheme().to_string(),
+                host: proxy.host_str().unwrap_or_default().to_string(),
+                port: proxy.port_or_known_default().unwrap_or_default(),
+                username: username.clone(),
+                password: password.clone(),
+                pac_url: None,
+                rules: Vec::new(),
+                failover: Vec::new(),
+            }),
+        })
+        .collect())
+}
+
+/// Whether the request failed before reaching the target, so another route may get through
+fn is_route_failure(error: &HttpError) -> bool {
+    matches!(
+        error,
+        HttpError::Connect(_) | HttpError::Dns(_) | HttpError::ProxyAuth(_) | HttpError::Timeout(TimeoutKind::Connect)
+    )
+}
+
+/// Route that last worked per host, kept in the `ClientPool`
+#[derive(Default)]
+pub struct RouteMemory {
+    last_good: Mutex<HashMap<String, (Option<ProxyConfig>, Instant)>>,
+}
+
+impl RouteMemory {
+    /// Move the route that last worked for the URL's host to the front, unless its cooldown ran out
+    fn prefer(&self, url: &str, routes: &mut [Option<ProxyConfig>]) {
+        let last_good = self.last_good.lock().unwrap();
+        let Some((route, at)) = last_good.get(&host_key(url)) else {
+            return;
+        };
+        if at.elapsed() > COOLDOWN {
+            return;
+        }
+        if let Some(index) = routes.iter().position(|r| r == route) {
+            routes[..=index].rotate_right(1);
+        }
+    }
+
+    fn remember(&self, url: &str, route: &Option<ProxyConfig>) {
+        let mut last_good = self.last_good.lock().unwrap();
+        last_good.retain(|_, (_, at)| at.elapsed() <= COOLDOWN);
+        last_good.insert(host_key(url), (route.clone(), Instant::now()));
+    }
+
+    pub fn clear(&self) {
+        self.last_good.lock().unwrap().clear();
+    }
+}
+
+/// Lowercase host of `url`, empty when it has none
+pub(super) fn host_key(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+        .unwrap_or_default()
+}
+
+/// Send the request through the first route that connects, returning the response and the route taken
+pub async fn send_with_failover(
+    pool: &ClientPool,
+    egress: &EgressPolicy,
+    secrets: &SecretStore,
+    config: &HttpRequestConfig,
+    request_id: &str,
+    timer: &mut Timer,
+    on_retry: impl Fn(RetryNotice),
+) -> Result<(reqwest::Response, ProxyRoute), HttpError> {
+    let profiles = candidates(config.proxy.as_ref());
+    let last_profile = profiles.len() - 1;
+    let mut routes = Vec::new();
+    for (index, profile) in profiles.into_iter().enumerate() {
+        match expand(pool, egress, profile, &config.url).await {
+            Ok(expanded) => {
+                for route in expanded {
+                    if !routes.contains(&route) {
+                        routes.push(route);
+                    }
+                }
+            }
+            // A profile whose script can't be loaded or run is skipped, unless nothing else is left
+            Err(_) if index < last_profile || !routes.is_empty() => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    pool.routes().prefer(&config.url, &mut routes);
+    let last = routes.len() - 1;
+
+    for (index, route) in routes.iter().enumerate() {
+        let client = match pool.get(route.as_ref(), config.tls.as_ref(), config.connect_timeout()).await {
+            Ok(client) => client,
+            // A route whose client can't be built is skipped too
+            Err(_) if index < last => continue,
+            Err(e) => return Err(e),
+        };
+        let request = build_request(&client, config, secrets)?;
+
+        match send(request, config, request_id, timer, &on_retry).await {
+            Ok(response) => {
+                if last > 0 {
+                    pool.routes().remember(&config.url, route);
+                }
+                return Ok((response, ProxyRoute::new(config.matched_rule.clone(), route.as_ref())));
+            }
+            Err(e) if index < last && is_route_failure(&e) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("the last route always returns")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::egress::EgressConfig;
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn proxy(proxy_type: &str, port: u16) -> ProxyConfig {
+        ProxyConfig {
+            proxy_type: proxy_type.to_string(),
+            host: "127.0.0.1".to_string(),
+            port,
+            username: None,
+            password: None,
+            pac_url: None,
+            rules: Vec::new(),
+            failover: Vec::new(),
+        }
+    }
+
+    /// Port nothing listens on
+    fn refused_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    /// Server answering every request with 200 and closing the connection
+    async fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = socket.read(&mut buf).await;
+                    let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok";
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn lists_each_route_once_in_order() {
+        let config = ProxyConfig {
+            failover: vec![proxy("socks5", 1080), proxy("none", 0), proxy("socks5", 1080)],
+            ..proxy("http", 3128)
+        };
+        assert_eq!(
+            candidates(Some(&config)),
+            vec![Some(proxy("http", 3128)), Some(proxy("socks5", 1080)), None]
+        );
+
+        // Direct first, then a proxy
+        let direct_first = ProxyConfig {
+            failover: vec![proxy("http", 3128)],
+            ..proxy("none", 0)
+        };
+        assert_eq!(candidates(Some(&direct_first)), vec![None, Some(proxy("http", 3128))]);
+        assert_eq!(candidates(None), vec![None]);
+    }
+
+    #[test]
+    fn prefers_last_good_route_until_cooldown() {
+        let memory = RouteMemory::default();
+        let url = "https://API.anthropic.com/v1/messages";
+        let routes = vec![Some(proxy("http", 3128)), Some(proxy("socks5", 1080)), None];
+
+        memory.remember(url, &None);
+        let mut ordered = routes.clone();
+        memory.prefer("https://api.anthropic.com/v1/models", &mut ordered);
+        assert_eq!(ordered, vec![None, Some(proxy("http", 3128)), Some(proxy("socks5", 1080))]);
+
+        let mut other_host = routes.clone();
+        memory.prefer("https://api.openai.com/", &mut other_host);
+        assert_eq!(other_host, routes);
+
+        let expired = Instant::now() - COOLDOWN - Duration::from_secs(1);
+        memory.last_good.lock().unwrap().insert(host_key(url), (None, expired));
+        let mut ordered = routes.clone();
+        memory.prefer(url, &mut ordered);
+        assert_eq!(ordered, routes);
+    }
+
+    #[tokio::test]
+    async fn falls_back_when_proxy_refuses_connections() {
+        let url = serve().await;
+        let dir = tempfile::tempdir().unwrap();
+        let secrets = SecretStore::open_file(dir.path()).unwrap();
+        let pool = ClientPool::default();
+        let egress = EgressPolicy::default();
+
+        let broken = json!({
+            "type": "http",
+            "host": "127.0.0.1",
+            "port": refused_port(),
+            "failover": [{ "type": "none", "host": "", "port": 0 }],
+        });
+        let config: HttpRequestConfig = serde_json::from_value(json!({
+            "url": url,
+            "method": "GET",
+            "headers": {},
+            "proxy": broken,
+        }))
+        .unwrap();
+
+        let (response, route) = send_with_failover(&pool, &egress, &secrets, &config, "failover", &mut Timer::start(), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(route.route_type, "direct");
+
+        // The direct route is now tried first
+        let mut routes = candidates(config.proxy.as_ref());
+        pool.routes().prefer(&url, &mut routes);
+        assert_eq!(routes[0], None);
+
+        // Without a fallback the failure surfaces
+        let config: HttpRequestConfig = serde_json::from_value(json!({
+            "url": url,
+            "method": "GET",
+            "headers": {},
+            "proxy": { "type": "http", "host": "127.0.0.1", "port": refused_port() },
+        }))
+        .unwrap();
+        let error = send_with_failover(&pool, &egress, &secrets, &config, "failover", &mut Timer::start(), |_| {})
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), "connect");
+    }
+
+    #[tokio::test]
+    async fn tries_every_route_the_pac_script_gives() {
+        let url = serve().await;
+        let dir = tempfile::tempdir().unwrap();
+        let secrets = SecretStore::open_file(dir.path()).unwrap();
+        let pool = ClientPool::default();
+        let pac_file = |name: &str| dir.path().join(format!("{}.pac", name)).to_string_lossy().into_owned();
+        let egress = EgressPolicy::default();
+        let listed = EgressConfig {
+            pac_files: vec![pac_file("fallback"), pac_file("broken")],
+            ..EgressConfig::default()
+        };
+        egress.set(&listed, true).unwrap();
+        let pac = |name: &str, script: String| {
+            std::fs::write(pac_file(name), format!("function FindProxyForURL(url, host) {{ {} }}", script)).unwrap();
+            serde_json::from_value::<HttpRequestConfig>(json!({
+                "url": url,
+                "method": "GET",
+                "headers": {},
+                "proxy": { "type": "pac", "host": "", "port": 0, "pac_url": pac_file(name) },
+            }))
+            .unwrap()
+        };
+
+        let fallback = pac("fallback", format!("return \"PROXY 127.0.0.1:{}; DIRECT\";", refused_port()));
+        let (response, route) = send_with_failover(&pool, &egress, &secrets, &fallback, "pac", &mut Timer::start(), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(route.route_type, "direct");
+
+        // A failing script doesn't mean a direct connection
+        let broken = pac("broken", "throw new Error(\"boom\");".to_string());
+        let error = send_with_failover(&pool, &egress, &secrets, &broken, "pac", &mut Timer::start(), |_| {})
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("boom"), "{error}");
+
+        // Nor is a script the policy doesn't list read at all
+        let unlisted = pac("unlisted", "return \"DIRECT\";".to_string());
+        let error = send_with_failover(&pool, &egress, &secrets, &unlisted, "pac", &mut Timer::start(), |_| {})
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), "egress_denied");
+    }
+}
@@ -0,0 +1,457 @@
+//! Proxy auto-config (PAC) for the `pac` proxy type
+//!
+//! Corporate networks publish a PAC script whose `FindProxyForURL(url, host)` picks the
+//! proxies for each destination. The script is loaded once per location, kept by the
+//! `ClientPool`, and run in an embedded QuickJS context with the standard PAC helper
+//! functions. Answers are cached per origin, so the script only sees `scheme://host:port/`
+//! and never request paths. Scripts may look up names, so they only run on the blocking pool;
+//! failover then tries every route of the answer in order.
+
+use super::HttpError;
+use reqwest::Url;
+use rquickjs::{Context, Ctx, Function, Runtime};
+use std::collections::HashMap;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Deadline for downloading the script
+const LOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Deadline for one run of the script, so a runaway loop can't stall every request
+const EVAL_TIMEOUT: Duration = Duration::from_secs(1);
+
+const MAX_SCRIPT_BYTES: usize = 1024 * 1024;
+const MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Cached answers beyond this are dropped wholesale
+const MAX_CACHED: usize = 1024;
+
+/// Helper functions PAC scripts expect, after Netscape's original definitions.
+/// Name lookups and `myIpAddress` are provided from Rust.
+const PAC_UTILS: &str = r#"
+var DAYS = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+var MONTHS = ["JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"];
+
+function alert() {}
+
+function dnsResolve(host) {
+  var ip = lookupHost(host);
+  return ip === undefined ? null : ip;
+}
+
+function isPlainHostName(host) {
+  return host.indexOf(".") < 0;
+}
+
+function dnsDomainIs(host, domain) {
+  host = host.toLowerCase();
+  domain = domain.toLowerCase();
+  return host.length >= domain.length && host.substring(host.length - domain.length) === domain;
+}
+
+function localHostOrDomainIs(host, hostdom) {
+  return host === hostdom || hostdom.lastIndexOf(host + ".", 0) === 0;
+}
+
+function isResolvable(host) {
+  return dnsResolve(host) !== null;
+}
+
+function dnsDomainLevels(host) {
+  return host.split(".").length - 1;
+}
+
+function convert_addr(ip) {
+  var parts = ip.split(".");
+  return ((parts[0] << 24) | (parts[1] << 16) | (parts[2] << 8) | parts[3]) >>> 0;
+}
+
+function isInNet(host, pattern, mask) {
+  var ip = /^\d+\.\d+\.\d+\.\d+$/.test(host) ? host : dnsResolve(host);
+  if (ip === null) return false;
+  var m = convert_addr(mask);
+  return ((convert_addr(ip) & m) >>> 0) === ((convert_addr(pattern) & m) >>> 0);
+}
+
+function shExpMatch(str, pattern) {
+  var source = pattern
+    .replace(/[.+^${}()|[\]\\]/g, "\\$&")
+    .replace(/\*/g, ".*")
+    .replace(/\?/g, ".");
+  return new RegExp("^" + source + "$").test(str);
+}
+
+function pacArgs(args) {
+  var list = Array.prototype.slice.call(args);
+  var gmt = list[list.length - 1] === "GMT";
+  if (gmt) list.pop();
+  return { list: list, gmt: gmt, now: new Date() };
+}
+
+function inRange(value, from, to) {
+  return from <= to ? value >= from && value <= to : value >= from || value <= to;
+}
+
+function weekdayRange() {
+  var a = pacArgs(arguments);
+  var day = a.gmt ? a.now.getUTCDay() : a.now.getDay();
+  var from = DAYS.indexOf(a.list[0]);
+  var to = a.list.length > 1 ? DAYS.indexOf(a.list[1]) : from;
+  return from >= 0 && to >= 0 && inRange(day, from, to);
+}
+
+function dateRange() {
+  var a = pacArgs(arguments);
+  var current = {
+    year: a.gmt ? a.now.getUTCFullYear() : a.now.getFullYear(),
+    month: a.gmt ? a.now.getUTCMonth() : a.now.getMonth(),
+    day: a.gmt ? a.now.getUTCDate() : a.now.getDate(),
+  };
+  function parse(values) {
+    var date = {};
+    values.forEach(function (v) {
+      if (MONTHS.indexOf(v) >= 0) date.month = MONTHS.indexOf(v);
+      else if (v > 31) date.year = v;
+      else date.day = v;
+    });
+    return date;
+  }
+  function key(date, fields) {
+    return fields.reduce(function (k, f) { return k * 10000 + date[f]; }, 0);
+  }
+
+  if (a.list.length === 0 || a.list.length % 2 === 1 && a.list.length > 1) return false;
+  var half = a.list.length === 1 ? 1 : a.list.length / 2;
+  var from = parse(a.list.slice(0, half));
+  var to = a.list.length === 1 ? from : parse(a.list.slice(half));
+  var fields = ["year", "month", "day"].filter(function (f) { return f in from && f in to; });
+  if (fields.length === 0) return false;
+  return inRange(key(current, fields), key(from, fields), key(to, fields));
+}
+
+function timeRange() {
+  var a = pacArgs(arguments);
+  var n = a.list.map(Number);
+  var h = a.gmt ? a.now.getUTCHours() : a.now.getHours();
+  var m = a.gmt ? a.now.getUTCMinutes() : a.now.getMinutes();
+  var s = a.gmt ? a.now.getUTCSeconds() : a.now.getSeconds();
+  var now = h * 3600 + m * 60 + s;
+  switch (n.length) {
+    case 1: return h === n[0];
+    case 2: return inRange(now, n[0] * 3600, n[1] * 3600 - 1);
+    case 4: return inRange(now, n[0] * 3600 + n[1] * 60, n[2] * 3600 + n[3] * 60 - 1);
+    case 6: return inRange(now, n[0] * 3600 + n[1] * 60 + n[2], n[3] * 3600 + n[4] * 60 + n[5]);
+    default: return false;
+  }
+}
+"#;
+
+/// One entry of a `FindProxyForURL` answer such as `PROXY a:8080; SOCKS b:1080; DIRECT`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PacRoute {
+    Direct,
+    Proxy(Url),
+}
+
+/// Parse a `FindProxyForURL` answer, skipping entries reqwest can't use.
+/// An empty answer means a direct connection.
+pub fn parse_answer(answer: &str) -> Vec<PacRoute> {
+    let routes: Vec<PacRoute> = answer
+        .split(';')
+        .filter_map(|entry| {
+            let mut parts = entry.split_whitespace();
+            let kind = parts.next()?.to_ascii_uppercase();
+            let scheme = match kind.as_str() {
+                "DIRECT" => return Some(PacRoute::Direct),
+                "PROXY" | "HTTP" => "http",
+                "HTTPS" => "https",
+                // PAC scripts mean the proxy resolves names, as browsers do
+                "SOCKS" | "SOCKS5" => "socks5h",
+                _ => return None,
+            };
+            let address = parts.next()?;
+            Url::parse(&format!("{}://{}", scheme, address)).ok().map(PacRoute::Proxy)
+        })
+        .collect();
+
+    if routes.is_empty() {
+        vec![PacRoute::Direct]
+    } else {
+        routes
+    }
+}
+
+/// File a `file://` URL or absolute path location refers to; `None` for URLs and relative paths
+pub fn local_path(location: &str) -> Option<&Path> {
+    let location = location.trim();
+    if location.starts_with("http://") || location.starts_with("https://") {
+        return None;
+    }
+    let path = Path::new(location.strip_prefix("file://").unwrap_or(location));
+    path.is_absolute().then_some(path)
+}
+
+/// A loaded PAC script
+pub struct PacResolver {
+    // Declared before the runtime so the context is dropped first
+    context: Context,
+    _runtime: Runtime,
+    /// End of the current run; checked by the interrupt handler
+    deadline: Arc<Mutex<Option<Instant>>>,
+    cache: Mutex<HashMap<String, Vec<PacRoute>>>,
+}
+
+impl PacResolver {
+    /// Load the script from an `http(s)://` URL, a `file://` URL or an absolute path
+    pub async fn load(location: &str) -> Result<Self, HttpError> {
+        let location = location.trim();
+        let script = if location.starts_with("http://") || location.starts_with("https://") {
+            fetch(location).await?
+        } else {
+            let Some(path) = local_path(location) else {
+                return Err(HttpError::InvalidConfig(format!(
+                    "PAC location must be a URL or an absolute path: '{}'",
+                    location
+                )));
+            };
+            tokio::fs::read_to_string(path).await.map_err(|e| load_error(location, e))?
+        };
+        tokio::task::spawn_blocking(move || Self::from_script(&script))
+            .await
+            .map_err(|e| load_error(location, e))?
+    }
+
+    /// Compile a script and check that it defines `FindProxyForURL`
+    pub fn from_script(script: &str) -> Result<Self, HttpError> {
+        if script.len() > MAX_SCRIPT_BYTES {
+            return Err(HttpError::InvalidConfig("PAC script is too large".to_string()));
+        }
+        let engine_error = |e: rquickjs::Error| HttpError::InvalidConfig(format!("Failed to start PAC engine: {}", e));
+
+        let runtime = Runtime::new().map_err(engine_error)?;
+        runtime.set_memory_limit(MEMORY_LIMIT);
+        let deadline: Arc<Mutex<Option<Instant>>> = Arc::default();
+        let expired = deadline.clone();
+        runtime.set_interrupt_handler(Some(Box::new(move || {
+            expired.lock().unwrap().is_some_and(|at| Instant::now() > at)
+        })));
+        let context = Context::full(&runtime).map_err(engine_error)?;
+
+        let resolver = Self {
+            context,
+            _runtime: runtime,
+            deadline,
+            cache: Mutex::default(),
+        };
+        resolver
+            .run(|ctx| {
+                let globals = ctx.globals();
+                globals.set("lookupHost", Function::new(ctx.clone(), lookup_host)?)?;
+                globals.set("myIpAddress", Function::new(ctx.clone(), my_ip_address)?)?;
+                ctx.eval::<(), _>(PAC_UTILS)?;
+                ctx.eval::<(), _>(script)?;
+                globals.get::<_, Function>("FindProxyForURL").map(drop)
+            })
+            .map_err(|e| HttpError::InvalidConfig(format!("Invalid PAC script: {}", e)))?;
+        Ok(resolver)
+    }
+
+    /// `find` on the blocking pool, for async callers
+    pub async fn routes(self: Arc<Self>, url: Url) -> Result<Vec<PacRoute>, HttpError> {
+        tokio::task::spawn_blocking(move || self.find(&url))
+            .await
+            .map_err(|e| HttpError::Request(format!("PAC script failed: {}", e)))?
+    }
+
+    /// Routes for `url` in the script's order of preference. Blocks while the script looks up names.
+    pub fn find(&self, url: &Url) -> Result<Vec<PacRoute>, HttpError> {
+        let origin = url.origin().ascii_serialization();
+        if let Some(routes) = self.cache.lock().unwrap().get(&origin) {
+            return Ok(routes.clone());
+        }
+
+        let host = url.host_str().unwrap_or_default().trim_matches(['[', ']']).to_string();
+        let answer = self
+            .run(|ctx| {
+                let find: Function = ctx.globals().get("FindProxyForURL")?;
+                find.call::<_, String>((format!("{}/", origin), host))
+            })
+            .map_err(|e| HttpError::Request(format!("PAC script failed: {}", e)))?;
+        let routes = parse_answer(&answer);
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED {
+            cache.clear();
+        }
+        cache.insert(origin, routes.clone());
+        Ok(routes)
+    }
+
+    /// First proxy the script picks for `url` with the given credentials added, or `None` to go
+    /// direct; diagnostics test this hop, while requests fall back through the whole answer
+    pub fn first_proxy(&self, url: &Url, username: Option<&str>, password: Option<&str>) -> Result<Option<Url>, HttpError> {
+        Ok(match self.find(url)?.into_iter().next() {
+            Some(PacRoute::Proxy(mut proxy)) => {
+                if let Some(user) = username {
+                    let _ = proxy.set_username(user);
+                    let _ = proxy.set_password(password);
+                }
+                Some(proxy)
+            }
+            Some(PacRoute::Direct) | None => None,
+        })
+    }
+
+    /// Run `f` in the script's context under the evaluation deadline. The deadline is set and
+    /// cleared while the context lock is held, so a concurrent run can't clear another's.
+    fn run<T>(&self, f: impl for<'js> FnOnce(&Ctx<'js>) -> rquickjs::Result<T>) -> Result<T, String> {
+        self.context.with(|ctx| {
+            *self.deadline.lock().unwrap() = Some(Instant::now() + EVAL_TIMEOUT);
+            let result = f(&ctx).map_err(|e| match e {
+                rquickjs::Error::Exception => {
+                    let thrown = ctx.catch();
+                    match thrown.as_exception().and_then(|ex| ex.message()) {
+                        Some(message) => message,
+                        None => format!("uncaught {:?}", thrown),
+                    }
+                }
+                e => e.to_string(),
+            });
+            *self.deadline.lock().unwrap() = None;
+            result
+        })
+    }
+}
+
+fn load_error(location: &str, error: impl std::fmt::Display) -> HttpError {
+    HttpError::InvalidConfig(format!("Failed to load PAC script from {}: {}", location, error))
+}
+
+/// Download the script directly, without any proxy
+async fn fetch(url: &str) -> Result<String, HttpError> {
+    let client = reqwest::Client::builder()
+        .no_proxy()
+        .timeout(LOAD_TIMEOUT)
+        .build()
+        .map_err(|e| load_error(url, e))?;
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| load_error(url, e))?;
+    response.text().await.map_err(|e| load_error(url, e))
+}
+
+/// First IPv4 address of `host`, behind `dnsResolve`
+fn lookup_host(host: String) -> Option<String> {
+    (host.as_str(), 0)
+        .to_socket_addrs()
+        .ok()?
+        .find(|addr| addr.is_ipv4())
+        .map(|addr| addr.ip().to_string())
+}
+
+/// `myIpAddress()`: address of the interface used for outgoing traffic
+fn my_ip_address() -> String {
+    // Connecting a UDP socket sends nothing; it only picks the route
+    UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| {
+            socket.connect("8.8.8.8:53")?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|_| "127.0.0.1".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = r#"
+        var calls = 0;
+        function FindProxyForURL(url, host) {
+          calls++;
+          if (isPlainHostName(host) || dnsDomainIs(host, ".corp.example")) return "DIRECT";
+          if (isInNet(host, "10.0.0.0", "255.0.0.0")) return "SOCKS socks.corp.example:1080";
+          if (shExpMatch(url, "http://*")) return "PROXY legacy.corp.example:3128";
+          if (shExpMatch(host, "api.*.com")) return "PROXY proxy.corp.example:8080; DIRECT";
+          return "";
+        }
+    "#;
+
+    fn first(resolver: &PacResolver, url: &str) -> PacRoute {
+        resolver.find(&Url::parse(url).unwrap()).unwrap().remove(0)
+    }
+
+    fn via(proxy: &str) -> PacRoute {
+        PacRoute::Proxy(Url::parse(proxy).unwrap())
+    }
+
+    #[test]
+    fn parses_answers() {
+        assert_eq!(
+            parse_answer("PROXY a.example:8080; SOCKS5 b.example:1080;DIRECT; SOCKS4 c.example:1080"),
+            vec![via("http://a.example:8080"), via("socks5h://b.example:1080"), PacRoute::Direct]
+        );
+        assert_eq!(parse_answer("HTTPS secure.example:443"), vec![via("https://secure.example:443")]);
+        assert_eq!(parse_answer(""), vec![PacRoute::Direct]);
+    }
+
+    #[test]
+    fn evaluates_script_per_origin() {
+        let resolver = PacResolver::from_script(SCRIPT).unwrap();
+
+        assert_eq!(first(&resolver, "https://intranet/"), PacRoute::Direct);
+        assert_eq!(first(&resolver, "https://wiki.corp.example/page"), PacRoute::Direct);
+        assert_eq!(first(&resolver, "https://10.1.2.3/"), via("socks5h://socks.corp.example:1080"));
+        assert_eq!(first(&resolver, "http://example.org/"), via("http://legacy.corp.example:3128"));
+        assert_eq!(first(&resolver, "https://api.openai.com/v1/chat"), via("http://proxy.corp.example:8080"));
+        assert_eq!(first(&resolver, "https://example.org/"), PacRoute::Direct);
+
+        // Other paths on a known origin are answered from the cache
+        first(&resolver, "https://api.openai.com/v1/responses");
+        let calls = resolver.run(|ctx| ctx.globals().get::<_, i32>("calls")).unwrap();
+        assert_eq!(calls, 6);
+    }
+
+    #[test]
+    fn rejects_broken_scripts() {
+        assert!(PacResolver::from_script("function FindProxyForURL(url, host) {").is_err());
+        assert!(PacResolver::from_script("var x = 1;").is_err());
+
+        let throwing = PacResolver::from_script("function FindProxyForURL() { throw new Error('boom'); }").unwrap();
+        let error = throwing.find(&Url::parse("https://example.org/").unwrap()).unwrap_err();
+        assert!(error.to_string().contains("boom"), "{error}");
+
+        let looping = PacResolver::from_script("function FindProxyForURL() { for (;;) {} }").unwrap();
+        assert!(looping.find(&Url::parse("https://example.org/").unwrap()).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn interrupts_concurrent_runaway_scripts() {
+        let looping = Arc::new(PacResolver::from_script("function FindProxyForURL() { while (true) {} }").unwrap());
+
+        // Runs take turns on the context, so each gets its own EVAL_TIMEOUT
+        let started = Instant::now();
+        let (a, b) = tokio::join!(
+            looping.clone().routes(Url::parse("https://a.example/").unwrap()),
+            looping.clone().routes(Url::parse("https://b.example/").unwrap()),
+        );
+        assert!(a.is_err() && b.is_err());
+        assert!(started.elapsed() < EVAL_TIMEOUT * 2 + Duration::from_millis(500), "took {:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn loads_script_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("proxy.pac");
+        std::fs::write(&path, SCRIPT).unwrap();
+
+        let resolver = PacResolver::load(&format!("file://{}", path.display())).await.unwrap();
+        assert_eq!(first(&resolver, "https://intranet/"), PacRoute::Direct);
+        assert!(PacResolver::load("relative/proxy.pac").await.is_err());
+    }
+}
@@ -0,0 +1,373 @@
+//! Public key pinning
+//!
+//! A gateway whose CA the machine trusts can read API keys in transit. A pin set lists the
+//! public keys a host may present, as base64 SHA-256 hashes of the subject public key info
+//! (the `spki_sha256` that `tls_inspect` shows). A chain matches when the leaf, an intermediate
+//! or the trust anchor it chains to carries a pinned key. Mismatches fail the handshake with
+//! `pin_mismatch`; report-only sets log them and let the connection through, so a pin set can
+//! be tried out before it's enforced.
+//!
+//! Pin sets live in the backend's `PinStore`, not in the TLS settings requests carry. Adding
+//! pins applies at once; a change that lets a host present other keys, or stops enforcing its
+//! pins, needs the user's confirmation in a native dialog. The confirmed sets are saved.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use super::tls::TlsConfig;
+use super::HttpError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::client::WebPkiServerVerifier;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{self, DigitallySignedStruct, RootCertStore, SignatureScheme};
+
+/// Mismatches kept for `tls_pin_reports`
+const LOG_SIZE: usize = 50;
+
+static VIOLATIONS: Mutex<VecDeque<PinViolation>> = Mutex::new(VecDeque::new());
+
+/// Keys a host may present
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinSet {
+    /// Exact host name, or `*.example.com` for its subdomains
+    pub host: String,
+    /// Base64 SHA-256 hashes of subject public key infos, optionally prefixed with `sha256/`
+    pub pins: Vec<String>,
+    /// Log mismatches instead of failing the connection
+    #[serde(default)]
+    pub report_only: bool,
+}
+
+impl PinSet {
+    fn matches_host(&self, host: &str) -> bool {
+        let pattern = self.host.trim().to_ascii_lowercase();
+        let host = host.to_ascii_lowercase();
+        match pattern.strip_prefix("*.") {
+            Some(domain) => host.strip_suffix(domain).is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+            None => host == pattern,
+        }
+    }
+
+    /// Pinned hashes without the optional prefix
+    fn keys(&self) -> impl Iterator<Item = &str> {
+        self.pins
+            .iter()
+            .map(|pin| pin.trim())
+            .map(|pin| pin.strip_prefix("sha256/").unwrap_or(pin))
+    }
+
+    /// Whether any of the chain's keys is pinned
+    pub(super) fn allows(&self, keys: &[String]) -> bool {
+        self.keys().any(|pin| keys.iter().any(|key| key == pin))
+    }
+}
+
+/// Pin sets held in Tauri managed state
+#[derive(Debug, Default)]
+pub struct PinStore {
+    sets: RwLock<Vec<PinSet>>,
+    /// Where the confirmed sets are saved; nothing is saved when absent
+    path: Option<PathBuf>,
+}
+
+impl PinStore {
+    /// Load the pin sets confirmed in an earlier session from `dir`
+    pub fn open(dir: &Path) -> Self {
+        let path = dir.join("pins.json");
+        let sets = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Self {
+            sets: RwLock::new(sets),
+            path: Some(path),
+        }
+    }
+
+    /// Pin sets every connection is checked against
+    pub fn current(&self) -> Vec<PinSet> {
+        self.sets.read().unwrap().clone()
+    }
+
+    /// Put the current pin sets into a request's TLS settings
+    pub fn apply(&self, tls: &mut Option<TlsConfig>) {
+        let sets = self.current();
+        if tls.is_some() || !sets.is_empty() {
+            tls.get_or_insert_with(TlsConfig::default).pins = sets;
+        }
+    }
+
+    /// What replacing the pin sets with `sets` would relax, as a line per host for the user to
+    /// confirm; `None` when every pinned host stays at least as strictly pinned
+    pub fn loosening(&self, sets: &[PinSet]) -> Option<String> {
+        let current = self.sets.read().unwrap();
+        let mut hosts: Vec<String> = Vec::new();
+        for set in current.iter().chain(sets) {
+            let host = set.host.trim().to_ascii_lowercase();
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+
+        let lines: Vec<String> = hosts
+            .iter()
+            .filter_map(|host| {
+                let before = for_host(&current, host)?;
+                let change = match for_host(sets, host) {
+                    None => "no longer pinned",
+                    Some(after) if after.keys().any(|key| !before.keys().any(|k| k == key)) => "accepts other keys",
+                    Some(after) if after.report_only && !before.report_only => "mismatches only reported",
+                    Some(_) => return None,
+                };
+                Some(format!("{}: {}", host, change))
+            })
+            .collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    /// Replace the pin sets. Without `confirmed`, a change that relaxes any host is refused as a whole.
+    pub fn set(&self, sets: Vec<PinSet>, confirmed: bool) -> Result<(), HttpError> {
+        if !confirmed {
+            if let Some(relaxed) = self.loosening(&sets) {
+                return Err(HttpError::InvalidConfig(format!("Not confirmed: {}", relaxed.replace('\n', ", "))));
+            }
+        }
+        if let Some(path) = &self.path {
+            if let Ok(data) = serde_json::to_vec(&sets) {
+                let _ = std::fs::write(path, data);
+            }
+        }
+        *self.sets.write().unwrap() = sets;
+        Ok(())
+    }
+}
+
+/// Pin set covering the host; the first match wins
+pub(super) fn for_host<'a>(pins: &'a [PinSet], host: &str) -> Option<&'a PinSet> {
+    pins.iter().find(|set| set.matches_host(host))
+}
+
+/// Chain that didn't carry a pinned key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PinViolation {
+    pub host: String,
+    /// Keys the chain carried, leaf first
+    pub presented: Vec<String>,
+    /// Whether the connection was refused, or only reported
+    pub enforced: bool,
+    /// Unix timestamp in seconds
+    pub at: u64,
+}
+
+fn record(violation: PinViolation) {
+    let mut log = VIOLATIONS.lock().unwrap();
+    if log.len() == LOG_SIZE {
+        log.pop_front();
+    }
+    log.push_back(violation);
+}
+
+/// Recent mismatches, oldest first
+pub fn recent() -> Vec<PinViolation> {
+    VIOLATIONS.lock().unwrap().iter().cloned().collect()
+}
+
+/// Base64 SHA-256 of a DER subject public key info
+pub(super) fn spki_sha256(spki: &[u8]) -> String {
+    BASE64.encode(Sha256::digest(spki))
+}
+
+/// Keys of the presented certificates, then of the trust anchor that issued the last of them
+pub(super) fn chain_keys(chain: &[CertificateDer<'_>], roots: &RootCertStore) -> Vec<String> {
+    let parsed: Vec<_> = chain
+        .iter()
+        .filter_map(|cert| x509_parser::parse_x509_certificate(cert).ok())
+        .map(|(_, cert)| cert)
+        .collect();
+    let mut keys: Vec<String> = parsed.iter().map(|cert| spki_sha256(cert.public_key().raw)).collect();
+
+    // Trust anchors keep the contents of their subject and key fields without the outer SEQUENCE
+    if let Some(last) = parsed.last() {
+        let anchor = roots
+            .roots
+            .iter()
+            .find(|anchor| der_sequence(anchor.subject.as_ref()) == last.issuer().as_raw());
+        if let Some(anchor) = anchor {
+            let key = spki_sha256(&der_sequence(anchor.subject_public_key_info.as_ref()));
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+    keys
+}
+
+/// DER SEQUENCE wrapping `contents`
+fn der_sequence(contents: &[u8]) -> Vec<u8> {
+    let mut der = vec![0x30];
+    let len = contents.len();
+    if len < 0x80 {
+        der.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+        der.push(0x80 | bytes.len() as u8);
+        der.extend(bytes);
+    }
+    der.extend_from_slice(contents);
+    der
+}
+
+/// Normal verification, then the pin set of the server's host if it has one
+#[derive(Debug)]
+pub(super) struct PinningVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    roots: Arc<RootCertStore>,
+    pins: Vec<PinSet>,
+}
+
+impl PinningVerifier {
+    pub(super) fn new(inner: Arc<WebPkiServerVerifier>, roots: Arc<RootCertStore>, pins: Vec<PinSet>) -> Self {
+        Self { inner, roots, pins }
+    }
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self
+            .inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+
+        let host = server_name.to_str();
+        let Some(set) = for_host(&self.pins, &host) else {
+            return Ok(verified);
+        };
+        let chain: Vec<_> = std::iter::once(end_entity).chain(intermediates).cloned().collect();
+        let keys = chain_keys(&chain, &self.roots);
+        if set.allows(&keys) {
+            return Ok(verified);
+        }
+
+        record(PinViolation {
+            host: host.to_string(),
+            presented: keys,
+            enforced: !set.report_only,
+            at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+        });
+        if set.report_only {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::General(format!("certificate pin mismatch for {}", host)))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(host: &str, pins: &[&str]) -> PinSet {
+        PinSet {
+            host: host.to_string(),
+            pins: pins.iter().map(|pin| pin.to_string()).collect(),
+            report_only: false,
+        }
+    }
+
+    #[test]
+    fn matches_exact_hosts_and_subdomain_patterns() {
+        let pins = vec![set("api.anthropic.com", &[]), set("*.openai.com", &[])];
+        assert_eq!(for_host(&pins, "API.Anthropic.com"), Some(&pins[0]));
+        assert_eq!(for_host(&pins, "api.openai.com"), Some(&pins[1]));
+        assert_eq!(for_host(&pins, "openai.com"), None);
+        assert_eq!(for_host(&pins, "notopenai.com"), None);
+        assert_eq!(for_host(&pins, "console.anthropic.com"), None);
+    }
+
+    #[test]
+    fn accepts_pins_with_or_without_prefix() {
+        let keys = vec!["leafkey=".to_string(), "rootkey=".to_string()];
+        assert!(set("h", &["sha256/rootkey="]).allows(&keys));
+        assert!(set("h", &[" leafkey= "]).allows(&keys));
+        assert!(!set("h", &["other="]).allows(&keys));
+        assert!(!set("h", &[]).allows(&keys));
+    }
+
+    #[test]
+    fn relaxing_pins_needs_confirmation() {
+        let store = PinStore::default();
+        let strict = vec![set("api.anthropic.com", &["a=", "b="]), set("*.openai.com", &["c="])];
+        store.set(strict.clone(), false).unwrap();
+
+        // Fewer keys, or a new pinned host, only tighten
+        let tighter = vec![set("api.anthropic.com", &["sha256/a="]), set("*.openai.com", &["c="]), set("example.com", &["d="])];
+        assert_eq!(store.loosening(&tighter), None);
+        store.set(tighter, false).unwrap();
+
+        let report_only = PinSet {
+            report_only: true,
+            ..set("*.openai.com", &["c="])
+        };
+        // An exact set shadows the wildcard one for its host
+        let looser = vec![set("api.anthropic.com", &["a=", "x="]), set("api.openai.com", &["y="]), report_only];
+        assert_eq!(
+            store.loosening(&looser).as_deref(),
+            Some("api.anthropic.com: accepts other keys\n*.openai.com: mismatches only reported\nexample.com: no longer pinned\napi.openai.com: accepts other keys")
+        );
+        let error = store.set(looser.clone(), false).unwrap_err();
+        assert_eq!(error.kind(), "invalid_config");
+        assert_eq!(store.current().len(), 3);
+
+        store.set(looser.clone(), true).unwrap();
+        assert_eq!(store.current(), looser);
+    }
+
+    #[test]
+    fn keeps_confirmed_pins_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let pins = vec![set("api.anthropic.com", &["a="])];
+        PinStore::open(dir.path()).set(pins.clone(), false).unwrap();
+        assert_eq!(PinStore::open(dir.path()).current(), pins);
+    }
+
+    #[test]
+    fn wraps_long_contents_in_sequence() {
+        assert_eq!(der_sequence(&[1, 2]), vec![0x30, 2, 1, 2]);
+        let long = der_sequence(&[0; 300]);
+        assert_eq!(&long[..4], &[0x30, 0x82, 0x01, 0x2c]);
+        assert_eq!(long.len(), 304);
+    }
+}
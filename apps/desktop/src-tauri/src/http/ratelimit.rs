@@ -0,0 +1,470 @@
+//! Rate limiting per provider
+//!
+//! Several agents talking to one provider quickly exceed its requests-per-minute and
+//! tokens-per-minute limits, and the 429s that follow cost more time than waiting would.
+//! Each request takes from two token buckets of its host, one counting requests and one
+//! estimated tokens, and waits in line while either is short. Limits come from the settings
+//! until a response reports the provider's own through `x-ratelimit-*` or
+//! `anthropic-ratelimit-*` headers, which then take over. Hosts without either aren't limited.
+//!
+//! A reported remaining count may predate requests still waiting for their response, so the
+//! estimates of those in flight come off it before it replaces the bucket level.
+
+use super::failover::host_key;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Period the limits are expressed in
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Longest wait before the head of a queue checks again, since limits may change meanwhile
+const RECHECK: Duration = Duration::from_secs(1);
+
+/// Limits from frontend for one host
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RateLimitConfig {
+    pub host: String,
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+}
+
+/// One bucket as reported by `rate_limit_status`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BucketLevel {
+    /// Capacity, refilled evenly over a minute
+    pub limit: u64,
+    pub available: u64,
+    /// Whether the limit was reported by the provider rather than set in the settings
+    pub from_headers: bool,
+}
+
+/// Buckets of one host; a bucket is absent while its host has no limit
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LaneStatus {
+    pub host: String,
+    pub requests: Option<BucketLevel>,
+    pub tokens: Option<BucketLevel>,
+    /// Requests waiting for capacity
+    pub queued: usize,
+}
+
+#[derive(Debug, Default)]
+struct Bucket {
+    limit: Option<f64>,
+    level: f64,
+    from_headers: bool,
+    /// Taken by requests whose response hasn't been observed yet
+    in_flight: f64,
+}
+
+impl Bucket {
+    fn refill(&mut self, elapsed: Duration) {
+        if let Some(limit) = self.limit {
+            self.level = (self.level + limit * elapsed.as_secs_f64() / WINDOW.as_secs_f64()).min(limit);
+        }
+    }
+
+    /// Time until `amount` is available; amounts over the limit wait for a full bucket
+    fn wait(&self, amount: f64) -> Duration {
+        let Some(limit) = self.limit else {
+            return Duration::ZERO;
+        };
+        let missing = amount.min(limit) - self.level;
+        if missing <= 0.0 {
+            return Duration::ZERO;
+        }
+        WINDOW.mul_f64(missing / limit)
+    }
+
+    fn take(&mut self, amount: f64) {
+        if let Some(limit) = self.limit {
+            self.level -= amount.min(limit);
+        }
+        self.in_flight += amount;
+    }
+
+    /// A request taken from this bucket got its response or gave up
+    fn settle(&mut self, amount: f64) {
+        self.in_flight = (self.in_flight - amount).max(0.0);
+    }
+
+    /// Apply a limit from the settings; ignored once the provider reported its own
+    fn configure(&mut self, limit: Option<u32>) {
+        if self.from_headers {
+            return;
+        }
+        match limit.filter(|l| *l > 0).map(f64::from) {
+            Some(limit) => {
+                self.level = if self.limit.is_some() { self.level.min(limit) } else { limit };
+                self.limit = Some(limit);
+            }
+            None => self.limit = None,
+        }
+    }
+
+    /// Apply what a response reported, less what other requests in flight will take
+    fn learn(&mut self, limit: Option<f64>, remaining: Option<f64>) {
+        if let Some(limit) = limit.filter(|l| *l > 0.0) {
+            if self.limit.is_none() {
+                self.level = limit;
+            }
+            self.limit = Some(limit);
+            self.from_headers = true;
+        }
+        if let (Some(limit), Some(remaining)) = (self.limit, remaining) {
+            self.level = (remaining - self.in_flight).clamp(0.0, limit);
+        }
+    }
+
+    fn status(&self) -> Option<BucketLevel> {
+        self.limit.map(|limit| BucketLevel {
+            limit: limit as u64,
+            available: self.level.max(0.0) as u64,
+            from_headers: self.from_headers,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    requests: Bucket,
+    tokens: Bucket,
+    refilled: Instant,
+}
+
+impl Buckets {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        self.requests.refill(now - self.refilled);
+        self.tokens.refill(now - self.refilled);
+        self.refilled = now;
+    }
+}
+
+/// Buckets and queue of one host
+#[derive(Debug)]
+struct Lane {
+    buckets: Mutex<Buckets>,
+    /// Held by the request at the head of the queue; tokio's mutex grants it in FIFO order
+    turn: tokio::sync::Mutex<()>,
+    queued: AtomicUsize,
+}
+
+impl Lane {
+    fn new(config: Option<&RateLimitConfig>) -> Self {
+        let mut buckets = Buckets {
+            requests: Bucket::default(),
+            tokens: Bucket::default(),
+            refilled: Instant::now(),
+        };
+        if let Some(config) = config {
+            buckets.requests.configure(config.requests_per_minute);
+            buckets.tokens.configure(config.tokens_per_minute);
+        }
+        Self {
+            buckets: Mutex::new(buckets),
+            turn: tokio::sync::Mutex::new(()),
+            queued: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// Counts a request as queued until it gets through or is dropped
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Capacity a request took; it counts as in flight until its response is observed or it's dropped
+#[must_use]
+pub struct Permit {
+    lane: Arc<Lane>,
+    tokens: f64,
+    settled: bool,
+}
+
+impl Permit {
+    fn settle(&mut self, buckets: &mut Buckets) {
+        if !self.settled {
+            buckets.requests.settle(1.0);
+            buckets.tokens.settle(self.tokens);
+            self.settled = true;
+        }
+    }
+
+    /// Adopt the limits and remaining capacity the response reports. The provider counted this
+    /// request already, so only the estimates of the others in flight come off the remaining counts.
+    pub fn observe(mut self, headers: &HeaderMap) {
+        let number = |names: [&str; 2]| {
+            names
+                .iter()
+                .find_map(|name| headers.get(*name)?.to_str().ok()?.trim().parse::<f64>().ok())
+        };
+        let requests = (
+            number(["x-ratelimit-limit-requests", "anthropic-ratelimit-requests-limit"]),
+            number(["x-ratelimit-remaining-requests", "anthropic-ratelimit-requests-remaining"]),
+        );
+        let tokens = (
+            number(["x-ratelimit-limit-tokens", "anthropic-ratelimit-tokens-limit"]),
+            number(["x-ratelimit-remaining-tokens", "anthropic-ratelimit-tokens-remaining"]),
+        );
+
+        let lane = self.lane.clone();
+        let mut buckets = lane.buckets.lock().unwrap();
+        self.settle(&mut buckets);
+        if requests == (None, None) && tokens == (None, None) {
+            return;
+        }
+        buckets.refill();
+        buckets.requests.learn(requests.0, requests.1);
+        buckets.tokens.learn(tokens.0, tokens.1);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let lane = self.lane.clone();
+        let mut buckets = lane.buckets.lock().unwrap();
+        self.settle(&mut buckets);
+    }
+}
+
+/// Buckets per host, held in Tauri managed state
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    lanes: Mutex<HashMap<String, Arc<Lane>>>,
+    configs: Mutex<HashMap<String, RateLimitConfig>>,
+}
+
+impl RateLimiter {
+    /// Replace the limits from the settings; hosts left out are no longer limited unless their provider reports limits
+    pub fn configure(&self, configs: Vec<RateLimitConfig>) {
+        let configs: HashMap<_, _> = configs
+            .into_iter()
+            .map(|config| (config.host.trim().to_ascii_lowercase(), config))
+            .collect();
+        for (host, lane) in self.lanes.lock().unwrap().iter() {
+            let config = configs.get(host);
+            let mut buckets = lane.buckets.lock().unwrap();
+            buckets.refill();
+            buckets.requests.configure(config.and_then(|c| c.requests_per_minute));
+            buckets.tokens.configure(config.and_then(|c| c.tokens_per_minute));
+        }
+        *self.configs.lock().unwrap() = configs;
+    }
+
+    fn lane(&self, host: &str) -> Arc<Lane> {
+        self.lanes
+            .lock()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Lane::new(self.configs.lock().unwrap().get(host))))
+            .clone()
+    }
+
+    /// Wait until the host of `url` has capacity for one more request of `tokens`, behind any already waiting
+    pub async fn acquire(&self, url: &str, tokens: u64) -> Permit {
+        let lane = self.lane(&host_key(url));
+        lane.queued.fetch_add(1, Ordering::Relaxed);
+        let _waiting = Waiting(&lane.queued);
+        let _turn = lane.turn.lock().await;
+
+        loop {
+            let wait = {
+                let mut buckets = lane.buckets.lock().unwrap();
+                buckets.refill();
+                let wait = buckets.requests.wait(1.0).max(buckets.tokens.wait(tokens as f64));
+                if wait.is_zero() {
+                    buckets.requests.take(1.0);
+                    buckets.tokens.take(tokens as f64);
+                    break;
+                }
+                wait
+            };
+            tokio::time::sleep(wait.min(RECHECK)).await;
+        }
+        Permit {
+            lane: lane.clone(),
+            tokens: tokens as f64,
+            settled: false,
+        }
+    }
+
+    /// Bucket levels of every host seen so far, sorted by host
+    pub fn status(&self) -> Vec<LaneStatus> {
+        let mut status: Vec<_> = self
+            .lanes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(host, lane)| {
+                let mut buckets = lane.buckets.lock().unwrap();
+                buckets.refill();
+                LaneStatus {
+                    host: host.clone(),
+                    requests: buckets.requests.status(),
+                    tokens: buckets.tokens.status(),
+                    queued: lane.queued.load(Ordering::Relaxed),
+                }
+            })
+            .collect();
+        status.sort_by(|a, b| a.host.cmp(&b.host));
+        status
+    }
+}
+
+/// Rough token count of a request: about four bytes of body per input token, plus the
+/// output budget it asks for, which is how providers count a request before it runs
+pub fn estimate_tokens(body: Option<&str>) -> u64 {
+    let Some(body) = body else {
+        return 0;
+    };
+    let input = body.len().div_ceil(4) as u64;
+    let output = serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|json| {
+            ["max_tokens", "max_output_tokens", "max_completion_tokens"]
+                .iter()
+                .find_map(|field| json.get(*field))
+                .or_else(|| json.pointer("/generationConfig/maxOutputTokens"))
+                .and_then(Value::as_u64)
+        })
+        .unwrap_or(0);
+    input + output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    const URL: &str = "https://api.anthropic.com/v1/messages";
+
+    fn limits(requests_per_minute: Option<u32>, tokens_per_minute: Option<u32>) -> Vec<RateLimitConfig> {
+        vec![RateLimitConfig {
+            host: "API.anthropic.com".to_string(),
+            requests_per_minute,
+            tokens_per_minute,
+        }]
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queues_requests_until_buckets_refill() {
+        let limiter = Arc::new(RateLimiter::default());
+        limiter.configure(limits(Some(2), Some(6000)));
+
+        let started = Instant::now();
+        drop(limiter.acquire(URL, 100).await);
+        drop(limiter.acquire(URL, 100).await);
+        assert_eq!(started.elapsed(), Duration::ZERO);
+
+        // The third request waits half a minute for one request's worth of refill
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { drop(limiter.acquire(URL, 100).await) }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(limiter.status()[0].queued, 1);
+        waiting.await.unwrap();
+        assert_eq!(started.elapsed().as_millis(), 30_000);
+        assert_eq!(limiter.status()[0].queued, 0);
+
+        // Requests over the token limit take a full bucket instead of waiting forever
+        drop(limiter.acquire(URL, 10_000).await);
+        assert_eq!(
+            limiter.status()[0].tokens,
+            Some(BucketLevel {
+                limit: 6000,
+                available: 0,
+                from_headers: false,
+            })
+        );
+
+        // Other hosts have no limits
+        let started = Instant::now();
+        for _ in 0..10 {
+            drop(limiter.acquire("https://api.openai.com/v1/responses", 100_000).await);
+        }
+        assert_eq!(started.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn adopts_limits_from_response_headers() {
+        let limiter = RateLimiter::default();
+        limiter.configure(limits(Some(50), None));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-ratelimit-requests-limit", HeaderValue::from_static("1000"));
+        headers.insert("anthropic-ratelimit-requests-remaining", HeaderValue::from_static("999"));
+        headers.insert("anthropic-ratelimit-tokens-limit", HeaderValue::from_static("80000"));
+        headers.insert("anthropic-ratelimit-tokens-remaining", HeaderValue::from_static("12000"));
+        limiter.acquire(URL, 0).await.observe(&headers);
+
+        let status = &limiter.status()[0];
+        assert_eq!(status.host, "api.anthropic.com");
+        assert_eq!(
+            status.requests,
+            Some(BucketLevel {
+                limit: 1000,
+                available: 999,
+                from_headers: true,
+            })
+        );
+        assert_eq!(status.tokens.map(|t| (t.limit, t.available)), Some((80000, 12000)));
+
+        // Settings no longer override what the provider reported
+        limiter.configure(limits(Some(10), Some(10)));
+        assert_eq!(limiter.status()[0].requests.map(|r| r.limit), Some(1000));
+
+        let mut openai = HeaderMap::new();
+        openai.insert("x-ratelimit-limit-requests", HeaderValue::from_static("500"));
+        openai.insert("x-ratelimit-remaining-tokens", HeaderValue::from_static("100"));
+        limiter.acquire("https://api.openai.com/v1/responses", 0).await.observe(&openai);
+        let status = &limiter.status()[1];
+        assert_eq!(status.requests.map(|r| r.limit), Some(500));
+        // Remaining counts mean nothing without a limit to refill towards
+        assert_eq!(status.tokens, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_requests_in_flight_off_reported_capacity() {
+        let limiter = RateLimiter::default();
+        limiter.configure(limits(None, Some(10_000)));
+        let first = limiter.acquire(URL, 1000).await;
+        let second = limiter.acquire(URL, 3000).await;
+        let third = limiter.acquire(URL, 500).await;
+        assert_eq!(limiter.status()[0].tokens.map(|t| t.available), Some(5500));
+
+        // The first response counts itself but not the two still waiting for theirs
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-ratelimit-tokens-limit", HeaderValue::from_static("10000"));
+        headers.insert("anthropic-ratelimit-tokens-remaining", HeaderValue::from_static("9000"));
+        first.observe(&headers);
+        assert_eq!(limiter.status()[0].tokens.map(|t| t.available), Some(5500));
+
+        // A request that gave up no longer counts against later reports
+        drop(third);
+        headers.insert("anthropic-ratelimit-tokens-remaining", HeaderValue::from_static("6000"));
+        second.observe(&headers);
+        assert_eq!(limiter.status()[0].tokens.map(|t| t.available), Some(6000));
+    }
+
+    #[test]
+    fn estimates_input_and_requested_output() {
+        assert_eq!(estimate_tokens(None), 0);
+        assert_eq!(estimate_tokens(Some("abcdefgh")), 2);
+        let body = r#"{"max_tokens":1000,"messages":[]}"#;
+        assert_eq!(estimate_tokens(Some(body)), 1000 + body.len().div_ceil(4) as u64);
+        let gemini = r#"{"generationConfig":{"maxOutputTokens":64}}"#;
+        assert_eq!(estimate_tokens(Some(gemini)), 64 + gemini.len().div_ceil(4) as u64);
+    }
+}
@@ -0,0 +1,258 @@
+//! Retry policy for rate-limited and overloaded provider responses
+//!
+//! Retries happen before any response data reaches the frontend, so a stream is
+//! either retried transparently or started exactly once. A POST that failed with a
+//! 5xx may already have run on the server, so only statuses that mean the request
+//! was turned away are retried for methods that aren't idempotent, unless the policy
+//! opts in.
+
+use rand::Rng;
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_BASE_DELAY_MS: u64 = 1000;
+const DEFAULT_MAX_DELAY_MS: u64 = 30000;
+const DEFAULT_JITTER: f64 = 0.2;
+
+/// Retry configuration from frontend; every field falls back to a default
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RetryPolicy {
+    /// Total attempts including the first one
+    pub max_attempts: Option<u32>,
+    pub base_delay_ms: Option<u64>,
+    pub max_delay_ms: Option<u64>,
+    /// Random spread applied to each delay, as a fraction (0.2 = ±20%)
+    pub jitter: Option<f64>,
+    /// Wait as long as the provider's `Retry-After` asks, up to `max_delay_ms`
+    pub respect_retry_after: Option<bool>,
+    /// Also retry 500, 502 and 504 for POST and PATCH, which may repeat work the server already did
+    pub retry_non_idempotent: Option<bool>,
+}
+
+/// Progress event emitted before waiting for the next attempt
+#[derive(Debug, Clone, Serialize)]
+pub struct RetryNotice {
+    pub request_id: String,
+    /// The attempt about to be made, starting at 2
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay_ms: u64,
+    pub status: u16,
+}
+
+impl RetryPolicy {
+    fn max_attempts(&self) -> u32 {
+        self.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1)
+    }
+
+    fn max_delay(&self) -> Duration {
+        Duration::from_millis(self.max_delay_ms.unwrap_or(DEFAULT_MAX_DELAY_MS))
+    }
+
+    /// Delay before retry number `retry` (1-based), before jitter
+    fn backoff(&self, retry: u32) -> Duration {
+        let base = self.base_delay_ms.unwrap_or(DEFAULT_BASE_DELAY_MS);
+        let factor = 2u64.saturating_pow(retry.saturating_sub(1));
+        Duration::from_millis(base.saturating_mul(factor)).min(self.max_delay())
+    }
+
+    /// How long to wait after `response_headers` before retry number `retry`
+    fn delay(&self, retry: u32, response_headers: &HeaderMap) -> Duration {
+        if self.respect_retry_after.unwrap_or(true) {
+            if let Some(delay) = retry_after(response_headers) {
+                return delay.min(self.max_delay());
+            }
+        }
+
+        let delay = self.backoff(retry);
+        let jitter = self.jitter.unwrap_or(DEFAULT_JITTER).clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        let spread = rand::thread_rng().gen_range(-jitter..=jitter);
+        delay.mul_f64(1.0 + spread).min(self.max_delay())
+    }
+}
+
+/// Whether a status is worth retrying: rate limits and server-side failures (incl. 529)
+pub fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Whether the server turned the request away without running it: rate limited, unavailable or overloaded
+fn is_rejected(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 429 | 503 | 529)
+}
+
+/// Whether a request with `method` that got `status` may be sent again under `policy`
+fn should_retry(policy: &RetryPolicy, method: &str, status: StatusCode) -> bool {
+    let idempotent = !matches!(method.to_ascii_uppercase().as_str(), "POST" | "PATCH");
+    is_retryable(status) && (idempotent || is_rejected(status) || policy.retry_non_idempotent.unwrap_or(false))
+}
+
+/// Parse `retry-after-ms`, or `Retry-After` as delta-seconds or an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| Some(headers.get(name)?.to_str().ok()?.trim());
+    let seconds = |value: &str| value.parse::<f64>().ok().filter(|n| *n >= 0.0 && n.is_finite());
+
+    if let Some(ms) = header("retry-after-ms").and_then(seconds) {
+        return Some(Duration::from_secs_f64(ms / 1000.0));
+    }
+    let value = header("retry-after")?;
+    if let Some(secs) = seconds(value) {
+        return Some(Duration::from_secs_f64(secs));
+    }
+    // A date in the past means retry now
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// Send `request`, retrying retryable statuses according to `policy`.
+///
+/// `on_retry` is called before each wait. The final response is returned as-is,
+/// whatever its status, once it is not retryable or attempts run out.
+pub async fn send_with_retry(
+    request: RequestBuilder,
+    method: &str,
+    policy: Option<&RetryPolicy>,
+    request_id: &str,
+    on_retry: impl Fn(RetryNotice),
+) -> Result<Response, reqwest::Error> {
+    let Some(policy) = policy else {
+        return request.send().await;
+    };
+    let max_attempts = policy.max_attempts();
+
+    let mut attempt = 1;
+    loop {
+        // Streaming bodies can't be replayed; send those once
+        let Some(next) = request.try_clone() else {
+            return request.send().await;
+        };
+
+        let response = next.send().await?;
+        if attempt >= max_attempts || !should_retry(policy, method, response.status()) {
+            return Ok(response);
+        }
+
+        let delay = policy.delay(attempt, response.headers());
+        on_retry(RetryNotice {
+            request_id: request_id.to_string(),
+            attempt: attempt + 1,
+            max_attempts,
+            delay_ms: delay.as_millis() as u64,
+            status: response.status().as_u16(),
+        });
+
+        drop(response);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            base_delay_ms: Some(500),
+            max_delay_ms: Some(4000),
+            jitter: Some(0.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_max() {
+        let policy = policy();
+        let headers = HeaderMap::new();
+        let delays: Vec<u64> = (1..=5)
+            .map(|retry| policy.delay(retry, &headers).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![500, 1000, 2000, 4000, 4000]);
+    }
+
+    #[test]
+    fn honors_retry_after_headers() {
+        let policy = policy();
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "2".parse().unwrap());
+        assert_eq!(policy.delay(1, &headers), Duration::from_secs(2));
+
+        headers.insert("retry-after-ms", "250".parse().unwrap());
+        assert_eq!(policy.delay(1, &headers), Duration::from_millis(250));
+
+        headers.clear();
+        headers.insert("retry-after", "600".parse().unwrap());
+        assert_eq!(policy.delay(1, &headers), Duration::from_millis(4000));
+
+        let ignoring = RetryPolicy {
+            respect_retry_after: Some(false),
+            ..policy
+        };
+        assert_eq!(ignoring.delay(1, &headers), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn honors_retry_after_dates() {
+        let policy = RetryPolicy {
+            max_delay_ms: Some(60_000),
+            ..policy()
+        };
+        let mut headers = HeaderMap::new();
+        let at = SystemTime::now() + Duration::from_secs(10);
+        headers.insert("retry-after", httpdate::fmt_http_date(at).parse().unwrap());
+        let delay = policy.delay(1, &headers);
+        assert!((Duration::from_secs(8)..=Duration::from_secs(10)).contains(&delay), "{delay:?}");
+
+        headers.insert("retry-after", "Sun, 06 Nov 1994 08:49:37 GMT".parse().unwrap());
+        assert_eq!(policy.delay(1, &headers), Duration::ZERO);
+
+        headers.insert("retry-after", "soon".parse().unwrap());
+        assert_eq!(policy.delay(1, &headers), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn retries_server_errors_for_posts_only_when_asked() {
+        let status = |code| StatusCode::from_u16(code).unwrap();
+        let default = policy();
+        assert!(should_retry(&default, "get", status(500)));
+        assert!(should_retry(&default, "POST", status(429)));
+        assert!(should_retry(&default, "POST", status(529)));
+        assert!(!should_retry(&default, "POST", status(500)));
+        assert!(!should_retry(&default, "PATCH", status(502)));
+        assert!(!should_retry(&default, "GET", status(404)));
+
+        let opted_in = RetryPolicy {
+            retry_non_idempotent: Some(true),
+            ..policy()
+        };
+        assert!(should_retry(&opted_in, "POST", status(500)));
+        assert!(!should_retry(&opted_in, "POST", status(400)));
+    }
+
+    #[test]
+    fn jitter_stays_within_spread() {
+        let policy = RetryPolicy {
+            jitter: Some(0.5),
+            ..policy()
+        };
+        for _ in 0..100 {
+            let delay = policy.delay(2, &HeaderMap::new()).as_millis();
+            assert!((500..=1500).contains(&delay), "{delay}");
+        }
+    }
+
+    #[test]
+    fn retries_rate_limits_and_server_errors_only() {
+        assert!(is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable(StatusCode::from_u16(529).unwrap()));
+        assert!(is_retryable(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable(StatusCode::BAD_REQUEST));
+        assert!(!is_retryable(StatusCode::UNAUTHORIZED));
+    }
+}
@@ -0,0 +1,250 @@
+//! Per-host proxy rules
+//!
+//! One global proxy doesn't fit every destination: provider APIs may need a SOCKS tunnel
+//! while a local Ollama or a LAN MCP server must be reached directly. The `rules` of a
+//! `ProxyConfig` are checked in order for every request and the first match picks the
+//! proxy; the global settings apply when none matches.
+
+use super::{HttpError, ProxyConfig};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use url::Host;
+
+/// Route for destinations matching `pattern`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ProxyRule {
+    /// Host glob such as `*.openai.com` or `api.*.com`, or a CIDR range such as `192.168.0.0/16`
+    #[serde(rename = "match")]
+    pub pattern: String,
+    /// Proxy for matching hosts, whose own rules are ignored; a direct connection when absent
+    pub proxy: Option<ProxyConfig>,
+}
+
+/// Route a URL takes, as returned by `proxy_resolve`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProxyRoute {
+    /// Pattern of the rule that matched; absent when the global settings apply
+    pub rule: Option<String>,
+    /// `direct`, or the proxy type: `http`, `socks5`, `system`, `pac`, ...
+    #[serde(rename = "type")]
+    pub route_type: String,
+    /// Proxy address without credentials, or the PAC script location
+    pub proxy: Option<String>,
+}
+
+impl ProxyRoute {
+    pub(super) fn new(rule: Option<String>, proxy: Option<&ProxyConfig>) -> Self {
+        let (route_type, address) = match proxy.filter(|p| p.is_enabled()) {
+            None => ("direct".to_string(), None),
+            Some(p) if p.is_system() => (p.proxy_type.clone(), None),
+            Some(p) if p.is_pac() => (p.proxy_type.clone(), p.pac_url.clone()),
+            Some(p) => (p.proxy_type.clone(), Some(format!("{}://{}:{}", p.proxy_type, p.host, p.port))),
+        };
+        Self {
+            rule,
+            route_type,
+            proxy: address,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HostPattern {
+    /// Lowercase glob where `*` matches any run of characters and `?` a single one
+    Glob(String),
+    /// Matches IP literal hosts inside the range
+    Cidr(IpAddr, u8),
+}
+
+impl HostPattern {
+    fn parse(pattern: &str) -> Result<Self, HttpError> {
+        let pattern = pattern.trim().to_ascii_lowercase();
+        let invalid = || HttpError::InvalidConfig(format!("Invalid proxy rule: '{}'", pattern));
+
+        if let Some((addr, prefix)) = pattern.split_once('/') {
+            let addr: IpAddr = addr.trim_matches(['[', ']']).parse().map_err(|_| invalid())?;
+            let max = if addr.is_ipv4() { 32 } else { 128 };
+            let prefix = prefix.parse::<u8>().ok().filter(|p| *p <= max).ok_or_else(invalid)?;
+            return Ok(Self::Cidr(addr, prefix));
+        }
+
+        let glob = pattern.trim_matches(['[', ']']);
+        if glob.is_empty() || (glob.contains([':', '/']) && glob.parse::<IpAddr>().is_err()) {
+            return Err(invalid());
+        }
+        Ok(Self::Glob(glob.to_string()))
+    }
+
+    fn matches(&self, host: &Host<&str>) -> bool {
+        match (self, host) {
+            (Self::Glob(glob), Host::Domain(domain)) => glob_matches(glob, &domain.to_ascii_lowercase()),
+            (Self::Glob(glob), Host::Ipv4(ip)) => glob_matches(glob, &ip.to_string()),
+            (Self::Glob(glob), Host::Ipv6(ip)) => glob_matches(glob, &ip.to_string()),
+            (Self::Cidr(net, prefix), Host::Ipv4(ip)) => in_cidr(IpAddr::V4(*ip), *net, *prefix),
+            (Self::Cidr(net, prefix), Host::Ipv6(ip)) => in_cidr(IpAddr::V6(*ip), *net, *prefix),
+            (Self::Cidr(..), Host::Domain(_)) => false,
+        }
+    }
+}
+
+fn glob_matches(glob: &str, text: &str) -> bool {
+    let (glob, text) = (glob.as_bytes(), text.as_bytes());
+    let (mut g, mut t) = (0, 0);
+    // Position of the last `*` and where in `text` it currently stops matching
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if g < glob.len() && (glob[g] == b'?' || glob[g] == text[t]) {
+            g += 1;
+            t += 1;
+        } else if g < glob.len() && glob[g] == b'*' {
+            star = Some((g, t));
+            g += 1;
+        } else if let Some((star_g, star_t)) = star {
+            // Let the last `*` swallow one more character and retry
+            g = star_g + 1;
+            t = star_t + 1;
+            star = Some((star_g, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    glob[g..].iter().all(|&c| c == b'*')
+}
+
+fn in_cidr(ip: IpAddr, net: IpAddr, prefix: u8) -> bool {
+    match (ip, net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Proxy to use for `url` and the pattern of the rule that picked it.
+/// The proxy comes without rules; `None` means a direct connection.
+pub fn resolve(proxy: Option<&ProxyConfig>, url: &str) -> Result<(Option<String>, Option<ProxyConfig>), HttpError> {
+    let Some(proxy) = proxy else {
+        return Ok((None, None));
+    };
+    let rules = proxy
+        .rules
+        .iter()
+        .map(|rule| Ok((rule, HostPattern::parse(&rule.pattern)?)))
+        .collect::<Result<Vec<_>, HttpError>>()?;
+    if rules.is_empty() {
+        return Ok((None, effective(Some(proxy))));
+    }
+
+    let url = Url::parse(url).map_err(|e| HttpError::InvalidConfig(format!("Invalid URL '{}': {}", url, e)))?;
+    let host = url.host();
+    let matched = rules
+        .iter()
+        .find(|(_, pattern)| host.as_ref().is_some_and(|host| pattern.matches(host)));
+
+    Ok(match matched {
+        Some((rule, _)) => (Some(rule.pattern.clone()), effective(rule.proxy.as_ref())),
+        None => (None, effective(Some(proxy))),
+    })
+}
+
+/// Describe the route `url` takes, for `proxy_resolve`
+pub fn preview(proxy: Option<&ProxyConfig>, url: &str) -> Result<ProxyRoute, HttpError> {
+    let (rule, proxy) = resolve(proxy, url)?;
+    Ok(ProxyRoute::new(rule, proxy.as_ref()))
+}
+
+/// Disabled or incomplete settings mean a direct connection, unless failover profiles follow
+fn effective(proxy: Option<&ProxyConfig>) -> Option<ProxyConfig> {
+    proxy.filter(|p| p.is_enabled() || !p.failover.is_empty()).map(|p| ProxyConfig {
+        rules: Vec::new(),
+        ..p.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(proxy_type: &str, host: &str, port: u16) -> ProxyConfig {
+        ProxyConfig {
+            proxy_type: proxy_type.to_string(),
+            host: host.to_string(),
+            port,
+            username: None,
+            password: None,
+            pac_url: None,
+            rules: Vec::new(),
+            failover: Vec::new(),
+        }
+    }
+
+    fn rule(pattern: &str, proxy: Option<ProxyConfig>) -> ProxyRule {
+        ProxyRule {
+            pattern: pattern.to_string(),
+            proxy,
+        }
+    }
+
+    #[test]
+    fn matches_globs_and_ranges() {
+        let glob = |p: &str, host: &str| {
+            let url = Url::parse(&format!("http://{}/", host)).unwrap();
+            HostPattern::parse(p).unwrap().matches(&url.host().unwrap())
+        };
+        assert!(glob("*.openai.com", "api.openai.com"));
+        assert!(!glob("*.openai.com", "openai.com"));
+        assert!(glob("api.*.com", "API.anthropic.com"));
+        assert!(glob("ollama-?", "ollama-1"));
+        assert!(!glob("ollama-?", "ollama-12"));
+        assert!(glob("192.168.1.10", "192.168.1.10"));
+        assert!(glob("192.168.0.0/16", "192.168.20.5"));
+        assert!(!glob("192.168.0.0/16", "192.169.0.1"));
+        assert!(glob("0.0.0.0/0", "8.8.8.8"));
+        assert!(glob("fd00::/8", "[fd12::1]"));
+        assert!(!glob("10.0.0.0/8", "ten.example"));
+
+        for invalid in ["", "10.0.0.0/33", "example.com/8", "http://example.com"] {
+            assert!(HostPattern::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let socks = proxy("socks5", "127.0.0.1", 1080);
+        let config = ProxyConfig {
+            rules: vec![
+                rule("*.anthropic.com", Some(socks.clone())),
+                rule("api.openai.com", Some(socks.clone())),
+                rule("192.168.0.0/16", None),
+                rule("*", Some(proxy("none", "", 0))),
+            ],
+            ..proxy("http", "proxy.corp", 3128)
+        };
+
+        let route = |url: &str| preview(Some(&config), url).unwrap();
+        assert_eq!(route("https://api.anthropic.com/v1/messages").proxy.as_deref(), Some("socks5://127.0.0.1:1080"));
+        assert_eq!(route("https://api.openai.com/v1").rule.as_deref(), Some("api.openai.com"));
+        assert_eq!(route("http://192.168.1.20:11434/api").route_type, "direct");
+        assert_eq!(route("https://example.org/").rule.as_deref(), Some("*"));
+        assert_eq!(route("https://example.org/").route_type, "direct");
+
+        let (_, picked) = resolve(Some(&config), "https://api.anthropic.com/").unwrap();
+        assert_eq!(picked, Some(socks));
+
+        // Without a catch-all the global proxy applies
+        let fallback = ProxyConfig {
+            rules: vec![rule("localhost", None)],
+            ..proxy("http", "proxy.corp", 3128)
+        };
+        let global = preview(Some(&fallback), "https://example.org/").unwrap();
+        assert_eq!((global.rule, global.proxy.as_deref()), (None, Some("http://proxy.corp:3128")));
+        assert_eq!(preview(None, "https://example.org/").unwrap().route_type, "direct");
+    }
+}
@@ -0,0 +1,474 @@
+//! Request scheduling
+//!
+//! Provider calls, oracle searches, MCP calls and bid evaluations all go through the HTTP
+//! commands, and bursts of background work used to delay the agent that is speaking. A
+//! request holds a slot from before it's sent until its body is read; slots are capped per
+//! host and globally, and waiting requests get them by priority. Within a priority the
+//! session with the fewest requests running goes first, so one busy session can't starve
+//! another, and requests of one session keep their order.
+
+use super::ratelimit::{Permit, RateLimiter};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// Scheduling class of a request, most urgent first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// The turn of the agent that is speaking
+    Speaking,
+    /// Bid evaluations, tool calls and anything else not marked
+    #[default]
+    Bidding,
+    /// Summaries and other work nobody waits on
+    Background,
+}
+
+/// Concurrency limits from frontend
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SchedulerConfig {
+    /// Requests in flight across all hosts
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent: usize,
+    /// Requests in flight per host, unless `hosts` sets another limit
+    #[serde(default = "default_max_per_host")]
+    pub max_per_host: usize,
+    /// Limits for particular hosts
+    #[serde(default)]
+    pub hosts: HashMap<String, usize>,
+}
+
+fn default_max_concurrent() -> usize {
+    12
+}
+
+fn default_max_per_host() -> usize {
+    6
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: default_max_concurrent(),
+            max_per_host: default_max_per_host(),
+            hosts: HashMap::new(),
+        }
+    }
+}
+
+impl SchedulerConfig {
+    fn host_limit(&self, host: &str) -> usize {
+        self.hosts.get(host).copied().unwrap_or(self.max_per_host).max(1)
+    }
+}
+
+/// Waiting request as reported by `scheduler_status`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueuedRequest {
+    pub request_id: Option<String>,
+    pub host: String,
+    pub priority: Priority,
+    pub session_id: Option<String>,
+    /// Place in the queue, 1 for next
+    pub position: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SchedulerStatus {
+    pub running: usize,
+    pub max_concurrent: usize,
+    /// Waiting requests, next first
+    pub queued: Vec<QueuedRequest>,
+}
+
+/// What a request is scheduled by
+#[derive(Debug, Clone)]
+pub struct Ticket {
+    pub request_id: Option<String>,
+    pub host: String,
+    pub priority: Priority,
+    pub session_id: Option<String>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    id: u64,
+    ticket: Ticket,
+    granted: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    config: SchedulerConfig,
+    waiting: Vec<Waiter>,
+    running: usize,
+    running_per_host: HashMap<String, usize>,
+    running_per_session: HashMap<String, usize>,
+    /// When each session last got a slot, by grant count
+    last_served: HashMap<String, u64>,
+    grants: u64,
+    next_id: u64,
+}
+
+impl State {
+    fn session(ticket: &Ticket) -> &str {
+        ticket.session_id.as_deref().unwrap_or_default()
+    }
+
+    /// Waiting order: priority, then the session with the fewest running and longest unserved, then arrival
+    fn order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.waiting.len()).filter(|i| !self.waiting[*i].granted).collect();
+        order.sort_by_key(|i| {
+            let waiter = &self.waiting[*i];
+            let session = Self::session(&waiter.ticket);
+            (
+                waiter.ticket.priority,
+                self.running_per_session.get(session).copied().unwrap_or(0),
+                self.last_served.get(session).copied().unwrap_or(0),
+                waiter.id,
+            )
+        });
+        order
+    }
+
+    /// Hand free slots to waiting requests, skipping those whose host is full
+    fn dispatch(&mut self) -> bool {
+        let mut granted = false;
+        while self.running < self.config.max_concurrent.max(1) {
+            let next = self.order().into_iter().find(|i| {
+                let host = &self.waiting[*i].ticket.host;
+                self.running_per_host.get(host).copied().unwrap_or(0) < self.config.host_limit(host)
+            });
+            let Some(index) = next else { break };
+
+            let waiter = &mut self.waiting[index];
+            waiter.granted = true;
+            let session = Self::session(&waiter.ticket).to_string();
+            self.running += 1;
+            *self.running_per_host.entry(waiter.ticket.host.clone()).or_default() += 1;
+            *self.running_per_session.entry(session.clone()).or_default() += 1;
+            self.grants += 1;
+            self.last_served.insert(session, self.grants);
+            granted = true;
+        }
+        granted
+    }
+
+    fn release(&mut self, ticket: &Ticket) {
+        self.running -= 1;
+        decrement(&mut self.running_per_host, &ticket.host);
+        decrement(&mut self.running_per_session, Self::session(ticket));
+    }
+
+    fn position(&self, id: u64) -> Option<usize> {
+        self.order().iter().position(|i| self.waiting[*i].id == id).map(|p| p + 1)
+    }
+}
+
+fn decrement(counts: &mut HashMap<String, usize>, key: &str) {
+    if let Some(count) = counts.get_mut(key) {
+        *count -= 1;
+        if *count == 0 {
+            counts.remove(key);
+        }
+    }
+}
+
+/// Concurrency limits and the queue of waiting requests, held in Tauri managed state
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    state: Mutex<State>,
+    /// Woken whenever slots are handed out or the queue changes
+    changed: Notify,
+}
+
+/// Slot of a running request, freed on drop
+#[derive(Debug)]
+pub struct Slot<'a> {
+    scheduler: &'a Scheduler,
+    ticket: Ticket,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.state.lock().unwrap();
+        state.release(&self.ticket);
+        state.dispatch();
+        self.scheduler.changed.notify_waiters();
+    }
+}
+
+/// Removes a request from the queue when it stops waiting before getting a slot, e.g. on cancel
+struct Waiting<'a> {
+    scheduler: &'a Scheduler,
+    id: u64,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.state.lock().unwrap();
+        let Some(index) = state.waiting.iter().position(|w| w.id == self.id) else {
+            return;
+        };
+        let waiter = state.waiting.remove(index);
+        if waiter.granted {
+            state.release(&waiter.ticket);
+        }
+        state.dispatch();
+        self.scheduler.changed.notify_waiters();
+    }
+}
+
+impl Scheduler {
+    /// Replace the concurrency limits; requests already running keep their slots
+    pub fn configure(&self, config: SchedulerConfig) {
+        let mut state = self.state.lock().unwrap();
+        state.config = config;
+        if state.dispatch() {
+            self.changed.notify_waiters();
+        }
+    }
+
+    /// Wait for a slot, reporting the place in the queue through `on_position` whenever it changes
+    pub async fn acquire(&self, ticket: Ticket, mut on_position: impl FnMut(usize)) -> Slot<'_> {
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            state.next_id += 1;
+            let id = state.next_id;
+            state.waiting.push(Waiter {
+                id,
+                ticket: ticket.clone(),
+                granted: false,
+            });
+            if state.dispatch() {
+                self.changed.notify_waiters();
+            }
+            id
+        };
+        let waiting = Waiting { scheduler: self, id };
+
+        let mut reported = None;
+        loop {
+            // Registered before checking, so a change between the check and the wait isn't missed
+            let changed = self.changed.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                let index = state.waiting.iter().position(|w| w.id == id).expect("removed only by its own guard");
+                if state.waiting[index].granted {
+                    state.waiting.remove(index);
+                    std::mem::forget(waiting);
+                    return Slot { scheduler: self, ticket };
+                }
+                let position = state.position(id);
+                if position != reported {
+                    reported = position;
+                    if let Some(position) = position {
+                        on_position(position);
+                    }
+                }
+            }
+            changed.await;
+        }
+    }
+
+    /// Admit a request to `url`: wait for its rate limit first and only then for a slot, so
+    /// requests to a throttled host sleep without holding slots other hosts could use
+    pub async fn admit(
+        &self,
+        limiter: &RateLimiter,
+        ticket: Ticket,
+        url: &str,
+        tokens: u64,
+        on_position: impl FnMut(usize),
+    ) -> (Slot<'_>, Permit) {
+        let permit = limiter.acquire(url, tokens).await;
+        (self.acquire(ticket, on_position).await, permit)
+    }
+
+    pub fn status(&self) -> SchedulerStatus {
+        let state = self.state.lock().unwrap();
+        let queued = state
+            .order()
+            .into_iter()
+            .enumerate()
+            .map(|(place, index)| {
+                let ticket = &state.waiting[index].ticket;
+                QueuedRequest {
+                    request_id: ticket.request_id.clone(),
+                    host: ticket.host.clone(),
+                    priority: ticket.priority,
+                    session_id: ticket.session_id.clone(),
+                    position: place + 1,
+                }
+            })
+            .collect();
+        SchedulerStatus {
+            running: state.running,
+            max_concurrent: state.config.max_concurrent,
+            queued,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::ratelimit::RateLimitConfig;
+    use std::sync::Arc;
+
+    fn ticket(name: &str, host: &str, priority: Priority, session: &str) -> Ticket {
+        Ticket {
+            request_id: Some(name.to_string()),
+            host: host.to_string(),
+            priority,
+            session_id: Some(session.to_string()),
+        }
+    }
+
+    fn scheduler(max_concurrent: usize, max_per_host: usize) -> Arc<Scheduler> {
+        let scheduler = Arc::new(Scheduler::default());
+        scheduler.configure(SchedulerConfig {
+            max_concurrent,
+            max_per_host,
+            hosts: HashMap::new(),
+        });
+        scheduler
+    }
+
+    /// Queue a request that records when it gets its slot and then holds it until released
+    fn queue(scheduler: &Arc<Scheduler>, ticket: Ticket, started: &Arc<Mutex<Vec<String>>>) -> Arc<Notify> {
+        let done = Arc::new(Notify::new());
+        let (scheduler, started, release) = (scheduler.clone(), started.clone(), done.clone());
+        tokio::spawn(async move {
+            let name = ticket.request_id.clone().unwrap();
+            let _slot = scheduler.acquire(ticket, |_| {}).await;
+            started.lock().unwrap().push(name);
+            release.notified().await;
+        });
+        done
+    }
+
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn grants_slots_by_priority_then_session_fairness() {
+        let scheduler = scheduler(1, 1);
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let host = "api.anthropic.com";
+
+        let first = queue(&scheduler, ticket("first", host, Priority::Bidding, "a"), &started);
+        settle().await;
+        let mut releases = vec![
+            queue(&scheduler, ticket("a-background", host, Priority::Background, "a"), &started),
+            queue(&scheduler, ticket("a-bid-1", host, Priority::Bidding, "a"), &started),
+            queue(&scheduler, ticket("a-bid-2", host, Priority::Bidding, "a"), &started),
+            queue(&scheduler, ticket("b-bid", host, Priority::Bidding, "b"), &started),
+            queue(&scheduler, ticket("b-speaking", host, Priority::Speaking, "b"), &started),
+        ];
+        settle().await;
+
+        let status = scheduler.status();
+        assert_eq!(status.running, 1);
+        let order: Vec<_> = status.queued.iter().map(|q| q.request_id.clone().unwrap()).collect();
+        assert_eq!(order, vec!["b-speaking", "b-bid", "a-bid-1", "a-bid-2", "a-background"]);
+
+        first.notify_one();
+        for release in releases.drain(..) {
+            settle().await;
+            release.notify_one();
+        }
+        settle().await;
+        assert_eq!(
+            *started.lock().unwrap(),
+            vec!["first", "b-speaking", "a-bid-1", "b-bid", "a-bid-2", "a-background"]
+        );
+    }
+
+    #[tokio::test]
+    async fn caps_each_host_without_blocking_others() {
+        let scheduler = scheduler(3, 1);
+        let started = Arc::new(Mutex::new(Vec::new()));
+
+        let _anthropic = queue(&scheduler, ticket("anthropic-1", "api.anthropic.com", Priority::Speaking, "a"), &started);
+        let _queued = queue(&scheduler, ticket("anthropic-2", "api.anthropic.com", Priority::Speaking, "a"), &started);
+        let _openai = queue(&scheduler, ticket("openai", "api.openai.com", Priority::Background, "a"), &started);
+        settle().await;
+
+        assert_eq!(*started.lock().unwrap(), vec!["anthropic-1", "openai"]);
+        let status = scheduler.status();
+        assert_eq!(status.running, 2);
+        assert_eq!(status.queued.len(), 1);
+        assert_eq!(status.queued[0].position, 1);
+    }
+
+    #[tokio::test]
+    async fn reports_position_and_leaves_queue_when_dropped() {
+        let scheduler = scheduler(1, 1);
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let running = queue(&scheduler, ticket("running", "h", Priority::Bidding, "a"), &started);
+        let _next = queue(&scheduler, ticket("next", "h", Priority::Speaking, "a"), &started);
+        settle().await;
+
+        let positions = Arc::new(Mutex::new(Vec::new()));
+        let waiter = tokio::spawn({
+            let (scheduler, positions) = (scheduler.clone(), positions.clone());
+            async move {
+                let _slot = scheduler
+                    .acquire(ticket("cancelled", "h", Priority::Bidding, "a"), |p| positions.lock().unwrap().push(p))
+                    .await;
+            }
+        });
+        settle().await;
+        assert_eq!(*positions.lock().unwrap(), vec![2]);
+
+        waiter.abort();
+        settle().await;
+        assert_eq!(scheduler.status().queued.len(), 1);
+
+        running.notify_one();
+        settle().await;
+        assert_eq!(*started.lock().unwrap(), vec!["running", "next"]);
+        assert_eq!(scheduler.status().running, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn throttled_hosts_wait_without_holding_slots() {
+        let scheduler = scheduler(1, 1);
+        let limiter = Arc::new(RateLimiter::default());
+        limiter.configure(vec![RateLimitConfig {
+            host: "slow.example".to_string(),
+            requests_per_minute: Some(1),
+            tokens_per_minute: None,
+        }]);
+        let slow = "https://slow.example/v1";
+        drop(scheduler.admit(&limiter, ticket("slow-1", "slow.example", Priority::Speaking, "a"), slow, 0, |_| {}).await);
+
+        // The next request to the throttled host waits a minute for its turn
+        let throttled = tokio::spawn({
+            let (scheduler, limiter) = (scheduler.clone(), limiter.clone());
+            async move {
+                let ticket = ticket("slow-2", "slow.example", Priority::Speaking, "a");
+                drop(scheduler.admit(&limiter, ticket, slow, 0, |_| {}).await);
+            }
+        });
+        settle().await;
+        assert!(!throttled.is_finished());
+
+        // Meanwhile the only slot is free for another host
+        let started = tokio::time::Instant::now();
+        let ticket = ticket("fast", "fast.example", Priority::Background, "b");
+        let (slot, permit) = scheduler.admit(&limiter, ticket, "https://fast.example/v1", 0, |_| {}).await;
+        assert_eq!(started.elapsed(), std::time::Duration::ZERO);
+        assert!(!throttled.is_finished());
+        drop((slot, permit));
+
+        throttled.await.unwrap();
+        assert_eq!(scheduler.status().running, 0);
+    }
+}
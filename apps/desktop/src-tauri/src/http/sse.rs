@@ -0,0 +1,192 @@
+//! Incremental Server-Sent Events parser
+//!
+//! Splits a text stream into SSE messages following the WHATWG event stream format,
+//! so the webview receives one structured event per message instead of raw bytes.
+
+use serde::Serialize;
+
+/// Data payload that providers send to mark the end of a stream
+const DONE_SENTINEL: &str = "[DONE]";
+
+/// A single dispatched SSE message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SseEvent {
+    /// Event type, `message` unless the server sent an `event:` field
+    pub event: String,
+    pub data: String,
+    /// Last event id seen on the stream, if any
+    pub id: Option<String>,
+    /// Reconnection time the server asked for with a `retry:` field, in ms
+    pub retry: Option<u64>,
+}
+
+impl SseEvent {
+    /// Whether this is the `[DONE]` sentinel used by OpenAI-compatible providers
+    pub fn is_done(&self) -> bool {
+        self.data.trim() == DONE_SENTINEL
+    }
+}
+
+/// Stateful parser fed with decoded text chunks
+#[derive(Debug, Default)]
+pub struct SseParser {
+    line: String,
+    /// The previous chunk ended in `\r`, so a leading `\n` belongs to that line break
+    skip_lf: bool,
+    /// Some text has been seen, so a byte order mark is no longer stripped
+    started: bool,
+    event: Option<String>,
+    data: Option<String>,
+    last_id: Option<String>,
+    retry: Option<u64>,
+}
+
+impl SseParser {
+    /// Feed a chunk of text and return every message completed by it
+    pub fn push(&mut self, text: &str) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        let mut text = text;
+        if !self.started && !text.is_empty() {
+            self.started = true;
+            text = text.strip_prefix('\u{FEFF}').unwrap_or(text);
+        }
+
+        for c in text.chars() {
+            if std::mem::take(&mut self.skip_lf) && c == '\n' {
+                continue;
+            }
+            match c {
+                '\r' => {
+                    self.skip_lf = true;
+                    self.end_line(&mut events);
+                }
+                '\n' => self.end_line(&mut events),
+                _ => self.line.push(c),
+            }
+        }
+
+        events
+    }
+
+    /// Dispatch whatever is left once the stream has ended without a trailing blank line
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let mut events = Vec::new();
+        if !self.line.is_empty() {
+            self.end_line(&mut events);
+        }
+        self.dispatch(&mut events);
+        events.pop()
+    }
+
+    fn end_line(&mut self, events: &mut Vec<SseEvent>) {
+        let line = std::mem::take(&mut self.line);
+        if line.is_empty() {
+            self.dispatch(events);
+            return;
+        }
+        if line.starts_with(':') {
+            return;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line.as_str(), ""),
+        };
+
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => {
+                let data = self.data.get_or_insert_with(String::new);
+                data.push_str(value);
+                data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.last_id = Some(value.to_string()),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                self.retry = value.parse().ok();
+            }
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self, events: &mut Vec<SseEvent>) {
+        let event = self.event.take();
+        let Some(mut data) = self.data.take() else {
+            return;
+        };
+        if data.ends_with('\n') {
+            data.pop();
+        }
+
+        events.push(SseEvent {
+            event: event
+                .filter(|e| !e.is_empty())
+                .unwrap_or_else(|| "message".to_string()),
+            data,
+            id: self.last_id.clone(),
+            retry: self.retry,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(chunks: &[&str]) -> Vec<SseEvent> {
+        let mut parser = SseParser::default();
+        let mut events: Vec<_> = chunks.iter().flat_map(|chunk| parser.push(chunk)).collect();
+        events.extend(parser.finish());
+        events
+    }
+
+    #[test]
+    fn joins_multi_line_data() {
+        let events = parse(&["data: first\ndata:second\ndata\n\n"]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "first\nsecond\n");
+        assert_eq!(events[0].event, "message");
+    }
+
+    #[test]
+    fn keeps_last_id_and_retry_across_events() {
+        let events = parse(&[
+            "event: delta\nid: 7\nretry: 1500\ndata: a\n\n",
+            "retry: soon\ndata: b\n\n",
+            "id\ndata: c\n\n",
+        ]);
+        let fields: Vec<_> = events.iter().map(|e| (e.event.as_str(), e.id.as_deref(), e.retry)).collect();
+        assert_eq!(
+            fields,
+            vec![("delta", Some("7"), Some(1500)), ("message", Some("7"), Some(1500)), ("message", Some(""), Some(1500))]
+        );
+    }
+
+    #[test]
+    fn handles_crlf_and_cr_split_across_chunks() {
+        let events = parse(&["data: a\r", "\n\r", "\ndata: b\r\rdata: c\n\n"]);
+        let data: Vec<_> = events.iter().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn ignores_comments_and_unknown_fields() {
+        let events = parse(&[": keep-alive\n\n", "foo: bar\n:\ndata: x\n\n"]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "x");
+    }
+
+    #[test]
+    fn strips_only_leading_byte_order_mark() {
+        let events = parse(&["\u{FEFF}", "data: a\n\ndata: \u{FEFF}b\n\n"]);
+        let data: Vec<_> = events.iter().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, vec!["a", "\u{FEFF}b"]);
+        assert_eq!(parse(&["\u{FEFF}data: a\n\n"])[0].data, "a");
+    }
+
+    #[test]
+    fn dispatches_unterminated_message_at_end() {
+        let events = parse(&["data: [DONE]"]);
+        assert_eq!(events.len(), 1);
+        assert!(events[0].is_done());
+    }
+}
@@ -0,0 +1,269 @@
+//! Replay buffers for streaming requests
+//!
+//! A stream outlives the webview listener that started it: if the page reloads
+//! mid-debate, the command keeps reading while nobody is listening. Every delivered
+//! chunk is numbered and kept per `request_id`, so a new listener can attach and
+//! replay whatever it missed.
+
+use super::{StreamChunk, StreamSink};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Per-stream replay budget; the oldest chunks are dropped beyond this
+const MAX_BUFFERED_BYTES: usize = 4 * 1024 * 1024;
+
+/// How long a finished stream stays attachable
+const RETAIN_FINISHED: Duration = Duration::from_secs(300);
+
+/// Summary of a buffered stream, as returned by `http_stream_list` and `http_stream_attach`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StreamInfo {
+    pub request_id: String,
+    pub url: String,
+    /// Unix time in ms
+    pub started_at: u64,
+    /// Oldest offset that can still be replayed
+    pub first_offset: u64,
+    /// Offset the next chunk will get
+    pub next_offset: u64,
+    pub done: bool,
+}
+
+struct StreamBuffer {
+    url: String,
+    started_at: u64,
+    sink: StreamSink,
+    chunks: VecDeque<StreamChunk>,
+    bytes: usize,
+    first_offset: u64,
+    next_offset: u64,
+    finished_at: Option<Instant>,
+}
+
+impl StreamBuffer {
+    fn info(&self, request_id: &str) -> StreamInfo {
+        StreamInfo {
+            request_id: request_id.to_string(),
+            url: self.url.clone(),
+            started_at: self.started_at,
+            first_offset: self.first_offset,
+            next_offset: self.next_offset,
+            done: self.finished_at.is_some(),
+        }
+    }
+
+    fn push(&mut self, chunk: StreamChunk) {
+        self.bytes += chunk_size(&chunk);
+        self.chunks.push_back(chunk);
+
+        // Always keep the latest chunk so a finished stream still reports how it ended
+        while self.bytes > MAX_BUFFERED_BYTES && self.chunks.len() > 1 {
+            if let Some(dropped) = self.chunks.pop_front() {
+                self.bytes -= chunk_size(&dropped);
+                self.first_offset = dropped.offset + 1;
+            }
+        }
+    }
+}
+
+fn chunk_size(chunk: &StreamChunk) -> usize {
+    let events = chunk.event.iter().chain(chunk.events.iter().flatten());
+    chunk.chunk.len() + events.map(|e| e.data.len()).sum::<usize>()
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Streams by `request_id`, held in Tauri managed state.
+/// Cloning is cheap and shares the registry, so delivery tasks can hold their own handle.
+///
+/// The map lock only guards lookups; each stream has its own lock, held while a chunk is
+/// sent so live chunks can't overtake a replay, without one slow listener stalling the others.
+#[derive(Clone, Default)]
+pub struct StreamRegistry {
+    streams: Arc<Mutex<HashMap<String, Arc<Mutex<StreamBuffer>>>>>,
+}
+
+impl StreamRegistry {
+    /// Start buffering a stream, delivering live chunks to `sink`
+    pub(super) fn start(&self, request_id: &str, url: &str, sink: StreamSink) {
+        let mut streams = self.streams.lock().unwrap();
+        purge(&mut streams);
+        streams.insert(
+            request_id.to_string(),
+            Arc::new(Mutex::new(StreamBuffer {
+                url: url.to_string(),
+                started_at: unix_ms(),
+                sink,
+                chunks: VecDeque::new(),
+                bytes: 0,
+                first_offset: 0,
+                next_offset: 0,
+                finished_at: None,
+            })),
+        );
+    }
+
+    fn get(&self, request_id: &str) -> Option<Arc<Mutex<StreamBuffer>>> {
+        self.streams.lock().unwrap().get(request_id).cloned()
+    }
+
+    /// Number a chunk, buffer it and deliver it to the current listener
+    pub(super) fn publish(&self, request_id: &str, mut chunk: StreamChunk) {
+        let Some(stream) = self.get(request_id) else {
+            return;
+        };
+        let mut stream = stream.lock().unwrap();
+
+        chunk.offset = stream.next_offset;
+        stream.next_offset += 1;
+        if chunk.done {
+            stream.finished_at = Some(Instant::now());
+        }
+
+        stream.sink.send(chunk.clone());
+        stream.push(chunk);
+    }
+
+    /// Replay buffered chunks from `from_offset` to `sink` and make it the stream's listener.
+    /// Returns `None` for unknown or expired streams.
+    pub(super) fn attach(&self, request_id: &str, from_offset: u64, sink: StreamSink) -> Option<StreamInfo> {
+        purge(&mut self.streams.lock().unwrap());
+        let stream = self.get(request_id)?;
+        let mut stream = stream.lock().unwrap();
+
+        for chunk in stream.chunks.iter().filter(|c| c.offset >= from_offset) {
+            sink.send(chunk.clone());
+        }
+        stream.sink = sink;
+
+        Some(stream.info(request_id))
+    }
+
+    /// Running streams and recently finished ones, oldest first
+    pub fn list(&self) -> Vec<StreamInfo> {
+        let streams: Vec<_> = {
+            let mut streams = self.streams.lock().unwrap();
+            purge(&mut streams);
+            streams.iter().map(|(id, stream)| (id.clone(), stream.clone())).collect()
+        };
+
+        let mut list: Vec<StreamInfo> = streams
+            .iter()
+            .map(|(id, stream)| stream.lock().unwrap().info(id))
+            .collect();
+        list.sort_by_key(|info| info.started_at);
+        list
+    }
+}
+
+/// Forget finished streams past their retention period; streams busy delivering are kept
+fn purge(streams: &mut HashMap<String, Arc<Mutex<StreamBuffer>>>) {
+    streams.retain(|_, stream| {
+        stream.try_lock().map_or(true, |stream| {
+            stream
+                .finished_at
+                .is_none_or(|finished| finished.elapsed() < RETAIN_FINISHED)
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri::ipc::{Channel, InvokeResponseBody};
+
+    /// Sink whose received chunks can be inspected
+    fn channel() -> (Arc<Mutex<Vec<serde_json::Value>>>, StreamSink) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        let channel = Channel::new(move |body| {
+            if let InvokeResponseBody::Json(json) = body {
+                log.lock().unwrap().push(serde_json::from_str(&json).unwrap());
+            }
+            Ok(())
+        });
+        (received, StreamSink::Channel(channel))
+    }
+
+    fn texts(received: &Mutex<Vec<serde_json::Value>>) -> Vec<String> {
+        received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|c| format!("{}:{}", c["offset"], c["chunk"].as_str().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn replays_missed_chunks_on_attach() {
+        let registry = StreamRegistry::default();
+        let (first, sink) = channel();
+        registry.start("req", "https://example.com", sink);
+
+        for part in ["a", "b", "c"] {
+            registry.publish("req", StreamChunk::data("req", part.to_string()));
+        }
+        assert_eq!(texts(&first), vec!["0:a", "1:b", "2:c"]);
+
+        // A reloaded page saw chunk 0 and asks for the rest
+        let (second, sink) = channel();
+        let info = registry.attach("req", 1, sink).unwrap();
+        assert_eq!((info.next_offset, info.done), (3, false));
+
+        registry.publish("req", StreamChunk::done("req"));
+        assert_eq!(texts(&second), vec!["1:b", "2:c", "3:"]);
+        assert_eq!(first.lock().unwrap().len(), 3);
+
+        assert!(registry.list()[0].done);
+        assert!(registry.attach("missing", 0, channel().1).is_none());
+    }
+
+    #[test]
+    fn slow_listener_does_not_block_other_streams() {
+        let registry = StreamRegistry::default();
+        let (entered, on_enter) = std::sync::mpsc::channel();
+        let (release, on_release) = std::sync::mpsc::channel::<()>();
+        let on_release = Mutex::new(on_release);
+        let stuck = Channel::new(move |_| {
+            let _ = entered.send(());
+            let _ = on_release.lock().unwrap().recv();
+            Ok(())
+        });
+        registry.start("slow", "https://example.com", StreamSink::Channel(stuck));
+
+        let publisher = {
+            let registry = registry.clone();
+            std::thread::spawn(move || registry.publish("slow", StreamChunk::data("slow", "a".to_string())))
+        };
+        on_enter.recv().unwrap();
+
+        let (received, sink) = channel();
+        registry.start("fast", "https://example.com", sink);
+        registry.publish("fast", StreamChunk::data("fast", "b".to_string()));
+        assert_eq!(texts(&received), vec!["0:b"]);
+
+        release.send(()).unwrap();
+        publisher.join().unwrap();
+    }
+
+    #[test]
+    fn drops_oldest_chunks_beyond_budget() {
+        let registry = StreamRegistry::default();
+        registry.start("req", "https://example.com", channel().1);
+
+        let large = "x".repeat(MAX_BUFFERED_BYTES / 2);
+        for _ in 0..4 {
+            registry.publish("req", StreamChunk::data("req", large.clone()));
+        }
+
+        let info = &registry.list()[0];
+        assert_eq!((info.first_offset, info.next_offset), (2, 4));
+    }
+}
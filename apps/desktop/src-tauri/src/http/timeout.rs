@@ -0,0 +1,119 @@
+//! Per-request deadlines
+//!
+//! A single overall timeout can't tell a stalled stream from a long but healthy one,
+//! so each phase of a request gets its own limit and reports which one expired.
+
+use serde::Serialize;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Which deadline expired
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutKind {
+    /// TCP/TLS/proxy connection was not established in time
+    Connect,
+    /// Response headers did not arrive in time
+    FirstByte,
+    /// No body data arrived for too long mid-stream
+    Idle,
+    /// The request as a whole ran past its deadline
+    Total,
+}
+
+impl TimeoutKind {
+    pub fn message(self) -> &'static str {
+        match self {
+            TimeoutKind::Connect => "Connection timed out (check proxy settings)",
+            TimeoutKind::FirstByte => "Timed out waiting for the response to start",
+            TimeoutKind::Idle => "Timed out waiting for more data",
+            TimeoutKind::Total => "Request timed out",
+        }
+    }
+}
+
+/// Deadlines for one request, created when it starts
+#[derive(Debug, Clone, Copy)]
+pub struct Deadlines {
+    first_byte: Option<Duration>,
+    idle: Option<Duration>,
+    total_at: Option<Instant>,
+}
+
+impl Deadlines {
+    pub fn new(total: Option<Duration>, first_byte: Option<Duration>, idle: Option<Duration>) -> Self {
+        Self {
+            first_byte,
+            idle,
+            total_at: total.map(|total| Instant::now() + total),
+        }
+    }
+
+    /// Wait for response headers under the first-byte and total deadlines
+    pub async fn first_byte<F: Future>(&self, future: F) -> Result<F::Output, TimeoutKind> {
+        self.limit(future, self.first_byte, TimeoutKind::FirstByte).await
+    }
+
+    /// Wait for the next body chunk under the idle and total deadlines
+    pub async fn next_chunk<F: Future>(&self, future: F) -> Result<F::Output, TimeoutKind> {
+        self.limit(future, self.idle, TimeoutKind::Idle).await
+    }
+
+    /// Wait under the total deadline only
+    pub async fn total<F: Future>(&self, future: F) -> Result<F::Output, TimeoutKind> {
+        self.limit(future, None, TimeoutKind::Total).await
+    }
+
+    async fn limit<F: Future>(
+        &self,
+        future: F,
+        step: Option<Duration>,
+        step_kind: TimeoutKind,
+    ) -> Result<F::Output, TimeoutKind> {
+        let step_at = step.map(|step| Instant::now() + step);
+
+        // Whichever deadline comes first decides the kind reported
+        let (deadline, kind) = match (step_at, self.total_at) {
+            (Some(step_at), Some(total_at)) if total_at <= step_at => (total_at, TimeoutKind::Total),
+            (Some(step_at), _) => (step_at, step_kind),
+            (None, Some(total_at)) => (total_at, TimeoutKind::Total),
+            (None, None) => return Ok(future.await),
+        };
+
+        tokio::time::timeout_at(deadline, future)
+            .await
+            .map_err(|_| kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Option<Duration> {
+        Some(Duration::from_millis(ms))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reports_the_deadline_that_expired() {
+        let slow = || tokio::time::sleep(Duration::from_secs(10));
+
+        let deadlines = Deadlines::new(ms(5000), ms(100), ms(200));
+        assert_eq!(deadlines.first_byte(slow()).await, Err(TimeoutKind::FirstByte));
+        assert_eq!(deadlines.next_chunk(slow()).await, Err(TimeoutKind::Idle));
+        assert_eq!(deadlines.total(slow()).await, Err(TimeoutKind::Total));
+
+        let deadlines = Deadlines::new(ms(50), ms(100), ms(200));
+        assert_eq!(deadlines.first_byte(slow()).await, Err(TimeoutKind::Total));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_deadline_resets_per_chunk() {
+        let deadlines = Deadlines::new(None, None, ms(100));
+        for _ in 0..10 {
+            let chunk = tokio::time::sleep(Duration::from_millis(80));
+            assert!(deadlines.next_chunk(chunk).await.is_ok());
+        }
+    }
+}
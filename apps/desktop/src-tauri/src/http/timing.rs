@@ -0,0 +1,375 @@
+//! Timing metrics for HTTP requests
+//!
+//! reqwest doesn't report where a request spent its time, so the pooled clients get a
+//! DNS resolver and a connector layer that time themselves and record into the request
+//! currently being sent, found through a task-local. Pooled connections skip both, which
+//! is how a reused connection shows up as missing DNS and connect times.
+//!
+//! The connector is opaque from the outside, so the TLS handshake is found from within:
+//! rustls asks its session store for a resumable session while building the ClientHello,
+//! and `TimedSessionStore` notes that moment as the start of the handshake.
+
+use super::egress::AddressGuard;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::Serialize;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_rustls::rustls::client::{
+    ClientSessionMemoryCache, ClientSessionStore, Tls12ClientSessionValue, Tls13ClientSessionValue,
+};
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::NamedGroup;
+use tower_layer::Layer;
+use tower_service::Service;
+
+tokio::task_local! {
+    static CONNECTION: Arc<ConnectionTiming>;
+}
+
+/// Where a request's time went, sent with `HttpResponse` and the final `StreamChunk`.
+/// Durations are in ms and measured from the start of the command unless noted.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TimingMetrics {
+    /// Before the first attempt was sent: scheduler and rate limit queueing, client lookup, secret resolution
+    pub queued_ms: u64,
+    /// Host name resolution; absent when a pooled connection was reused or the proxy resolves
+    pub dns_ms: Option<u64>,
+    /// Opening the connection up to the TLS handshake with the target, including DNS and any
+    /// proxy handshake; absent when reused
+    pub connect_ms: Option<u64>,
+    /// TLS handshake with the target; absent for plain HTTP and reused connections
+    pub tls_ms: Option<u64>,
+    /// Response headers received, including any retries
+    pub first_byte_ms: Option<u64>,
+    /// First body data received; the time to first token for streams
+    pub first_chunk_ms: Option<u64>,
+    /// Longest gap between consecutive body chunks
+    pub max_chunk_gap_ms: Option<u64>,
+    /// Average gap between consecutive body chunks
+    pub avg_chunk_gap_ms: Option<u64>,
+    pub total_ms: u64,
+    /// Body chunks received from the network
+    pub chunks: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+/// Connection phases recorded by the resolver and connector layer
+#[derive(Debug, Default)]
+struct ConnectionTiming {
+    dns: Mutex<Option<Duration>>,
+    connect: Mutex<Option<Duration>>,
+    tls: Mutex<Option<Duration>>,
+    /// Start of the latest TLS handshake; through an HTTPS proxy, the one with the target comes last
+    handshake: Mutex<Option<Instant>>,
+}
+
+impl ConnectionTiming {
+    /// Add to a phase; retries may open more than one connection
+    fn record(phase: &Mutex<Option<Duration>>, elapsed: Duration) {
+        *phase.lock().unwrap().get_or_insert(Duration::ZERO) += elapsed;
+    }
+
+    fn current() -> Option<Arc<Self>> {
+        CONNECTION.try_with(Arc::clone).ok()
+    }
+}
+
+/// Records the phases of one request
+#[derive(Debug)]
+pub struct Timer {
+    started: Instant,
+    connection: Arc<ConnectionTiming>,
+    /// Start of the first attempt; time spent on failover routes and retries after it counts
+    /// toward the response, not toward queueing
+    first_sent: Option<Instant>,
+    headers: Option<Instant>,
+    first_chunk: Option<Instant>,
+    last_chunk: Option<Instant>,
+    max_gap: Duration,
+    total_gap: Duration,
+    chunks: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+}
+
+impl Timer {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            connection: Arc::default(),
+            first_sent: None,
+            headers: None,
+            first_chunk: None,
+            last_chunk: None,
+            max_gap: Duration::ZERO,
+            total_gap: Duration::ZERO,
+            chunks: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+        }
+    }
+
+    /// Send a request, attributing any DNS lookup and connection it opens to this timer
+    pub async fn send<F: Future>(&mut self, bytes: u64, send: F) -> F::Output {
+        self.first_sent.get_or_insert_with(Instant::now);
+        self.bytes_sent = bytes;
+        let output = CONNECTION.scope(self.connection.clone(), send).await;
+        self.headers = Some(Instant::now());
+        output
+    }
+
+    /// Record a body chunk as it arrives, or a whole body read in one go
+    pub fn chunk(&mut self, bytes: usize) {
+        let now = Instant::now();
+        if let Some(last) = self.last_chunk {
+            let gap = now - last;
+            self.max_gap = self.max_gap.max(gap);
+            self.total_gap += gap;
+        }
+        self.first_chunk.get_or_insert(now);
+        self.last_chunk = Some(now);
+        self.chunks += 1;
+        self.bytes_received += bytes as u64;
+    }
+
+    pub fn finish(&self) -> TimingMetrics {
+        let ms = |d: Duration| d.as_millis() as u64;
+        let since_start = |at: Option<Instant>| at.map(|at| ms(at - self.started));
+        let gaps = self.chunks.saturating_sub(1);
+
+        TimingMetrics {
+            queued_ms: ms(self.first_sent.unwrap_or_else(Instant::now) - self.started),
+            dns_ms: self.connection.dns.lock().unwrap().map(ms),
+            connect_ms: self.connection.connect.lock().unwrap().map(ms),
+            tls_ms: self.connection.tls.lock().unwrap().map(ms),
+            first_byte_ms: since_start(self.headers),
+            first_chunk_ms: since_start(self.first_chunk),
+            max_chunk_gap_ms: (gaps > 0).then(|| ms(self.max_gap)),
+            avg_chunk_gap_ms: (gaps > 0).then(|| ms(self.total_gap / gaps as u32)),
+            total_ms: ms(self.started.elapsed()),
+            chunks: self.chunks,
+            bytes_sent: self.bytes_sent,
+            bytes_received: self.bytes_received,
+        }
+    }
+}
+
+/// System resolver that times each lookup and applies the egress address filter
+#[derive(Debug)]
+pub struct TimedResolver {
+    guard: Arc<AddressGuard>,
+    /// Proxy hosts of the client, which may well be local
+    proxies: Vec<String>,
+}
+
+impl TimedResolver {
+    pub fn new(guard: Arc<AddressGuard>, proxies: Vec<String>) -> Self {
+        let proxies = proxies.iter().map(|host| host.to_ascii_lowercase()).collect();
+        Self { guard, proxies }
+    }
+}
+
+impl Resolve for TimedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let timing = ConnectionTiming::current();
+        let host = name.as_str().to_ascii_lowercase();
+        let guard = (!self.proxies.contains(&host)).then(|| self.guard.clone());
+        Box::pin(async move {
+            let started = Instant::now();
+            let addrs = tokio::net::lookup_host((host.as_str(), 0)).await;
+            if let Some(timing) = timing {
+                ConnectionTiming::record(&timing.dns, started.elapsed());
+            }
+            let mut addrs: Vec<SocketAddr> = addrs?.collect();
+            if let Some(guard) = guard {
+                addrs = guard.filter(&host, addrs)?;
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Connector layer that times opening new connections
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimedConnectLayer;
+
+impl<S> Layer<S> for TimedConnectLayer {
+    type Service = TimedConnect<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimedConnect { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TimedConnect<S> {
+    inner: S,
+}
+
+impl<S, Request> Service<Request> for TimedConnect<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // Look up the request while still inside its task; the connection may finish elsewhere
+        let timing = ConnectionTiming::current();
+        let connecting = self.inner.call(request);
+        Box::pin(async move {
+            let started = Instant::now();
+            let result = connecting.await;
+            if let Some(timing) = timing {
+                match timing.handshake.lock().unwrap().take() {
+                    Some(handshake) => {
+                        ConnectionTiming::record(&timing.connect, handshake.saturating_duration_since(started));
+                        ConnectionTiming::record(&timing.tls, handshake.elapsed());
+                    }
+                    None => ConnectionTiming::record(&timing.connect, started.elapsed()),
+                }
+            }
+            result
+        })
+    }
+}
+
+/// TLS session cache that marks the start of each handshake for the request being sent
+#[derive(Debug)]
+pub struct TimedSessionStore {
+    inner: ClientSessionMemoryCache,
+}
+
+impl Default for TimedSessionStore {
+    fn default() -> Self {
+        Self {
+            inner: ClientSessionMemoryCache::new(256),
+        }
+    }
+}
+
+impl ClientSessionStore for TimedSessionStore {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.inner.set_kx_hint(server_name, group)
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.inner.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.inner.set_tls12_session(server_name, value)
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        self.inner.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.inner.remove_tls12_session(server_name)
+    }
+
+    fn insert_tls13_ticket(&self, server_name: ServerName<'static>, value: Tls13ClientSessionValue) {
+        self.inner.insert_tls13_ticket(server_name, value)
+    }
+
+    /// Called once per handshake, while the ClientHello is built
+    fn take_tls13_ticket(&self, server_name: &ServerName<'static>) -> Option<Tls13ClientSessionValue> {
+        if let Some(timing) = ConnectionTiming::current() {
+            *timing.handshake.lock().unwrap() = Some(Instant::now());
+        }
+        self.inner.take_tls13_ticket(server_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test(start_paused = true)]
+    async fn measures_chunk_gaps() {
+        let mut timer = Timer::start();
+        timer.send(10, tokio::time::sleep(Duration::from_millis(100))).await;
+        for gap in [0, 30, 90] {
+            tokio::time::sleep(Duration::from_millis(gap)).await;
+            timer.chunk(5);
+        }
+
+        let metrics = timer.finish();
+        assert_eq!(metrics.first_byte_ms, Some(100));
+        assert_eq!(metrics.first_chunk_ms, Some(100));
+        assert_eq!(metrics.max_chunk_gap_ms, Some(90));
+        assert_eq!(metrics.avg_chunk_gap_ms, Some(60));
+        assert_eq!((metrics.chunks, metrics.bytes_sent, metrics.bytes_received), (3, 10, 15));
+        assert_eq!(metrics.total_ms, 220);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn counts_queueing_only_before_the_first_attempt() {
+        let mut timer = Timer::start();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        // A failed route, then failover to the next one
+        timer.send(10, tokio::time::sleep(Duration::from_millis(100))).await;
+        timer.send(10, tokio::time::sleep(Duration::from_millis(50))).await;
+
+        let metrics = timer.finish();
+        assert_eq!(metrics.queued_ms, 40);
+        assert_eq!(metrics.first_byte_ms, Some(190));
+    }
+
+    #[tokio::test]
+    async fn reports_connect_only_for_new_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://localhost:{}/", listener.local_addr().unwrap().port());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            while let Ok(n) = socket.read(&mut buf).await {
+                if n == 0 {
+                    return;
+                }
+                let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                if socket.write_all(response.as_bytes()).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        let client = crate::http::build_client(None, None, None, &Default::default(), Arc::new(AddressGuard::new(true))).unwrap();
+        let mut first = Timer::start();
+        first.send(0, client.get(&url).send()).await.unwrap().text().await.unwrap();
+        let mut second = Timer::start();
+        second.send(0, client.get(&url).send()).await.unwrap().text().await.unwrap();
+
+        let (first, second) = (first.finish(), second.finish());
+        assert!(first.dns_ms.is_some() && first.connect_ms.is_some());
+        assert_eq!(first.tls_ms, None);
+        assert_eq!((second.dns_ms, second.connect_ms), (None, None));
+    }
+
+    #[tokio::test]
+    async fn resolver_refuses_private_addresses_outside_a_timer() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://localhost:{}/", listener.local_addr().unwrap().port());
+
+        // No timer in scope, as for a pooled reconnect or a redirect
+        let client = crate::http::build_client(None, None, None, &Default::default(), Arc::new(AddressGuard::default())).unwrap();
+        let error = client.get(&url).send().await.unwrap_err();
+        assert_eq!(crate::http::HttpError::from_reqwest(&error).kind(), "egress_denied");
+    }
+}
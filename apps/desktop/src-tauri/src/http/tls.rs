@@ -0,0 +1,627 @@
+//! TLS settings for corporate networks
+//!
+//! TLS-inspecting gateways re-sign every certificate with their own CA, which the system
+//! roots may not include, and some endpoints sit behind mutual TLS. Requests carrying a
+//! `TlsConfig` get a rustls client that also trusts the extra roots, presents the client
+//! identity and enforces the minimum version and any pin sets. `tls_inspect` shows the
+//! chain a server presents, e.g. to find out which CA a gateway signs with.
+
+use super::egress::EgressPolicy;
+use super::pinning::{self, PinSet, PinningVerifier};
+use super::timeout::TimeoutKind;
+use super::timing::TimedSessionStore;
+use super::{diagnostics, HttpError, ProxyConfig};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use p12_keystore::KeyStore;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::client::WebPkiServerVerifier;
+use tokio_rustls::rustls::crypto::{ring, CryptoProvider};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{self, DigitallySignedStruct, SignatureScheme, SupportedProtocolVersion};
+use tokio_rustls::TlsConnector;
+use x509_parser::extensions::GeneralName;
+
+/// Deadline for the handshake of `tls_inspect`
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+static TLS13_ONLY: &[&SupportedProtocolVersion] = &[&rustls::version::TLS13];
+
+/// TLS settings from frontend; files are read when a client is built, so call
+/// `http_reset_clients` after replacing them
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct TlsConfig {
+    /// PEM file of root certificates trusted in addition to the system roots
+    pub ca_file: Option<String>,
+    /// Client identity for mutual TLS: a PEM file with the certificate chain, or a PKCS#12 (.p12/.pfx) bundle
+    pub client_cert: Option<String>,
+    /// PEM private key, when it isn't in a PEM `client_cert` already
+    pub client_key: Option<String>,
+    /// Password of a PKCS#12 `client_cert`
+    pub client_cert_password: Option<String>,
+    /// Lowest accepted protocol version, `1.2` or `1.3`; 1.2 when absent
+    pub min_version: Option<String>,
+    /// Public keys hosts must present, filled in from the `PinStore`; never taken from the webview
+    #[serde(skip)]
+    pub pins: Vec<PinSet>,
+}
+
+impl TlsConfig {
+    /// Whether these settings change anything over the default TLS setup
+    pub fn is_custom(&self) -> bool {
+        *self != Self::default()
+    }
+}
+
+/// Certificate chain and verdict returned by `tls_inspect`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TlsReport {
+    pub url: String,
+    /// `TLS 1.3` or `TLS 1.2`
+    pub version: String,
+    pub cipher_suite: String,
+    /// Certificates in the order the server sent them, leaf first
+    pub chain: Vec<CertificateInfo>,
+    /// Whether the chain verified against the system roots and `ca_file`
+    pub trusted: bool,
+    /// Why verification failed
+    pub error: Option<String>,
+    /// Whether the chain carries a pinned key; absent when no pin set covers the host
+    pub pinned: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    /// Validity bounds as Unix timestamps in seconds
+    pub not_before: i64,
+    pub not_after: i64,
+    pub dns_names: Vec<String>,
+    pub is_ca: bool,
+    /// SHA-256 of the certificate as colon-separated hex
+    pub sha256: String,
+    /// Base64 SHA-256 of the subject public key info
+    pub spki_sha256: String,
+    /// PEM encoding, e.g. to save a gateway's CA as `ca_file`
+    pub pem: String,
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn invalid(message: String) -> HttpError {
+    HttpError::InvalidConfig(message)
+}
+
+/// rustls client for the settings: system roots plus `ca_file`, the client identity, the minimum version and pins
+pub fn client_config(tls: Option<&TlsConfig>) -> Result<rustls::ClientConfig, HttpError> {
+    let default = TlsConfig::default();
+    let tls = tls.unwrap_or(&default);
+    let roots = Arc::new(roots(tls)?);
+    let builder = rustls::ClientConfig::builder_with_provider(provider())
+        .with_protocol_versions(versions(tls.min_version.as_deref())?)
+        .map_err(|e| invalid(format!("Unsupported TLS settings: {}", e)))?;
+    let builder = if tls.pins.is_empty() {
+        builder.with_root_certificates(roots)
+    } else {
+        let verifier = PinningVerifier::new(webpki_verifier(&roots)?, roots, tls.pins.clone());
+        builder.dangerous().with_custom_certificate_verifier(Arc::new(verifier))
+    };
+    let mut config = with_identity(builder, tls)?;
+    config.resumption = rustls::client::Resumption::store(Arc::new(TimedSessionStore::default()));
+    Ok(config)
+}
+
+fn webpki_verifier(roots: &Arc<rustls::RootCertStore>) -> Result<Arc<WebPkiServerVerifier>, HttpError> {
+    WebPkiServerVerifier::builder_with_provider(roots.clone(), provider())
+        .build()
+        .map_err(|e| invalid(format!("Unusable root certificates: {}", e)))
+}
+
+fn with_identity(
+    builder: rustls::ConfigBuilder<rustls::ClientConfig, rustls::client::WantsClientCert>,
+    tls: &TlsConfig,
+) -> Result<rustls::ClientConfig, HttpError> {
+    match identity(tls)? {
+        Some((chain, key)) => builder
+            .with_client_auth_cert(chain, key)
+            .map_err(|e| invalid(format!("Invalid client certificate: {}", e))),
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+fn versions(min_version: Option<&str>) -> Result<&'static [&'static SupportedProtocolVersion], HttpError> {
+    match min_version.map(str::trim) {
+        None | Some("" | "1.2") => Ok(rustls::DEFAULT_VERSIONS),
+        Some("1.3") => Ok(TLS13_ONLY),
+        Some(other) => Err(invalid(format!("Minimum TLS version must be 1.2 or 1.3, not '{}'", other))),
+    }
+}
+
+fn read(path: &str, what: &str) -> Result<Vec<u8>, HttpError> {
+    std::fs::read(path).map_err(|e| invalid(format!("Could not read {} '{}': {}", what, path, e)))
+}
+
+fn read_certs(pem: &[u8], path: &str) -> Result<Vec<CertificateDer<'static>>, HttpError> {
+    let certs = CertificateDer::pem_slice_iter(pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(format!("Invalid PEM certificate in '{}': {}", path, e)))?;
+    if certs.is_empty() {
+        return Err(invalid(format!("No PEM certificates in '{}'", path)));
+    }
+    Ok(certs)
+}
+
+fn roots(tls: &TlsConfig) -> Result<rustls::RootCertStore, HttpError> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+
+    if let Some(path) = &tls.ca_file {
+        for cert in read_certs(&read(path, "CA file")?, path)? {
+            roots
+                .add(cert)
+                .map_err(|e| invalid(format!("Unusable CA certificate in '{}': {}", path, e)))?;
+        }
+    }
+    Ok(roots)
+}
+
+type Identity = (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>);
+
+/// Client certificate chain and key, from PEM files or a PKCS#12 bundle
+fn identity(tls: &TlsConfig) -> Result<Option<Identity>, HttpError> {
+    let Some(path) = &tls.client_cert else {
+        return Ok(None);
+    };
+    let data = read(path, "client certificate")?;
+
+    if !data.windows(10).any(|w| w == b"-----BEGIN") {
+        let password = tls.client_cert_password.as_deref().unwrap_or_default();
+        let store = KeyStore::from_pkcs12(&data, password)
+            .map_err(|e| invalid(format!("Could not open PKCS#12 bundle '{}': {}", path, e)))?;
+        let (_, chain) = store
+            .private_key_chain()
+            .ok_or_else(|| invalid(format!("No private key in PKCS#12 bundle '{}'", path)))?;
+        let certs = chain.chain().iter().map(|cert| CertificateDer::from(cert.as_der().to_vec())).collect();
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(chain.key().to_vec()));
+        return Ok(Some((certs, key)));
+    }
+
+    let certs = read_certs(&data, path)?;
+    let (key_data, key_path) = match &tls.client_key {
+        Some(key_path) => (read(key_path, "client key")?, key_path),
+        None => (data, path),
+    };
+    let key = PrivateKeyDer::from_pem_slice(&key_data)
+        .map_err(|e| invalid(format!("No usable PEM private key in '{}': {}", key_path, e)))?;
+    Ok(Some((certs, key)))
+}
+
+/// Chain a server presented and what normal verification said about it
+type Presented = (Vec<CertificateDer<'static>>, Result<(), String>);
+
+/// Accepts any chain so it can be reported, recording what normal verification would have said.
+/// Nothing but the handshake is sent over connections using it.
+#[derive(Debug)]
+struct InspectingVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    seen: Mutex<Option<Presented>>,
+}
+
+impl ServerCertVerifier for InspectingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verdict = self
+            .inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+        let chain = std::iter::once(end_entity)
+            .chain(intermediates)
+            .map(|cert| cert.clone().into_owned())
+            .collect();
+        *self.seen.lock().unwrap() = Some((chain, verdict));
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Handshake with the host of `url` along the route the proxy settings pick and describe its chain
+pub async fn inspect(
+    proxy: Option<&ProxyConfig>,
+    tls: Option<&TlsConfig>,
+    url: &str,
+    egress: &EgressPolicy,
+) -> Result<TlsReport, HttpError> {
+    let parsed = Url::parse(url).map_err(|e| invalid(format!("Invalid URL '{}': {}", url, e)))?;
+    let host = parsed.host_str().unwrap_or_default().trim_matches(['[', ']']).to_string();
+    let name = ServerName::try_from(host.clone()).map_err(|e| invalid(format!("Invalid TLS server name '{}': {}", host, e)))?;
+
+    let default = TlsConfig::default();
+    let settings = tls.unwrap_or(&default);
+    let roots = Arc::new(roots(settings)?);
+    let verifier = Arc::new(InspectingVerifier {
+        inner: webpki_verifier(&roots)?,
+        seen: Mutex::new(None),
+    });
+    let builder = rustls::ClientConfig::builder_with_provider(provider())
+        .with_protocol_versions(versions(settings.min_version.as_deref())?)
+        .map_err(|e| invalid(format!("Unsupported TLS settings: {}", e)))?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone());
+    let config = with_identity(builder, settings)?;
+
+    let io = diagnostics::open(proxy, tls, &parsed, egress).await?;
+    let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, TlsConnector::from(Arc::new(config)).connect(name, io))
+        .await
+        .map_err(|_| HttpError::Timeout(TimeoutKind::Connect))?
+        .map_err(|e| HttpError::Tls(format!("TLS handshake with {} failed: {}", host, e)))?;
+    let connection = stream.get_ref().1;
+
+    let (chain, verdict) = verifier
+        .seen
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or_else(|| HttpError::Tls(format!("{} presented no certificate", host)))?;
+    Ok(TlsReport {
+        url: url.to_string(),
+        version: match connection.protocol_version() {
+            Some(rustls::ProtocolVersion::TLSv1_3) => "TLS 1.3".to_string(),
+            Some(rustls::ProtocolVersion::TLSv1_2) => "TLS 1.2".to_string(),
+            other => format!("{:?}", other),
+        },
+        cipher_suite: connection
+            .negotiated_cipher_suite()
+            .map(|suite| format!("{:?}", suite.suite()))
+            .unwrap_or_default(),
+        chain: chain.iter().map(|cert| describe(cert)).collect::<Result<_, _>>()?,
+        trusted: verdict.is_ok(),
+        error: verdict.err(),
+        pinned: pinning::for_host(&settings.pins, &host).map(|set| set.allows(&pinning::chain_keys(&chain, &roots))),
+    })
+}
+
+fn describe(cert: &CertificateDer<'_>) -> Result<CertificateInfo, HttpError> {
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert)
+        .map_err(|e| HttpError::Tls(format!("Unparsable certificate: {}", e)))?;
+
+    let dns_names = match parsed.subject_alternative_name() {
+        Ok(Some(san)) => san
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(dns) => Some(dns.to_string()),
+                GeneralName::IPAddress(_) => Some(name.to_string()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let sha256 = Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":");
+    let body = BASE64.encode(cert.as_ref());
+    let lines = body.as_bytes().chunks(64).map(|line| String::from_utf8_lossy(line)).collect::<Vec<_>>();
+
+    Ok(CertificateInfo {
+        subject: parsed.subject().to_string(),
+        issuer: parsed.issuer().to_string(),
+        serial: parsed.raw_serial_as_string(),
+        not_before: parsed.validity().not_before.timestamp(),
+        not_after: parsed.validity().not_after.timestamp(),
+        dns_names,
+        is_ca: parsed.is_ca(),
+        sha256,
+        spki_sha256: pinning::spki_sha256(parsed.public_key().raw),
+        pem: format!("-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n", lines.join("\n")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::egress::{AddressGuard, EgressConfig};
+    use p12_keystore::{Certificate, KeyStoreEntry, PrivateKeyChain};
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+    use rustls::server::WebPkiClientVerifier;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+
+    /// Policy that lets the local test servers through
+    fn open_policy() -> EgressPolicy {
+        let policy = EgressPolicy::default();
+        let config = EgressConfig {
+            allow_private: true,
+            ..EgressConfig::default()
+        };
+        policy.set(&config, true).unwrap();
+        policy
+    }
+
+    struct Pki {
+        dir: tempfile::TempDir,
+        ca: rcgen::Certificate,
+        server: (rcgen::Certificate, KeyPair),
+        client: (rcgen::Certificate, KeyPair),
+    }
+
+    impl Pki {
+        /// Test CA with a `localhost` server certificate and a client certificate
+        fn new() -> Self {
+            let ca_key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            params.distinguished_name.push(DnType::CommonName, "Gateway Test CA");
+            let ca = params.self_signed(&ca_key).unwrap();
+
+            let issue = |name: &str| {
+                let key = KeyPair::generate().unwrap();
+                let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+                params.distinguished_name.push(DnType::CommonName, name);
+                (params.signed_by(&key, &ca, &ca_key).unwrap(), key)
+            };
+            let server = issue("localhost");
+            let client = issue("agent");
+
+            let pki = Self {
+                dir: tempfile::tempdir().unwrap(),
+                ca,
+                server,
+                client,
+            };
+            pki.write("ca.pem", pki.ca.pem().as_bytes());
+            pki.write("client.pem", format!("{}{}", pki.client.0.pem(), pki.client.1.serialize_pem()).as_bytes());
+            pki
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.dir.path().join(name).to_string_lossy().into_owned()
+        }
+
+        fn write(&self, name: &str, contents: &[u8]) -> String {
+            std::fs::write(self.path(name), contents).unwrap();
+            self.path(name)
+        }
+
+        /// HTTPS server presenting the server certificate and the CA, optionally requiring a client certificate
+        async fn serve(&self, require_client_cert: bool) -> u16 {
+            let chain = vec![self.server.0.der().clone(), self.ca.der().clone()];
+            let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(self.server.1.serialize_der()));
+            let builder = rustls::ServerConfig::builder_with_provider(provider())
+                .with_safe_default_protocol_versions()
+                .unwrap();
+            let builder = if require_client_cert {
+                let mut roots = rustls::RootCertStore::empty();
+                roots.add(self.ca.der().clone()).unwrap();
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider())
+                    .build()
+                    .unwrap();
+                builder.with_client_cert_verifier(verifier)
+            } else {
+                builder.with_no_client_auth()
+            };
+            let acceptor = TlsAcceptor::from(Arc::new(builder.with_single_cert(chain, key).unwrap()));
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            tokio::spawn(async move {
+                loop {
+                    let (socket, _) = listener.accept().await.unwrap();
+                    let acceptor = acceptor.clone();
+                    tokio::spawn(async move {
+                        let Ok(mut stream) = acceptor.accept(socket).await else { return };
+                        let mut buf = [0u8; 1024];
+                        let _ = stream.read(&mut buf).await;
+                        let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok";
+                        let _ = stream.write_all(response.as_bytes()).await;
+                        let _ = stream.shutdown().await;
+                    });
+                }
+            });
+            port
+        }
+    }
+
+    async fn get(tls: &TlsConfig, port: u16) -> Result<u16, HttpError> {
+        let client = super::super::build_client(None, Some(tls), None, &Default::default(), Arc::new(AddressGuard::new(true)))?;
+        let response = client
+            .get(format!("https://localhost:{}/", port))
+            .send()
+            .await
+            .map_err(|e| HttpError::from_reqwest(&e))?;
+        Ok(response.status().as_u16())
+    }
+
+    #[tokio::test]
+    async fn reports_presented_chain() {
+        let pki = Pki::new();
+        let port = pki.serve(false).await;
+        let url = format!("https://localhost:{}/", port);
+
+        let report = inspect(None, None, &url, &open_policy()).await.unwrap();
+        assert!(!report.trusted);
+        assert!(report.error.is_some());
+        assert_eq!(report.chain.len(), 2);
+        assert_eq!(report.chain[0].subject, "CN=localhost");
+        assert_eq!(report.chain[0].issuer, "CN=Gateway Test CA");
+        assert_eq!(report.chain[0].dns_names, vec!["localhost"]);
+        assert!(report.chain[1].is_ca);
+        assert_eq!(
+            report.chain[0].spki_sha256,
+            BASE64.encode(Sha256::digest(pki.server.1.public_key_der()))
+        );
+        assert_eq!(report.chain[1].pem, pki.ca.pem().replace("\r\n", "\n"));
+
+        let tls = TlsConfig {
+            ca_file: Some(pki.path("ca.pem")),
+            ..TlsConfig::default()
+        };
+        let report = inspect(None, Some(&tls), &url, &open_policy()).await.unwrap();
+        assert!(report.trusted, "{:?}", report.error);
+        assert_eq!(report.error, None);
+    }
+
+    #[tokio::test]
+    async fn times_the_handshake_apart_from_the_connect() {
+        let pki = Pki::new();
+        let port = pki.serve(false).await;
+        let tls = TlsConfig {
+            ca_file: Some(pki.path("ca.pem")),
+            ..TlsConfig::default()
+        };
+        let client = super::super::build_client(None, Some(&tls), None, &Default::default(), Arc::new(AddressGuard::new(true))).unwrap();
+
+        let mut timer = super::super::Timer::start();
+        let url = format!("https://localhost:{}/", port);
+        timer.send(0, client.get(&url).send()).await.unwrap();
+
+        let timing = timer.finish();
+        assert!(timing.connect_ms.is_some() && timing.tls_ms.is_some(), "{:?}", timing);
+    }
+
+    #[tokio::test]
+    async fn trusts_extra_roots_and_presents_client_identity() {
+        let pki = Pki::new();
+        let open = pki.serve(false).await;
+        let mutual = pki.serve(true).await;
+
+        assert_eq!(get(&TlsConfig::default(), open).await.unwrap_err().kind(), "tls");
+
+        let trusted = TlsConfig {
+            ca_file: Some(pki.path("ca.pem")),
+            ..TlsConfig::default()
+        };
+        assert_eq!(get(&trusted, open).await.unwrap(), 200);
+        assert!(get(&trusted, mutual).await.is_err());
+
+        let pem_identity = TlsConfig {
+            client_cert: Some(pki.path("client.pem")),
+            ..trusted.clone()
+        };
+        assert_eq!(get(&pem_identity, mutual).await.unwrap(), 200);
+
+        let mut store = KeyStore::new();
+        let chain = PrivateKeyChain::new(
+            pki.client.1.serialize_der(),
+            [1u8; 20],
+            [Certificate::from_der(pki.client.0.der()).unwrap()],
+        );
+        store.add_entry("agent", KeyStoreEntry::PrivateKeyChain(chain));
+        let bundle = pki.write("client.p12", &store.writer("hunter2").write().unwrap());
+        let p12_identity = TlsConfig {
+            client_cert: Some(bundle),
+            client_cert_password: Some("hunter2".to_string()),
+            ..trusted.clone()
+        };
+        assert_eq!(get(&p12_identity, mutual).await.unwrap(), 200);
+
+        let wrong_password = TlsConfig {
+            client_cert_password: Some("nope".to_string()),
+            ..p12_identity
+        };
+        assert_eq!(get(&wrong_password, mutual).await.unwrap_err().kind(), "invalid_config");
+    }
+
+    #[tokio::test]
+    async fn enforces_pins_or_reports_mismatches() {
+        let pki = Pki::new();
+        let port = pki.serve(false).await;
+        let leaf_key = BASE64.encode(Sha256::digest(pki.server.1.public_key_der()));
+        let ca_key = pinning::chain_keys(&[pki.ca.der().clone()], &rustls::RootCertStore::empty()).remove(0);
+        let pinned = |pin: &str, report_only| TlsConfig {
+            ca_file: Some(pki.path("ca.pem")),
+            pins: vec![PinSet {
+                host: "localhost".to_string(),
+                pins: vec![format!("sha256/{}", pin)],
+                report_only,
+            }],
+            ..TlsConfig::default()
+        };
+
+        assert_eq!(get(&pinned(&ca_key, false), port).await.unwrap(), 200);
+        let wrong = BASE64.encode([0u8; 32]);
+        assert_eq!(get(&pinned(&wrong, false), port).await.unwrap_err().kind(), "pin_mismatch");
+        assert_eq!(get(&pinned(&wrong, true), port).await.unwrap(), 200);
+
+        let reported: Vec<_> = pinning::recent()
+            .into_iter()
+            .filter(|violation| violation.presented.contains(&leaf_key))
+            .map(|violation| violation.enforced)
+            .collect();
+        assert_eq!(reported, vec![true, false]);
+
+        let url = format!("https://localhost:{}/", port);
+        let report = inspect(None, Some(&pinned(&leaf_key, false)), &url, &open_policy()).await.unwrap();
+        assert_eq!(report.pinned, Some(true));
+        let report = inspect(None, Some(&pinned(&wrong, false)), &url, &open_policy()).await.unwrap();
+        assert_eq!(report.pinned, Some(false));
+    }
+
+    #[test]
+    fn pins_trust_anchor_the_chain_ends_at() {
+        let pki = Pki::new();
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(pki.ca.der().clone()).unwrap();
+        let ca_key = pinning::chain_keys(&[pki.ca.der().clone()], &rustls::RootCertStore::empty()).remove(0);
+
+        let keys = pinning::chain_keys(&[pki.server.0.der().clone()], &roots);
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[1], ca_key);
+    }
+
+    #[test]
+    fn validates_minimum_version() {
+        let tls13 = TlsConfig {
+            min_version: Some("1.3".to_string()),
+            ..TlsConfig::default()
+        };
+        assert!(client_config(Some(&tls13)).is_ok());
+
+        let tls11 = TlsConfig {
+            min_version: Some("1.1".to_string()),
+            ..TlsConfig::default()
+        };
+        assert_eq!(client_config(Some(&tls11)).unwrap_err().kind(), "invalid_config");
+    }
+}
@@ -4,8 +4,8 @@
 //! Handles HTTP requests with proxy support for AI API calls.
 
 mod http;
+mod secrets;
 
-#[cfg(debug_assertions)]
 use tauri::Manager;
 
 /// Configure the Tauri application
@@ -16,14 +16,54 @@ pub fn run() {
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
+        .manage(http::ActiveRequests::default())
+        .manage(http::StreamRegistry::default())
+        .manage(http::Scheduler::default())
+        .manage(http::RateLimiter::default())
         .invoke_handler(tauri::generate_handler![
             http::http_request,
             http::http_request_stream,
+            http::http_stream_attach,
+            http::http_stream_list,
+            http::http_cancel,
+            http::http_reset_clients,
+            http::http_choose_save_path,
+            http::egress_set_policy,
+            http::proxy_detect,
+            http::proxy_resolve,
+            http::proxy_test,
+            http::tls_inspect,
+            http::tls_pin_reports,
+            http::tls_set_pins,
+            http::scheduler_configure,
+            http::scheduler_status,
+            http::rate_limit_configure,
+            http::rate_limit_status,
+            http::cache_configure,
+            http::cache_stats,
+            http::cache_clear,
+            secrets::secret_set,
+            secrets::secret_get,
+            secrets::secret_delete,
+            secrets::secret_list,
         ])
-        .setup(|_app| {
+        .setup(|app| {
+            let data_dir = app.path().app_data_dir()?;
+            app.manage(secrets::SecretStore::open(&data_dir)?);
+            let egress = http::EgressPolicy::open(&data_dir);
+            app.manage(http::ClientPool::new(egress.guard()));
+            app.manage(egress);
+            app.manage(http::PinStore::open(&data_dir));
+            app.manage(http::ResponseCache::open(&app.path().app_cache_dir()?.join("http-cache"))?);
+            let downloads = app.path().app_cache_dir()?.join("downloads");
+            std::fs::create_dir_all(&downloads)?;
+            app.manage(http::SaveTargets::new(
+                [Some(downloads), app.path().download_dir().ok()].into_iter().flatten().collect(),
+            ));
+
             #[cfg(debug_assertions)]
             {
-                let window = _app.get_webview_window("main").unwrap();
+                let window = app.get_webview_window("main").unwrap();
                 window.open_devtools();
             }
             Ok(())
@@ -0,0 +1,607 @@
+//! Secure storage for provider API keys
+//!
+//! Keys live in the platform secret store (macOS Keychain, Windows Credential Manager,
+//! Secret Service on Linux). When no secret store is reachable, e.g. a headless Linux
+//! session without a keyring daemon, they fall back to an encrypted file in the app
+//! data directory. HTTP commands resolve keys by name so raw values never have to
+//! pass through the webview.
+//!
+//! Each secret is bound to the hosts it may be sent to, so a script that can make
+//! requests still can't have a key delivered to a server of its choosing. Bindings are
+//! only set together with the value, so changing them means knowing the secret.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::State;
+
+/// Service name secrets are filed under in the platform store
+const KEYRING_SERVICE: &str = "com.socratic-council.app";
+
+/// Entry looked up at startup to check whether the platform store is usable
+const KEYRING_PROBE: &str = "__probe__";
+
+/// Opening marker of a header placeholder, as in `Bearer {{secret:openai}}`
+const PLACEHOLDER_OPEN: &str = "{{secret:";
+const PLACEHOLDER_CLOSE: &str = "}}";
+
+/// Hosts provider keys are bound to when stored without explicit hosts; the egress policy
+/// always allows them too
+pub(crate) const PROVIDER_HOSTS: &[(&str, &[&str])] = &[
+    ("openai", &["api.openai.com"]),
+    ("anthropic", &["api.anthropic.com"]),
+    ("google", &["generativelanguage.googleapis.com"]),
+    ("deepseek", &["api.deepseek.com"]),
+    ("kimi", &["api.moonshot.cn", "api.moonshot.ai"]),
+];
+
+const INDEX_FILE: &str = "secrets-index.json";
+const VAULT_FILE: &str = "secrets.enc";
+const VAULT_KEY_FILE: &str = "secrets.key";
+
+/// Storage backend for secret values
+trait SecretBackend: Send + Sync {
+    fn set(&self, name: &str, value: &str) -> Result<(), String>;
+    fn get(&self, name: &str) -> Result<Option<String>, String>;
+    fn delete(&self, name: &str) -> Result<(), String>;
+}
+
+/// Platform secret store via the `keyring` crate
+struct KeyringBackend;
+
+impl KeyringBackend {
+    /// Returns a backend only if the platform store answers a lookup
+    fn probe() -> Option<Self> {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_PROBE).ok()?;
+        match entry.get_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Some(Self),
+            Err(_) => None,
+        }
+    }
+
+    fn entry(name: &str) -> Result<keyring::Entry, String> {
+        keyring::Entry::new(KEYRING_SERVICE, name)
+            .map_err(|e| format!("Failed to open keychain entry: {}", e))
+    }
+}
+
+impl SecretBackend for KeyringBackend {
+    fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        Self::entry(name)?
+            .set_password(value)
+            .map_err(|e| format!("Failed to store secret: {}", e))
+    }
+
+    fn get(&self, name: &str) -> Result<Option<String>, String> {
+        match Self::entry(name)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(format!("Failed to read secret: {}", e)),
+        }
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        match Self::entry(name)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(format!("Failed to delete secret: {}", e)),
+        }
+    }
+}
+
+/// On-disk layout of the encrypted vault
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    nonce: String,
+    ciphertext: String,
+}
+
+/// ChaCha20-Poly1305 encrypted file, used when no platform store is available.
+///
+/// The key is kept in a separate owner-only file next to the vault, so this guards
+/// against the vault leaking through backups or sync, not against a local attacker.
+struct EncryptedFileBackend {
+    path: PathBuf,
+    cipher: ChaCha20Poly1305,
+    lock: Mutex<()>,
+}
+
+impl EncryptedFileBackend {
+    fn open(dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create secrets directory: {}", e))?;
+
+        let key_path = dir.join(VAULT_KEY_FILE);
+        let key = match fs::read(&key_path) {
+            Ok(bytes) if bytes.len() == 32 => *Key::from_slice(&bytes),
+            Ok(_) => return Err("Secrets key file is corrupt".to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+                write_private(&key_path, key.as_slice())?;
+                key
+            }
+            Err(e) => return Err(format!("Failed to read secrets key: {}", e)),
+        };
+
+        Ok(Self {
+            path: dir.join(VAULT_FILE),
+            cipher: ChaCha20Poly1305::new(&key),
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn load(&self) -> Result<BTreeMap<String, String>, String> {
+        let raw = match fs::read(&self.path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(format!("Failed to read secrets vault: {}", e)),
+        };
+
+        let vault: VaultFile =
+            serde_json::from_slice(&raw).map_err(|e| format!("Secrets vault is corrupt: {}", e))?;
+        let nonce = BASE64
+            .decode(vault.nonce)
+            .map_err(|e| format!("Secrets vault is corrupt: {}", e))?;
+        let ciphertext = BASE64
+            .decode(vault.ciphertext)
+            .map_err(|e| format!("Secrets vault is corrupt: {}", e))?;
+        if nonce.len() != 12 {
+            return Err("Secrets vault is corrupt: bad nonce".to_string());
+        }
+
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| "Failed to decrypt secrets vault".to_string())?;
+        serde_json::from_slice(&plaintext).map_err(|e| format!("Secrets vault is corrupt: {}", e))
+    }
+
+    fn save(&self, secrets: &BTreeMap<String, String>) -> Result<(), String> {
+        let plaintext = serde_json::to_vec(secrets).map_err(|e| e.to_string())?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| "Failed to encrypt secrets vault".to_string())?;
+
+        let vault = VaultFile {
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+        let raw = serde_json::to_vec(&vault).map_err(|e| e.to_string())?;
+        write_private(&self.path, &raw)
+    }
+}
+
+impl SecretBackend for EncryptedFileBackend {
+    fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        let _guard = self.lock.lock().unwrap();
+        let mut secrets = self.load()?;
+        secrets.insert(name.to_string(), value.to_string());
+        self.save(&secrets)
+    }
+
+    fn get(&self, name: &str) -> Result<Option<String>, String> {
+        let _guard = self.lock.lock().unwrap();
+        Ok(self.load()?.remove(name))
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        let _guard = self.lock.lock().unwrap();
+        let mut secrets = self.load()?;
+        if secrets.remove(name).is_some() {
+            self.save(&secrets)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write a file readable only by the current user
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let tmp = path.with_extension("tmp");
+
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        file.write_all(contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+
+    #[cfg(not(unix))]
+    fs::write(&tmp, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Secret store held in Tauri managed state
+pub struct SecretStore {
+    backend: Box<dyn SecretBackend>,
+    /// Values read so far, so requests and their retries don't go back to the keychain or
+    /// decrypt the vault on the async workers every time
+    values: Mutex<BTreeMap<String, String>>,
+    /// Known secret names with the hosts each may be sent to; platform stores cannot enumerate entries themselves
+    index: Mutex<BTreeMap<String, BTreeSet<String>>>,
+    index_path: PathBuf,
+}
+
+impl SecretStore {
+    /// Open the store, preferring the platform secret store over the encrypted file
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        match KeyringBackend::probe() {
+            Some(backend) => Self::with_backend(dir, Box::new(backend)),
+            None => Self::open_file(dir),
+        }
+    }
+
+    /// Open the store backed only by the encrypted file in `dir`
+    pub fn open_file(dir: &Path) -> Result<Self, String> {
+        let backend = EncryptedFileBackend::open(dir)?;
+        Self::with_backend(dir, Box::new(backend))
+    }
+
+    fn with_backend(dir: &Path, backend: Box<dyn SecretBackend>) -> Result<Self, String> {
+        let index_path = dir.join(INDEX_FILE);
+        let raw = fs::read(&index_path).unwrap_or_default();
+        let index = match serde_json::from_slice(&raw) {
+            Ok(index) => index,
+            // Indexes written before secrets were bound to hosts only list names
+            Err(_) => serde_json::from_slice::<BTreeSet<String>>(&raw)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|name| {
+                    let hosts = default_hosts(&name);
+                    (name, hosts)
+                })
+                .collect(),
+        };
+
+        Ok(Self {
+            backend,
+            values: Mutex::default(),
+            index: Mutex::new(index),
+            index_path,
+        })
+    }
+
+    /// Store `value` under `name`, to be sent only to `hosts`; provider names default to their API hosts
+    pub fn set(&self, name: &str, value: &str, hosts: &[String]) -> Result<(), String> {
+        validate_name(name)?;
+        let mut hosts: BTreeSet<String> = hosts
+            .iter()
+            .map(|host| host.trim().to_ascii_lowercase())
+            .filter(|host| !host.is_empty())
+            .collect();
+        if hosts.is_empty() {
+            hosts = default_hosts(name);
+        }
+        self.backend.set(name, value)?;
+        self.values.lock().unwrap().insert(name.to_string(), value.to_string());
+
+        let mut index = self.index.lock().unwrap();
+        if index.get(name) != Some(&hosts) {
+            index.insert(name.to_string(), hosts);
+            self.save_index(&index)?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<String>, String> {
+        validate_name(name)?;
+        if let Some(value) = self.values.lock().unwrap().get(name) {
+            return Ok(Some(value.clone()));
+        }
+        let value = self.backend.get(name)?;
+        if let Some(value) = &value {
+            self.values.lock().unwrap().insert(name.to_string(), value.clone());
+        }
+        Ok(value)
+    }
+
+    /// Look up a secret that a request to `host` depends on, failing if it is missing or bound elsewhere
+    pub fn require(&self, name: &str, host: &str) -> Result<String, String> {
+        let allowed = self
+            .index
+            .lock()
+            .unwrap()
+            .get(name)
+            .is_some_and(|hosts| hosts.contains(&host.to_ascii_lowercase()));
+        if !allowed {
+            return Err(format!("Secret '{}' may not be sent to {}", name, host));
+        }
+        self.get(name)?
+            .ok_or_else(|| format!("Secret '{}' is not set", name))
+    }
+
+    /// Replace every `{{secret:name}}` placeholder in `template` with the stored value, for a request to `host`
+    pub fn render(&self, template: &str, host: &str) -> Result<String, String> {
+        render_template(template, |name| self.require(name, host))
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), String> {
+        validate_name(name)?;
+        self.backend.delete(name)?;
+        self.values.lock().unwrap().remove(name);
+
+        let mut index = self.index.lock().unwrap();
+        if index.remove(name).is_some() {
+            self.save_index(&index)?;
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.index.lock().unwrap().keys().cloned().collect()
+    }
+
+    fn save_index(&self, index: &BTreeMap<String, BTreeSet<String>>) -> Result<(), String> {
+        let raw = serde_json::to_vec(index).map_err(|e| e.to_string())?;
+        write_private(&self.index_path, &raw)
+    }
+}
+
+fn default_hosts(name: &str) -> BTreeSet<String> {
+    PROVIDER_HOSTS
+        .iter()
+        .find(|(provider, _)| *provider == name)
+        .map(|(_, hosts)| hosts.iter().map(|host| host.to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// Whether a header value contains secret placeholders that need resolving
+pub fn is_template(value: &str) -> bool {
+    value.contains(PLACEHOLDER_OPEN)
+}
+
+/// Substitute placeholders using `lookup`; unterminated placeholders are left as-is
+fn render_template(
+    template: &str,
+    mut lookup: impl FnMut(&str) -> Result<String, String>,
+) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find(PLACEHOLDER_OPEN) {
+        let after = &rest[start + PLACEHOLDER_OPEN.len()..];
+        let Some(end) = after.find(PLACEHOLDER_CLOSE) else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&lookup(after[..end].trim())?);
+        rest = &after[end + PLACEHOLDER_CLOSE.len()..];
+    }
+
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Keep the first three and last four characters of long values, enough to tell keys apart
+fn mask(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() < 16 {
+        return "•".repeat(8);
+    }
+    let head: String = chars[..3].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", head, tail)
+}
+
+/// Secret names are used as keychain account names and in header templates
+fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid && name != KEYRING_PROBE {
+        Ok(())
+    } else {
+        Err(format!("Invalid secret name: {}", name))
+    }
+}
+
+/// Store a secret under `name`, bound to `hosts`, or to the provider's API hosts when none are given
+#[tauri::command]
+pub fn secret_set(
+    store: State<'_, SecretStore>,
+    name: String,
+    value: String,
+    hosts: Option<Vec<String>>,
+) -> Result<(), String> {
+    store.set(&name, &value, &hosts.unwrap_or_default())
+}
+
+/// Masked form of a secret, e.g. to show which key is saved, or null if it is not set.
+/// The raw value never leaves the backend.
+#[tauri::command]
+pub fn secret_get(store: State<'_, SecretStore>, name: String) -> Result<Option<String>, String> {
+    Ok(store.get(&name)?.as_deref().map(mask))
+}
+
+/// Remove a secret; deleting a missing secret is not an error
+#[tauri::command]
+pub fn secret_delete(store: State<'_, SecretStore>, name: String) -> Result<(), String> {
+    store.delete(&name)
+}
+
+/// List the names of stored secrets (never their values)
+#[tauri::command]
+pub fn secret_list(store: State<'_, SecretStore>) -> Vec<String> {
+    store.list()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn file_store_round_trips_and_lists_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SecretStore::open_file(dir.path()).unwrap();
+
+        store.set("anthropic", "sk-ant-123", &[]).unwrap();
+        store.set("openai", "sk-456", &[]).unwrap();
+
+        assert_eq!(store.get("anthropic").unwrap().as_deref(), Some("sk-ant-123"));
+        assert_eq!(store.list(), vec!["anthropic", "openai"]);
+
+        store.delete("anthropic").unwrap();
+        assert_eq!(store.get("anthropic").unwrap(), None);
+        assert_eq!(store.list(), vec!["openai"]);
+        assert!(store.require("anthropic", "api.anthropic.com").is_err());
+    }
+
+    #[test]
+    fn file_store_persists_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        SecretStore::open_file(dir.path())
+            .unwrap()
+            .set("kimi", "very-secret-value", &[])
+            .unwrap();
+
+        let raw = fs::read_to_string(dir.path().join(VAULT_FILE)).unwrap();
+        assert!(!raw.contains("very-secret-value"));
+
+        let reopened = SecretStore::open_file(dir.path()).unwrap();
+        assert_eq!(reopened.get("kimi").unwrap().as_deref(), Some("very-secret-value"));
+        assert_eq!(reopened.list(), vec!["kimi"]);
+    }
+
+    #[test]
+    fn file_store_rejects_wrong_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SecretStore::open_file(dir.path()).unwrap();
+        store.set("google", "value", &[]).unwrap();
+
+        fs::write(dir.path().join(VAULT_KEY_FILE), [7u8; 32]).unwrap();
+        let reopened = SecretStore::open_file(dir.path()).unwrap();
+        assert!(reopened.get("google").is_err());
+    }
+
+    #[test]
+    fn renders_header_templates() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SecretStore::open_file(dir.path()).unwrap();
+        store.set("openai", "sk-456", &[]).unwrap();
+        store.set("org", "org-1", &["api.openai.com".to_string()]).unwrap();
+
+        let host = "api.openai.com";
+        assert_eq!(store.render("Bearer {{secret:openai}}", host).unwrap(), "Bearer sk-456");
+        assert_eq!(
+            store.render("{{ secret:openai }}/{{secret:org}}", host).unwrap(),
+            "{{ secret:openai }}/org-1"
+        );
+        assert_eq!(store.render("{{secret:openai", host).unwrap(), "{{secret:openai");
+        assert!(store.render("Bearer {{secret:missing}}", host).is_err());
+        assert!(!is_template("Bearer sk-456"));
+    }
+
+    #[test]
+    fn refuses_hosts_a_secret_is_not_bound_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SecretStore::open_file(dir.path()).unwrap();
+        store.set("anthropic", "sk-ant-123", &[]).unwrap();
+        store.set("gateway", "gw-1", &[" LLM.corp.example ".to_string()]).unwrap();
+        store.set("unbound", "x", &[]).unwrap();
+
+        assert!(store.require("anthropic", "API.Anthropic.com").is_ok());
+        assert!(store.render("{{secret:anthropic}}", "evil.example").is_err());
+        assert!(store.require("gateway", "llm.corp.example").is_ok());
+        assert!(store.require("gateway", "api.anthropic.com").is_err());
+        assert!(store.require("unbound", "api.anthropic.com").is_err());
+
+        // Rebinding needs the value, and bindings survive reopening
+        store.set("anthropic", "sk-mine", &["evil.example".to_string()]).unwrap();
+        let reopened = SecretStore::open_file(dir.path()).unwrap();
+        assert_eq!(reopened.require("anthropic", "evil.example").unwrap(), "sk-mine");
+        assert!(reopened.require("anthropic", "api.anthropic.com").is_err());
+    }
+
+    #[test]
+    fn binds_names_from_older_indexes_to_provider_hosts() {
+        let dir = tempfile::tempdir().unwrap();
+        SecretStore::open_file(dir.path()).unwrap().set("openai", "sk-456", &[]).unwrap();
+        fs::write(dir.path().join(INDEX_FILE), r#"["openai","custom"]"#).unwrap();
+
+        let store = SecretStore::open_file(dir.path()).unwrap();
+        assert_eq!(store.list(), vec!["custom", "openai"]);
+        assert_eq!(store.require("openai", "api.openai.com").unwrap(), "sk-456");
+        assert!(store.require("custom", "api.openai.com").is_err());
+    }
+
+    #[test]
+    fn masks_values() {
+        assert_eq!(mask("sk-ant-REDACTED"), "sk-…mnop");
+        assert_eq!(mask("short-key"), "••••••••");
+        assert_eq!(mask("é".repeat(20).as_str()), "ééé…éééé");
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SecretStore::open_file(dir.path()).unwrap();
+        assert!(store.set("", "x", &[]).is_err());
+        assert!(store.set("has space", "x", &[]).is_err());
+        assert!(store.set("{{secret}}", "x", &[]).is_err());
+        assert!(store.set(KEYRING_PROBE, "x", &[]).is_err());
+    }
+
+    /// Backend that counts lookups
+    #[derive(Default)]
+    struct Counting {
+        values: Mutex<BTreeMap<String, String>>,
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl SecretBackend for Counting {
+        fn set(&self, name: &str, value: &str) -> Result<(), String> {
+            self.values.lock().unwrap().insert(name.to_string(), value.to_string());
+            Ok(())
+        }
+
+        fn get(&self, name: &str) -> Result<Option<String>, String> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            Ok(self.values.lock().unwrap().get(name).cloned())
+        }
+
+        fn delete(&self, name: &str) -> Result<(), String> {
+            self.values.lock().unwrap().remove(name);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reads_each_value_from_the_backend_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = Counting::default();
+        backend.set("openai", "sk-456").unwrap();
+        let reads = backend.reads.clone();
+        let store = SecretStore::with_backend(dir.path(), Box::new(backend)).unwrap();
+        store.index.lock().unwrap().insert("openai".to_string(), default_hosts("openai"));
+
+        for _ in 0..3 {
+            assert_eq!(store.require("openai", "api.openai.com").unwrap(), "sk-456");
+        }
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+        // Changes go through the store, so it never hands out a stale value
+        store.set("openai", "sk-789", &[]).unwrap();
+        assert_eq!(store.require("openai", "api.openai.com").unwrap(), "sk-789");
+        store.delete("openai").unwrap();
+        assert_eq!(store.get("openai").unwrap(), None);
+    }
+}
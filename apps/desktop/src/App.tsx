@@ -1,7 +1,8 @@
-import { useState } from "react";
+import { useEffect, useState } from "react";
 import { Home } from "./pages/Home";
 import { Settings } from "./pages/Settings";
 import { Chat } from "./pages/Chat";
+import { cancelOrphanedTauriStreams } from "./services/tauriTransport";
 
 export type Page = "home" | "settings" | "chat";
 
@@ -16,6 +17,11 @@ export default function App() {
     topic: null,
   });
 
+  // Streams from before a reload have no discussion left to reattach to
+  useEffect(() => {
+    void cancelOrphanedTauriStreams().catch(() => {});
+  }, []);
+
   const navigate = (page: Page, topic?: string) => {
     setState({
       currentPage: page,
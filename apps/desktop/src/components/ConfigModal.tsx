@@ -1,14 +1,40 @@
-import { useState } from "react";
+import { useEffect, useState } from "react";
 import {
   type Provider,
   type ProxyType,
   type AppConfig,
+  type RateLimits,
   PROVIDER_INFO,
   DISCUSSION_LENGTHS,
+  formatProxyFailover,
+  formatProxyRules,
+  formatTlsPins,
+  parseProxyFailover,
+  parseProxyRules,
+  parseTlsPins,
 } from "../stores/config";
 import { getModelsByProvider } from "@socratic-council/shared";
 import { ProviderIcon } from "./icons/ProviderIcons";
 import { testProviderConnection } from "../services/api";
+import {
+  clearCache,
+  detectSystemProxy,
+  getCacheStats,
+  getPinReports,
+  getRateLimitStatus,
+  getSchedulerStatus,
+  inspectTls,
+  resolveProxyRoute,
+  testProxy,
+  type CacheStats,
+  type DetectedProxy,
+  type PinViolation,
+  type ProxyReport,
+  type ProxyRoute,
+  type RateLimitStatus,
+  type SchedulerStatus,
+  type TlsReport,
+} from "../services/tauriTransport";
 
 interface ConfigModalProps {
   isOpen: boolean;
@@ -16,6 +42,8 @@ interface ConfigModalProps {
   config: AppConfig;
   onUpdateCredential: (provider: Provider, credential: { apiKey: string; baseUrl?: string; verified?: boolean; lastTested?: number } | null) => void;
   onUpdateProxy: (proxy: AppConfig["proxy"]) => void;
+  onUpdateTls: (tls: AppConfig["tls"]) => void;
+  onUpdateRateLimits: (provider: Provider, limits: RateLimits) => void;
   onUpdatePreferences: (preferences: Partial<AppConfig["preferences"]>) => void;
   onUpdateModel: (provider: Provider, model: string) => void;
 }
@@ -58,6 +86,8 @@ export function ConfigModal({
   config,
   onUpdateCredential,
   onUpdateProxy,
+  onUpdateTls,
+  onUpdateRateLimits,
   onUpdatePreferences,
   onUpdateModel,
 }: ConfigModalProps) {
@@ -74,11 +104,115 @@ export function ConfigModal({
     kimi: null,
   });
   const [testError, setTestError] = useState<string | null>(null);
+  const [detectedProxy, setDetectedProxy] = useState<DetectedProxy | null>(null);
+  const [proxyRulesText, setProxyRulesText] = useState(() => formatProxyRules(config.proxy.rules));
+  const [proxyFailoverText, setProxyFailoverText] = useState(() => formatProxyFailover(config.proxy.failover));
+  const [routeTestUrl, setRouteTestUrl] = useState("");
+  const [routeTestResult, setRouteTestResult] = useState<ProxyRoute | string | null>(null);
+  const [proxyReport, setProxyReport] = useState<ProxyReport | null>(null);
+  const [runningProxyTest, setRunningProxyTest] = useState(false);
+  const [tlsInspectUrl, setTlsInspectUrl] = useState("https://api.anthropic.com");
+  const [tlsReport, setTlsReport] = useState<TlsReport | string | null>(null);
+  const [tlsPinsText, setTlsPinsText] = useState(() => formatTlsPins(config.tls.pins));
+  const [pinReports, setPinReports] = useState<PinViolation[] | string | null>(null);
+  const [rateLimitStatus, setRateLimitStatus] = useState<RateLimitStatus[] | string | null>(null);
+  const [schedulerStatus, setSchedulerStatus] = useState<SchedulerStatus | string | null>(null);
+  const [cacheStats, setCacheStats] = useState<CacheStats | string | null>(null);
+
+  const usesSystemProxy = isOpen && config.proxy.type === "system";
+  useEffect(() => {
+    if (!usesSystemProxy) return;
+    detectSystemProxy()
+      .then(setDetectedProxy)
+      .catch((error) => console.error("Failed to detect system proxy:", error));
+  }, [usesSystemProxy]);
 
   if (!isOpen) return null;
 
   const configuredCount = PROVIDERS.filter((p) => config.credentials[p]?.apiKey).length;
 
+  const errorMessage = (error: unknown) =>
+    typeof error === "object" && error && "message" in error ? String(error.message) : String(error);
+
+  const handleTestRoute = async () => {
+    if (!routeTestUrl.trim()) return;
+    setProxyReport(null);
+    try {
+      const route = await resolveProxyRoute(config.proxy, routeTestUrl.trim());
+      setRouteTestResult(route ?? "Routing preview requires the desktop app");
+    } catch (error) {
+      setRouteTestResult(errorMessage(error));
+    }
+  };
+
+  const handleRunProxyTest = async () => {
+    if (!routeTestUrl.trim()) return;
+    setRunningProxyTest(true);
+    setRouteTestResult(null);
+    setProxyReport(null);
+    try {
+      const report = await testProxy(config.proxy, routeTestUrl.trim());
+      if (report) setProxyReport(report);
+      else setRouteTestResult("Proxy diagnostics require the desktop app");
+    } catch (error) {
+      setRouteTestResult(errorMessage(error));
+    } finally {
+      setRunningProxyTest(false);
+    }
+  };
+
+  const handleInspectTls = async () => {
+    if (!tlsInspectUrl.trim()) return;
+    setTlsReport(null);
+    try {
+      const report = await inspectTls(config.proxy, tlsInspectUrl.trim());
+      setTlsReport(report ?? "Certificate inspection requires the desktop app");
+    } catch (error) {
+      setTlsReport(errorMessage(error));
+    }
+  };
+
+  const handleShowPinReports = async () => {
+    try {
+      setPinReports(await getPinReports());
+    } catch (error) {
+      setPinReports(errorMessage(error));
+    }
+  };
+
+  const handleShowRateLimits = async () => {
+    try {
+      setRateLimitStatus(await getRateLimitStatus());
+    } catch (error) {
+      setRateLimitStatus(errorMessage(error));
+    }
+  };
+
+  const handleShowSchedulerStatus = async () => {
+    try {
+      setSchedulerStatus((await getSchedulerStatus()) ?? "Request scheduling requires the desktop app");
+    } catch (error) {
+      setSchedulerStatus(errorMessage(error));
+    }
+  };
+
+  const handleShowCacheStats = async () => {
+    try {
+      setCacheStats((await getCacheStats()) ?? "The response cache requires the desktop app");
+    } catch (error) {
+      setCacheStats(errorMessage(error));
+    }
+  };
+
+  const handleClearCache = async () => {
+    try {
+      await clearCache();
+      setCacheStats((await getCacheStats()) ?? null);
+    } catch (error) {
+      setCacheStats(errorMessage(error));
+    }
+  };
+
   const handleSaveCredential = async (provider: Provider) => {
     if (!apiKeyInput.trim()) return;
 
@@ -427,35 +561,75 @@ export function ConfigModal({
                     <option value="https">HTTPS Proxy</option>
                     <option value="socks5">SOCKS5 Proxy</option>
                     <option value="socks5h">SOCKS5h Proxy (DNS through proxy)</option>
+                    <option value="system">System Proxy (environment / desktop settings)</option>
+                    <option value="pac">Auto-Config Script (PAC)</option>
                   </select>
                 </div>
 
-                {config.proxy.type !== "none" && (
+                {config.proxy.type === "system" && (
+                  <div className="text-xs text-gray-400 space-y-1">
+                    {detectedProxy === null ? (
+                      <div>Detecting system proxy…</div>
+                    ) : detectedProxy.source === "none" ? (
+                      <div>No system proxy found; requests connect directly.</div>
+                    ) : (
+                      <>
+                        <div>Detected from {detectedProxy.source}:</div>
+                        {[detectedProxy.http, detectedProxy.https, detectedProxy.all, detectedProxy.pac_url]
+                          .filter(Boolean)
+                          .map((url) => (
+                            <div key={url} className="font-mono text-gray-300">{url}</div>
+                          ))}
+                        {detectedProxy.no_proxy.length > 0 && (
+                          <div>Bypassed: {detectedProxy.no_proxy.join(", ")}</div>
+                        )}
+                      </>
+                    )}
+                  </div>
+                )}
+
+                {config.proxy.type === "pac" && (
+                  <div>
+                    <label className="block text-sm text-gray-300 mb-2">PAC Script URL or File:</label>
+                    <input
+                      type="text"
+                      value={config.proxy.pacUrl || ""}
+                      onChange={(e) => onUpdateProxy({ ...config.proxy, pacUrl: e.target.value || undefined })}
+                      placeholder="http://wpad.example.com/proxy.pac or /etc/proxy.pac"
+                      className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5
+                        text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                    />
+                  </div>
+                )}
+
+                {config.proxy.type !== "none" && config.proxy.type !== "system" && (
                   <>
-                    <div className="grid grid-cols-2 gap-4">
-                      <div>
-                        <label className="block text-sm text-gray-300 mb-2">Host:</label>
-                        <input
-                          type="text"
-                          value={config.proxy.host}
-                          onChange={(e) => onUpdateProxy({ ...config.proxy, host: e.target.value })}
-                          placeholder="127.0.0.1 or proxy.example.com"
-                          className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5
-                            text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
-                        />
-                      </div>
-                      <div>
-                        <label className="block text-sm text-gray-300 mb-2">Port:</label>
-                        <input
-                          type="number"
-                          value={config.proxy.port || ""}
-                          onChange={(e) => onUpdateProxy({ ...config.proxy, port: parseInt(e.target.value) || 0 })}
-                          placeholder="7897"
-                          className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5
-                            text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
-                        />
+                    {config.proxy.type !== "pac" && (
+                      <div className="grid grid-cols-2 gap-4">
+                        <div>
+                          <label className="block text-sm text-gray-300 mb-2">Host:</label>
+                          <input
+                            type="text"
+                            value={config.proxy.host}
+                            onChange={(e) => onUpdateProxy({ ...config.proxy, host: e.target.value })}
+                            placeholder="127.0.0.1 or proxy.example.com"
+                            className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5
+                              text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                          />
+                        </div>
+                        <div>
+                          <label className="block text-sm text-gray-300 mb-2">Port:</label>
+                          <input
+                            type="number"
+                            value={config.proxy.port || ""}
+                            onChange={(e) => onUpdateProxy({ ...config.proxy, port: parseInt(e.target.value) || 0 })}
+                            placeholder="7897"
+                            className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5
+                              text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                          />
+                        </div>
                       </div>
-                    </div>
+                    )}
 
                     <div className="grid grid-cols-2 gap-4">
                       <div>
@@ -482,15 +656,263 @@ export function ConfigModal({
                       </div>
                     </div>
 
-                    <div className="text-sm text-gray-500">
-                      Current proxy URL: {config.proxy.type}://
-                      {config.proxy.username && `${config.proxy.username}:***@`}
-                      {config.proxy.host || "host"}:{config.proxy.port || "port"}
-                    </div>
+                    {config.proxy.type !== "pac" && (
+                      <div className="text-sm text-gray-500">
+                        Current proxy URL: {config.proxy.type}://
+                        {config.proxy.username && `${config.proxy.username}:***@`}
+                        {config.proxy.host || "host"}:{config.proxy.port || "port"}
+                      </div>
+                    )}
                   </>
                 )}
               </div>
 
+              <div className="bg-gray-800/50 border border-gray-700 rounded-xl p-5 space-y-4">
+                <div>
+                  <label className="block text-sm text-gray-300 mb-2">Routing Rules:</label>
+                  <p className="text-xs text-gray-500 mb-2">
+                    One rule per line: a host pattern or CIDR range, then <code>direct</code>, <code>system</code>,{" "}
+                    <code>pac:&lt;url&gt;</code> or a proxy URL. The first match wins; other hosts use the proxy above.
+                  </p>
+                  <textarea
+                    value={proxyRulesText}
+                    onChange={(e) => setProxyRulesText(e.target.value)}
+                    onBlur={() => onUpdateProxy({ ...config.proxy, rules: parseProxyRules(proxyRulesText) })}
+                    rows={4}
+                    placeholder={"*.anthropic.com socks5://127.0.0.1:1080\n192.168.0.0/16 direct\nlocalhost direct"}
+                    className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5 font-mono text-sm
+                      text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                  />
+                </div>
+
+                <div>
+                  <label className="block text-sm text-gray-300 mb-2">Failover:</label>
+                  <p className="text-xs text-gray-500 mb-2">
+                    Routes tried in order, one per line, when the proxy above can't connect. The route that worked is
+                    used first for that host for the next few minutes.
+                  </p>
+                  <textarea
+                    value={proxyFailoverText}
+                    onChange={(e) => setProxyFailoverText(e.target.value)}
+                    onBlur={() => onUpdateProxy({ ...config.proxy, failover: parseProxyFailover(proxyFailoverText) })}
+                    rows={2}
+                    placeholder={"http://proxy.office.example:3128\ndirect"}
+                    className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5 font-mono text-sm
+                      text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                  />
+                </div>
+
+                <div className="flex gap-2">
+                  <input
+                    type="text"
+                    value={routeTestUrl}
+                    onChange={(e) => setRouteTestUrl(e.target.value)}
+                    placeholder="https://api.anthropic.com/v1/messages"
+                    className="flex-1 bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5
+                      text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                  />
+                  <button
+                    onClick={handleTestRoute}
+                    className="px-4 py-2.5 bg-gray-700 hover:bg-gray-600 text-white rounded-lg text-sm transition-colors"
+                  >
+                    Check Route
+                  </button>
+                  <button
+                    onClick={handleRunProxyTest}
+                    disabled={runningProxyTest}
+                    className="px-4 py-2.5 bg-gray-700 hover:bg-gray-600 disabled:opacity-50 text-white rounded-lg text-sm transition-colors"
+                  >
+                    {runningProxyTest ? "Testing…" : "Test Connection"}
+                  </button>
+                </div>
+                {proxyReport && (
+                  <div className="text-xs space-y-1">
+                    {proxyReport.steps.map((step) => (
+                      <div key={step.name} className={step.status === "ok" ? "text-green-400" : "text-red-400"}>
+                        {step.status === "ok" ? "✓" : "✗"} <span className="font-mono">{step.name}</span>{" "}
+                        <span className="text-gray-400">{step.detail} ({step.elapsed_ms} ms)</span>
+                      </div>
+                    ))}
+                  </div>
+                )}
+                {routeTestResult && (
+                  <div className="text-xs text-gray-400">
+                    {typeof routeTestResult === "string" ? (
+                      routeTestResult
+                    ) : (
+                      <>
+                        {routeTestResult.type === "direct" ? "Direct connection" : `Via ${routeTestResult.proxy ?? routeTestResult.type}`}
+                        {routeTestResult.rule ? ` (rule ${routeTestResult.rule})` : " (global settings)"}
+                      </>
+                    )}
+                  </div>
+                )}
+              </div>
+
+              <div className="bg-gray-800/50 border border-gray-700 rounded-xl p-5 space-y-4">
+                <div>
+                  <h3 className="font-medium text-white">TLS</h3>
+                  <p className="text-xs text-gray-500 mt-1">
+                    For gateways that re-sign HTTPS traffic and endpoints that require a client certificate. Paths are
+                    read by the app; restart it after replacing a file in place.
+                  </p>
+                </div>
+
+                <div>
+                  <label className="block text-sm text-gray-300 mb-2">Extra CA Certificates (PEM file):</label>
+                  <input
+                    type="text"
+                    value={config.tls.caFile || ""}
+                    onChange={(e) => onUpdateTls({ ...config.tls, caFile: e.target.value || undefined })}
+                    placeholder="/etc/ssl/corp-gateway-ca.pem"
+                    className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5 font-mono text-sm
+                      text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                  />
+                </div>
+
+                <div className="grid grid-cols-2 gap-4">
+                  <div>
+                    <label className="block text-sm text-gray-300 mb-2">Client Certificate (PEM or .p12):</label>
+                    <input
+                      type="text"
+                      value={config.tls.clientCert || ""}
+                      onChange={(e) => onUpdateTls({ ...config.tls, clientCert: e.target.value || undefined })}
+                      placeholder="/path/to/client.p12"
+                      className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5 font-mono text-sm
+                        text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                    />
+                  </div>
+                  <div>
+                    <label className="block text-sm text-gray-300 mb-2">Client Key (PEM, if separate):</label>
+                    <input
+                      type="text"
+                      value={config.tls.clientKey || ""}
+                      onChange={(e) => onUpdateTls({ ...config.tls, clientKey: e.target.value || undefined })}
+                      placeholder="/path/to/client.key"
+                      className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5 font-mono text-sm
+                        text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                    />
+                  </div>
+                </div>
+
+                <div className="grid grid-cols-2 gap-4">
+                  <div>
+                    <label className="block text-sm text-gray-300 mb-2">Certificate Password (.p12):</label>
+                    <input
+                      type="password"
+                      value={config.tls.clientCertPassword || ""}
+                      onChange={(e) => onUpdateTls({ ...config.tls, clientCertPassword: e.target.value || undefined })}
+                      className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5
+                        text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                    />
+                  </div>
+                  <div>
+                    <label className="block text-sm text-gray-300 mb-2">Minimum TLS Version:</label>
+                    <select
+                      value={config.tls.minVersion || "1.2"}
+                      onChange={(e) => onUpdateTls({ ...config.tls, minVersion: e.target.value as "1.2" | "1.3" })}
+                      className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5
+                        text-white focus:outline-none focus:border-primary transition-all"
+                    >
+                      <option value="1.2">TLS 1.2</option>
+                      <option value="1.3">TLS 1.3</option>
+                    </select>
+                  </div>
+                </div>
+
+                <div>
+                  <label className="block text-sm text-gray-300 mb-2">Certificate Pins:</label>
+                  <p className="text-xs text-gray-500 mb-2">
+                    One host per line, then the <code>sha256/…</code> keys it may present (copy them from Inspect
+                    Certificate below). Add <code>report-only</code> to log mismatches without blocking.
+                  </p>
+                  <textarea
+                    value={tlsPinsText}
+                    onChange={(e) => setTlsPinsText(e.target.value)}
+                    onBlur={() => onUpdateTls({ ...config.tls, pins: parseTlsPins(tlsPinsText) })}
+                    rows={2}
+                    placeholder={"api.anthropic.com sha256/… sha256/…\n*.openai.com sha256/… report-only"}
+                    className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5 font-mono text-sm
+                      text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                  />
+                  <button
+                    onClick={handleShowPinReports}
+                    className="mt-2 px-3 py-1.5 bg-gray-700 hover:bg-gray-600 text-white rounded-lg text-xs transition-colors"
+                  >
+                    Show Recent Mismatches
+                  </button>
+                  {pinReports && (
+                    <div className="text-xs mt-2 space-y-1">
+                      {typeof pinReports === "string" ? (
+                        <div className="text-gray-400">{pinReports}</div>
+                      ) : pinReports.length === 0 ? (
+                        <div className="text-gray-400">No pin mismatches since the app started</div>
+                      ) : (
+                        pinReports.map((violation) => (
+                          <div key={`${violation.at}-${violation.host}`} className="text-gray-400">
+                            <span className={violation.enforced ? "text-red-400" : "text-yellow-400"}>
+                              {violation.enforced ? "Blocked" : "Reported"}
+                            </span>{" "}
+                            {violation.host} at {new Date(violation.at * 1000).toLocaleTimeString()}
+                            <div className="font-mono break-all">
+                              {violation.presented.map((key) => `sha256/${key}`).join(" ")}
+                            </div>
+                          </div>
+                        ))
+                      )}
+                    </div>
+                  )}
+                </div>
+
+                <div className="flex gap-2">
+                  <input
+                    type="text"
+                    value={tlsInspectUrl}
+                    onChange={(e) => setTlsInspectUrl(e.target.value)}
+                    className="flex-1 bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5
+                      text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                  />
+                  <button
+                    onClick={handleInspectTls}
+                    className="px-4 py-2.5 bg-gray-700 hover:bg-gray-600 text-white rounded-lg text-sm transition-colors"
+                  >
+                    Inspect Certificate
+                  </button>
+                </div>
+                {tlsReport && (
+                  <div className="text-xs space-y-2">
+                    {typeof tlsReport === "string" ? (
+                      <div className="text-gray-400">{tlsReport}</div>
+                    ) : (
+                      <>
+                        <div className={tlsReport.trusted ? "text-green-400" : "text-red-400"}>
+                          {tlsReport.trusted ? "✓ Trusted" : `✗ Not trusted: ${tlsReport.error ?? "unknown reason"}`}{" "}
+                          <span className="text-gray-400">
+                            ({tlsReport.version}, {tlsReport.cipher_suite})
+                          </span>
+                        </div>
+                        {tlsReport.pinned != null && (
+                          <div className={tlsReport.pinned ? "text-green-400" : "text-red-400"}>
+                            {tlsReport.pinned ? "✓ Matches pinned keys" : "✗ Matches none of the pinned keys"}
+                          </div>
+                        )}
+                        {tlsReport.chain.map((cert) => (
+                          <div key={cert.sha256} className="text-gray-400">
+                            <div className="text-gray-300">{cert.subject}</div>
+                            <div>Issued by {cert.issuer}</div>
+                            <div>
+                              Valid until {new Date(cert.not_after * 1000).toLocaleDateString()}
+                              {cert.is_ca ? " · CA" : ""}
+                            </div>
+                            <div className="font-mono break-all">SPKI sha256/{cert.spki_sha256}</div>
+                          </div>
+                        ))}
+                      </>
+                    )}
+                  </div>
+                )}
+              </div>
+
               <div className="bg-yellow-500/10 border border-yellow-500/20 rounded-xl p-4">
                 <div className="flex items-start gap-3">
                   <span className="text-yellow-400">⚠️</span>
@@ -575,9 +997,210 @@ export function ConfigModal({
                       <div className="toggle-slider" />
                     </label>
                   </div>
+
+                  <div className="flex items-center justify-between">
+                    <div>
+                      <div className="text-sm text-white">Allow Private Networks</div>
+                      <div className="text-xs text-gray-400">
+                        Let custom base URLs and MCP servers point at localhost or LAN addresses
+                      </div>
+                    </div>
+                    <label className="toggle-switch">
+                      <input
+                        type="checkbox"
+                        checked={config.preferences.allowPrivateNetworks}
+                        onChange={(e) => onUpdatePreferences({ allowPrivateNetworks: e.target.checked })}
+                      />
+                      <div className="toggle-slider" />
+                    </label>
+                  </div>
                 </div>
               </div>
 
+              {/* Concurrency */}
+              <div className="bg-gray-800/50 border border-gray-700 rounded-xl p-5 space-y-4">
+                <div>
+                  <h3 className="font-medium text-white">Concurrent Requests</h3>
+                  <p className="text-xs text-gray-500 mt-1">
+                    Requests over these limits wait in line. The speaking agent goes first, then other calls, then
+                    moderator notes. Each discussion gets its share.
+                  </p>
+                </div>
+                <div className="grid grid-cols-2 gap-4">
+                  <div>
+                    <label className="block text-sm text-gray-300 mb-2">In Total:</label>
+                    <input
+                      type="number"
+                      min={1}
+                      value={config.preferences.maxConcurrentRequests}
+                      onChange={(e) =>
+                        onUpdatePreferences({ maxConcurrentRequests: Math.max(1, parseInt(e.target.value, 10) || 1) })
+                      }
+                      className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5
+                        text-white focus:outline-none focus:border-primary transition-all"
+                    />
+                  </div>
+                  <div>
+                    <label className="block text-sm text-gray-300 mb-2">Per Host:</label>
+                    <input
+                      type="number"
+                      min={1}
+                      value={config.preferences.maxRequestsPerHost}
+                      onChange={(e) =>
+                        onUpdatePreferences({ maxRequestsPerHost: Math.max(1, parseInt(e.target.value, 10) || 1) })
+                      }
+                      className="w-full bg-gray-900 border border-gray-600 rounded-lg px-4 py-2.5
+                        text-white focus:outline-none focus:border-primary transition-all"
+                    />
+                  </div>
+                </div>
+                <button
+                  onClick={handleShowSchedulerStatus}
+                  className="px-3 py-1.5 bg-gray-700 hover:bg-gray-600 text-white rounded-lg text-xs transition-colors"
+                >
+                  Show Queue
+                </button>
+                {schedulerStatus && (
+                  <div className="text-xs space-y-1">
+                    {typeof schedulerStatus === "string" ? (
+                      <div className="text-gray-400">{schedulerStatus}</div>
+                    ) : (
+                      <>
+                        <div className="text-gray-300">
+                          {schedulerStatus.running} of {schedulerStatus.max_concurrent} running,{" "}
+                          {schedulerStatus.queued.length} waiting
+                        </div>
+                        {schedulerStatus.queued.map((request) => (
+                          <div key={`${request.position}-${request.request_id}`} className="text-gray-400">
+                            #{request.position} {request.host} · {request.priority}
+                            {request.session_id ? ` · ${request.session_id}` : ""}
+                          </div>
+                        ))}
+                      </>
+                    )}
+                  </div>
+                )}
+              </div>
+
+              {/* Response Cache */}
+              <div className="bg-gray-800/50 border border-gray-700 rounded-xl p-5 space-y-4">
+                <div className="flex items-center justify-between">
+                  <div>
+                    <h3 className="font-medium text-white">Response Cache</h3>
+                    <p className="text-xs text-gray-500 mt-1">
+                      Keep search lookups on disk for a day, so replaying a debate or re-checking claims doesn't fetch
+                      them again. Model replies are never cached.
+                    </p>
+                  </div>
+                  <label className="toggle-switch">
+                    <input
+                      type="checkbox"
+                      checked={config.preferences.responseCache}
+                      onChange={(e) => onUpdatePreferences({ responseCache: e.target.checked })}
+                    />
+                    <div className="toggle-slider" />
+                  </label>
+                </div>
+                <div className="flex gap-2">
+                  <button
+                    onClick={handleShowCacheStats}
+                    className="px-3 py-1.5 bg-gray-700 hover:bg-gray-600 text-white rounded-lg text-xs transition-colors"
+                  >
+                    Show Usage
+                  </button>
+                  <button
+                    onClick={handleClearCache}
+                    className="px-3 py-1.5 bg-gray-700 hover:bg-gray-600 text-white rounded-lg text-xs transition-colors"
+                  >
+                    Clear Cache
+                  </button>
+                </div>
+                {cacheStats && (
+                  <div className="text-xs text-gray-400">
+                    {typeof cacheStats === "string"
+                      ? cacheStats
+                      : `${cacheStats.entries} responses · ${(cacheStats.bytes / 1024 / 1024).toFixed(1)} of ${(
+                          cacheStats.max_bytes /
+                          1024 /
+                          1024
+                        ).toFixed(0)} MB · ${cacheStats.hits} hits, ${cacheStats.misses} misses since launch`}
+                  </div>
+                )}
+              </div>
+
+              {/* Rate Limits */}
+              <div className="bg-gray-800/50 border border-gray-700 rounded-xl p-5 space-y-4">
+                <div>
+                  <h3 className="font-medium text-white">Rate Limits</h3>
+                  <p className="text-xs text-gray-500 mt-1">
+                    Requests wait in line instead of hitting provider limits. Leave blank to rely on the limits providers
+                    report, which replace these once known.
+                  </p>
+                </div>
+
+                {PROVIDERS.map((provider) => {
+                  const limits = config.rateLimits[provider] ?? {};
+                  const perMinute = (value: string) => parseInt(value, 10) || undefined;
+                  return (
+                    <div key={provider} className="grid grid-cols-3 gap-4 items-center">
+                      <div className="text-sm text-white">{PROVIDER_INFO[provider].name}</div>
+                      <input
+                        type="number"
+                        min={0}
+                        value={limits.requestsPerMinute ?? ""}
+                        onChange={(e) =>
+                          onUpdateRateLimits(provider, { ...limits, requestsPerMinute: perMinute(e.target.value) })
+                        }
+                        placeholder="Requests / min"
+                        className="bg-gray-900 border border-gray-600 rounded-lg px-3 py-2 text-sm
+                          text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                      />
+                      <input
+                        type="number"
+                        min={0}
+                        value={limits.tokensPerMinute ?? ""}
+                        onChange={(e) =>
+                          onUpdateRateLimits(provider, { ...limits, tokensPerMinute: perMinute(e.target.value) })
+                        }
+                        placeholder="Tokens / min"
+                        className="bg-gray-900 border border-gray-600 rounded-lg px-3 py-2 text-sm
+                          text-white placeholder-gray-500 focus:outline-none focus:border-primary transition-all"
+                      />
+                    </div>
+                  );
+                })}
+
+                <button
+                  onClick={handleShowRateLimits}
+                  className="px-3 py-1.5 bg-gray-700 hover:bg-gray-600 text-white rounded-lg text-xs transition-colors"
+                >
+                  Show Current Levels
+                </button>
+                {rateLimitStatus && (
+                  <div className="text-xs space-y-1">
+                    {typeof rateLimitStatus === "string" ? (
+                      <div className="text-gray-400">{rateLimitStatus}</div>
+                    ) : rateLimitStatus.length === 0 ? (
+                      <div className="text-gray-400">No requests made yet</div>
+                    ) : (
+                      rateLimitStatus.map((lane) => (
+                        <div key={lane.host} className="text-gray-400">
+                          <span className="text-gray-300">{lane.host}</span>
+                          {" · "}
+                          {lane.requests
+                            ? `${lane.requests.available}/${lane.requests.limit} requests`
+                            : "no request limit"}
+                          {" · "}
+                          {lane.tokens ? `${lane.tokens.available}/${lane.tokens.limit} tokens` : "no token limit"}
+                          {lane.queued > 0 ? ` · ${lane.queued} waiting` : ""}
+                          {lane.requests?.from_headers || lane.tokens?.from_headers ? " · reported by provider" : ""}
+                        </div>
+                      ))
+                    )}
+                  </div>
+                )}
+              </div>
+
               {/* Default Discussion Length */}
               <div className="bg-gray-800/50 border border-gray-700 rounded-xl p-5">
                 <h3 className="font-medium text-white mb-4">Default Discussion Length</h3>
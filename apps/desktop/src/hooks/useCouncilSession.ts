@@ -109,11 +109,18 @@ export function useCouncilSession(topic: string) {
   const lastModeratorKeyRef = useRef<string | null>(null);
   const moderatorDisplayByIdRef = useRef(new Map<string, { provider: Provider }>());
 
+  const sessionIdRef = useRef(`session_${Date.now()}_${Math.random().toString(36).slice(2)}`);
+
+  // Agent turns go ahead of moderator notes and other sessions' background work
   const transport = useMemo(
     () =>
       createTauriTransport({
         proxy,
         logger: (level, message, details) => apiLogger.log(level, "transport", message, details),
+        priority: "speaking",
+        sessionId: sessionIdRef.current,
+        onRetry: (notice) =>
+          useCouncilSessionStore.getState().setPendingRetry({ ...notice, retryAt: Date.now() + notice.delayMs }),
       }),
     [proxy]
   );
@@ -198,6 +205,8 @@ export function useCouncilSession(topic: string) {
           {
             idleTimeoutMs: 60000,
             requestTimeoutMs: 90000,
+            priority: "background",
+            sessionId: sessionIdRef.current,
           }
         );
 
@@ -8,6 +8,7 @@ import {
   getCouncilMessageById,
   getCouncilMessageIndexById,
   useCouncilSessionStore,
+  type PendingRetry,
 } from "../stores/councilSession";
 import { MessageBubble } from "../components/chat/MessageBubble";
 import { SidePanel } from "../components/chat/SidePanel";
@@ -25,6 +26,26 @@ const DiscordVirtuosoList = forwardRef<HTMLDivElement, HTMLAttributes<HTMLDivEle
   }
 );
 
+/** Countdown to the backend's next attempt; hides itself once the attempt starts */
+function RetryIndicator({ retry }: { retry: PendingRetry }) {
+  const [now, setNow] = useState(() => Date.now());
+
+  useEffect(() => {
+    const timer = window.setInterval(() => setNow(Date.now()), 250);
+    return () => window.clearInterval(timer);
+  }, [retry]);
+
+  const seconds = Math.ceil((retry.retryAt - now) / 1000);
+  if (seconds <= 0) return null;
+  const reason = retry.status === 429 ? "Rate limited" : `Provider error (HTTP ${retry.status})`;
+
+  return (
+    <div className="text-xs text-ink-500 text-center mt-1">
+      {reason}, retrying in {seconds}s (attempt {retry.attempt} of {retry.maxAttempts})
+    </div>
+  );
+}
+
 export function Chat({ topic, onNavigate }: ChatProps) {
   const { config, getMaxTurns } = useConfig();
   const maxTurns = getMaxTurns();
@@ -33,6 +54,7 @@ export function Chat({ topic, onNavigate }: ChatProps) {
 
   const messages = useCouncilSessionStore((s) => s.messages);
   const typingAgents = useCouncilSessionStore((s) => s.typingAgents);
+  const pendingRetry = useCouncilSessionStore((s) => s.pendingRetry);
   const currentTurn = useCouncilSessionStore((s) => s.currentTurn);
   const isRunning = useCouncilSessionStore((s) => s.isRunning);
   const isPaused = useCouncilSessionStore((s) => s.isPaused);
@@ -277,6 +299,7 @@ export function Chat({ topic, onNavigate }: ChatProps) {
               <span className="typing-dot" />
             </span>
           </div>
+          {pendingRetry && <RetryIndicator retry={pendingRetry} />}
         </div>
       )}
     </div>
@@ -98,6 +98,8 @@ export function Home({ onNavigate }: HomeProps) {
     config,
     updateCredential,
     updateProxy,
+    updateTls,
+    updateRateLimits,
     updatePreferences,
     updateModel,
     hasAnyApiKey,
@@ -279,6 +281,8 @@ export function Home({ onNavigate }: HomeProps) {
         config={config}
         onUpdateCredential={updateCredential}
         onUpdateProxy={updateProxy}
+        onUpdateTls={updateTls}
+        onUpdateRateLimits={updateRateLimits}
         onUpdatePreferences={updatePreferences}
         onUpdateModel={updateModel}
       />
@@ -10,7 +10,7 @@ import { ProviderManager } from "@socratic-council/sdk";
 import type { CompletionOptions } from "@socratic-council/sdk";
 
 import type { Provider, ProxyConfig, ProviderCredential } from "../stores/config";
-import { createTauriTransport } from "./tauriTransport";
+import { createTauriTransport, type RequestPriority } from "./tauriTransport";
 
 export interface ChatMessage {
   role: "system" | "user" | "assistant";
@@ -186,6 +186,8 @@ export async function callProvider(
     idleTimeoutMs?: number;
     requestTimeoutMs?: number;
     signal?: AbortSignal;
+    priority?: RequestPriority;
+    sessionId?: string;
   }
 ): Promise<CompletionResult> {
   const startTime = Date.now();
@@ -193,6 +195,8 @@ export async function callProvider(
   const transport = createTauriTransport({
     proxy,
     logger: (level, message, details) => apiLogger.log(level, provider, message, details),
+    priority: options?.priority,
+    sessionId: options?.sessionId,
   });
   const manager = new ProviderManager(buildCredentials(provider, credential), { transport });
   const instance = manager.getProvider(provider);
@@ -5,20 +5,115 @@ import type {
   StreamRequest,
   Transport,
   TransportRequest,
+  TransportRoute,
+  TransportTiming,
 } from "@socratic-council/sdk";
 
+interface TauriHttpError {
+  kind:
+    | "connect"
+    | "dns"
+    | "tls"
+    | "pin_mismatch"
+    | "proxy_auth"
+    | "timeout"
+    | "http_status"
+    | "decode"
+    | "cancelled"
+    | "invalid_config"
+    | "egress_denied"
+    | "request";
+  message: string;
+  phase?: "connect" | "first_byte" | "idle" | "total";
+  status?: number;
+  body?: string;
+}
+
+interface TauriTimingMetrics {
+  queued_ms: number;
+  dns_ms?: number | null;
+  connect_ms?: number | null;
+  tls_ms?: number | null;
+  first_byte_ms?: number | null;
+  first_chunk_ms?: number | null;
+  max_chunk_gap_ms?: number | null;
+  avg_chunk_gap_ms?: number | null;
+  total_ms: number;
+  chunks: number;
+  bytes_sent: number;
+  bytes_received: number;
+}
+
 interface TauriHttpResponse {
   status: number;
   headers: Record<string, string>;
   body: string;
-  error?: string;
+  error?: TauriHttpError;
+  timing?: TauriTimingMetrics;
+  /** Absent when served from the response cache */
+  route?: ProxyRoute | null;
+  cached?: boolean;
 }
 
-interface TauriStreamChunk {
+export interface TauriStreamChunk {
   request_id: string;
+  offset: number;
   chunk: string;
   done: boolean;
-  error?: string;
+  error?: TauriHttpError;
+  /** Time reading paused for the backend delivery queue, not webview processing time */
+  queue_lag_ms?: number;
+  timing?: TauriTimingMetrics | null;
+  /** Route the request took, which may be a failover profile */
+  route?: ProxyRoute | null;
+  /** Place in the backend queue while waiting for a slot, 1 for next */
+  queue_position?: number | null;
+  /** Parsed SSE message of a request made with `sse: true` */
+  event?: TauriSseEvent | null;
+  /** Messages of a coalesced batch, used instead of `event` */
+  events?: TauriSseEvent[] | null;
+  retry?: TauriRetryNotice;
+}
+
+interface TauriRetryNotice {
+  request_id: string;
+  attempt: number;
+  max_attempts: number;
+  delay_ms: number;
+  status: number;
+}
+
+interface TauriQueueNotice {
+  request_id: string;
+  position: number;
+}
+
+/** Retry the backend is about to make after a rate-limited or failed attempt */
+export interface RetryNotice {
+  requestId: string;
+  /** The attempt about to be made, starting at 2 */
+  attempt: number;
+  maxAttempts: number;
+  delayMs: number;
+  /** Status of the attempt that failed */
+  status: number;
+}
+
+interface TauriSseEvent {
+  event: string;
+  data: string;
+  id?: string | null;
+  /** Reconnection time the server asked for, in ms */
+  retry?: number | null;
+}
+
+export interface TauriStreamInfo {
+  request_id: string;
+  url: string;
+  started_at: number;
+  first_offset: number;
+  next_offset: number;
+  done: boolean;
 }
 
 type TransportLogger = (
@@ -27,19 +122,21 @@ type TransportLogger = (
   details?: unknown
 ) => void;
 
-function isTauri(): boolean {
+export function isTauri(): boolean {
   return (
     typeof window !== "undefined" &&
     ("__TAURI__" in window || "__TAURI_INTERNALS__" in window)
   );
 }
 
+/** Invoke a backend command, giving up after `timeoutMs`; `null` waits for as long as it runs */
 async function tauriInvoke<T>(
   cmd: string,
   args: Record<string, unknown>,
-  timeoutMs = 180000
+  timeoutMs: number | null = 180000
 ): Promise<T> {
   const { invoke } = await import("@tauri-apps/api/core");
+  if (timeoutMs === null) return invoke(cmd, args) as Promise<T>;
 
   const result = await Promise.race([
     invoke(cmd, args) as Promise<T>,
@@ -51,20 +148,390 @@ async function tauriInvoke<T>(
   return result;
 }
 
-async function tauriListen(event: string, callback: (payload: unknown) => void): Promise<() => void> {
+async function tauriListen<T>(event: string, handler: (payload: T) => void): Promise<() => void> {
   const { listen } = await import("@tauri-apps/api/event");
-  return listen(event, (e: { payload: unknown }) => callback(e.payload));
+  return listen<T>(event, (e) => handler(e.payload));
+}
+
+function newRequestId() {
+  return `req_${Date.now()}_${Math.random().toString(36).slice(2)}`;
+}
+
+async function tauriChannel<T>(onMessage: (message: T) => void) {
+  const { Channel } = await import("@tauri-apps/api/core");
+  const channel = new Channel<T>();
+  channel.onmessage = onMessage;
+  return channel;
+}
+
+export interface DetectedProxy {
+  source: "environment" | "gnome" | "kde" | "none";
+  http?: string | null;
+  https?: string | null;
+  all?: string | null;
+  no_proxy: string[];
+  pac_url?: string | null;
+}
+
+/** Proxy settings the `system` proxy type would use; passwords are masked */
+export async function detectSystemProxy(): Promise<DetectedProxy | null> {
+  if (!isTauri()) return null;
+  return tauriInvoke<DetectedProxy>("proxy_detect", {});
+}
+
+/** TLS settings for TLS-inspecting gateways and mutual TLS; file paths are read by the backend */
+export interface TlsSettings {
+  /** PEM file of root certificates trusted in addition to the system roots */
+  caFile?: string;
+  /** PEM certificate chain or PKCS#12 (.p12/.pfx) bundle presented for mutual TLS */
+  clientCert?: string;
+  /** PEM private key when `clientCert` doesn't include it */
+  clientKey?: string;
+  /** Password of a PKCS#12 `clientCert` */
+  clientCertPassword?: string;
+  minVersion?: "1.2" | "1.3";
+  /**
+   * Public keys hosts must present; connections presenting none of them fail with `pin_mismatch`.
+   * Held by the backend: apply changes with `syncTlsPins`, requests don't carry them.
+   */
+  pins?: TlsPinSet[];
+}
+
+export interface TlsPinSet {
+  /** Exact host, or `*.example.com` for its subdomains */
+  host: string;
+  /** Base64 SHA-256 of a public key info in the chain, as `sha256/<base64>` */
+  pins: string[];
+  /** Log mismatches instead of blocking the connection */
+  reportOnly?: boolean;
+}
+
+let tlsSettings: TlsSettings | undefined;
+
+/** Apply TLS settings to every backend request made from now on */
+export function setTlsSettings(tls: TlsSettings | undefined): void {
+  tlsSettings = tls;
+}
+
+function buildTlsConfig(tls: TlsSettings | undefined = tlsSettings): Record<string, unknown> | null {
+  if (!tls) return null;
+  const config = {
+    ca_file: tls.caFile || undefined,
+    client_cert: tls.clientCert || undefined,
+    client_key: tls.clientKey || undefined,
+    client_cert_password: tls.clientCertPassword || undefined,
+    min_version: tls.minVersion,
+  };
+  return Object.values(config).some((value) => value !== undefined) ? config : null;
+}
+
+/** Route a request would take under the given proxy settings and their rules */
+export interface ProxyRoute {
+  /** Pattern of the matching rule; null when the global settings apply */
+  rule?: string | null;
+  type: "direct" | ProxyConfig["type"];
+  /** Proxy address without credentials, or the PAC script location */
+  proxy?: string | null;
+}
+
+/** Preview whether `url` goes direct or through which proxy */
+export async function resolveProxyRoute(proxy: ProxyConfig | undefined, url: string): Promise<ProxyRoute | null> {
+  if (!isTauri()) return null;
+  return tauriInvoke<ProxyRoute>("proxy_resolve", { proxy: buildProxyConfig(proxy), url });
+}
+
+/** One staged check from `proxy_test` */
+export interface ProxyDiagnosticStep {
+  name: "route" | "connect" | "proxy_connect" | "proxy_tls" | "proxy_handshake" | "proxy_auth" | "tunnel" | "tls" | "http";
+  status: "ok" | "failed";
+  detail: string;
+  elapsed_ms: number;
+}
+
+export interface ProxyReport {
+  url: string;
+  route: ProxyRoute;
+  /** Checks in the order they ran; the last one failed unless `ok` */
+  steps: ProxyDiagnosticStep[];
+  ok: boolean;
+}
+
+/** Walk each step of reaching `url` through the proxy settings and report where it fails */
+export async function testProxy(proxy: ProxyConfig | undefined, url: string): Promise<ProxyReport | null> {
+  if (!isTauri()) return null;
+  return tauriInvoke<ProxyReport>("proxy_test", { proxy: buildProxyConfig(proxy), tls: buildTlsConfig(), url });
+}
+
+/** One certificate of a presented chain */
+export interface TlsCertificate {
+  subject: string;
+  issuer: string;
+  serial: string;
+  /** Unix timestamps in seconds */
+  not_before: number;
+  not_after: number;
+  dns_names: string[];
+  is_ca: boolean;
+  sha256: string;
+  /** Base64 SHA-256 of the public key info */
+  spki_sha256: string;
+  pem: string;
+}
+
+export interface TlsReport {
+  url: string;
+  version: string;
+  cipher_suite: string;
+  /** Leaf first, in the order the server sent them */
+  chain: TlsCertificate[];
+  /** Whether the chain verified against the system roots and the extra CA file */
+  trusted: boolean;
+  error?: string | null;
+  /** Whether the chain carries a pinned key; null when no pin set covers the host */
+  pinned?: boolean | null;
+}
+
+/** Show the certificate chain `url` presents through the proxy settings, e.g. to find a gateway's CA */
+export async function inspectTls(proxy: ProxyConfig | undefined, url: string): Promise<TlsReport | null> {
+  if (!isTauri()) return null;
+  return tauriInvoke<TlsReport>("tls_inspect", { proxy: buildProxyConfig(proxy), tls: buildTlsConfig(), url });
+}
+
+/** Chain that carried none of the keys pinned for its host */
+export interface PinViolation {
+  host: string;
+  /** Keys the chain carried, leaf first */
+  presented: string[];
+  /** False when a report-only pin set let the connection through */
+  enforced: boolean;
+  /** Unix timestamp in seconds */
+  at: number;
+}
+
+/** Recent certificate pin mismatches, oldest first */
+export async function getPinReports(): Promise<PinViolation[]> {
+  if (!isTauri()) return [];
+  return tauriInvoke<PinViolation[]>("tls_pin_reports", {});
+}
+
+/** Concurrency limits of the backend scheduler */
+export interface SchedulerSettings {
+  maxConcurrent: number;
+  maxPerHost: number;
+}
+
+/** Replace the backend concurrency limits */
+export async function syncScheduler(settings: SchedulerSettings): Promise<void> {
+  if (!isTauri()) return;
+  await tauriInvoke("scheduler_configure", {
+    config: { max_concurrent: settings.maxConcurrent, max_per_host: settings.maxPerHost },
+  });
+}
+
+export interface QueuedRequest {
+  request_id?: string | null;
+  host: string;
+  priority: RequestPriority;
+  session_id?: string | null;
+  /** Place in the queue, 1 for next */
+  position: number;
+}
+
+export interface SchedulerStatus {
+  running: number;
+  max_concurrent: number;
+  /** Waiting requests, next first */
+  queued: QueuedRequest[];
+}
+
+/** Requests running and waiting in the backend scheduler */
+export async function getSchedulerStatus(): Promise<SchedulerStatus | null> {
+  if (!isTauri()) return null;
+  return tauriInvoke<SchedulerStatus>("scheduler_status", {});
+}
+
+/** Turn the backend response cache on or off and set its size limit */
+export async function syncCache(settings: { enabled: boolean; maxBytes?: number }): Promise<void> {
+  if (!isTauri()) return;
+  await tauriInvoke("cache_configure", { config: { enabled: settings.enabled, max_bytes: settings.maxBytes } });
+}
+
+export interface CacheStats {
+  enabled: boolean;
+  entries: number;
+  bytes: number;
+  max_bytes: number;
+  /** Counted since the app started */
+  hits: number;
+  misses: number;
+  evictions: number;
+}
+
+/** Entries, size and hit counts of the backend response cache */
+export async function getCacheStats(): Promise<CacheStats | null> {
+  if (!isTauri()) return null;
+  return tauriInvoke<CacheStats>("cache_stats", {});
+}
+
+/** Delete every response the backend has cached */
+export async function clearCache(): Promise<void> {
+  if (!isTauri()) return;
+  await tauriInvoke("cache_clear", {});
+}
+
+/** Request and token caps for one host, used until its provider reports its own limits */
+export interface RateLimitSetting {
+  host: string;
+  requestsPerMinute?: number;
+  tokensPerMinute?: number;
+}
+
+/** Replace the backend rate limits set in the settings */
+export async function syncRateLimits(limits: RateLimitSetting[]): Promise<void> {
+  if (!isTauri()) return;
+  await tauriInvoke("rate_limit_configure", {
+    limits: limits.map(({ host, requestsPerMinute, tokensPerMinute }) => ({
+      host,
+      requests_per_minute: requestsPerMinute,
+      tokens_per_minute: tokensPerMinute,
+    })),
+  });
+}
+
+export interface RateLimitBucket {
+  /** Capacity, refilled evenly over a minute */
+  limit: number;
+  available: number;
+  /** Whether the provider reported the limit, rather than the settings */
+  from_headers: boolean;
 }
 
-function buildProxyConfig(proxy?: ProxyConfig) {
-  if (!proxy || proxy.type === "none") return null;
-  if (!proxy.host || !proxy.port || proxy.port <= 0) return null;
+/** Buckets of one host; a bucket is null while the host has no such limit */
+export interface RateLimitStatus {
+  host: string;
+  requests: RateLimitBucket | null;
+  tokens: RateLimitBucket | null;
+  /** Requests waiting for capacity */
+  queued: number;
+}
+
+/** Current rate limit buckets per host */
+export async function getRateLimitStatus(): Promise<RateLimitStatus[]> {
+  if (!isTauri()) return [];
+  return tauriInvoke<RateLimitStatus[]>("rate_limit_status", {});
+}
+
+/**
+ * Replace the backend egress allowlist and the PAC script files it may read; built-in provider
+ * endpoints stay allowed. Narrowing applies at once; destinations and files not allowed before
+ * are only added once the user confirms them in a native dialog, and rejects with
+ * `egress_denied` when declined.
+ */
+export async function syncEgressPolicy(allow: string[], pacFiles: string[], allowPrivate: boolean): Promise<void> {
+  if (!isTauri()) return;
+  await tauriInvoke("egress_set_policy", { policy: { allow, pac_files: pacFiles, allow_private: allowPrivate } });
+}
+
+/**
+ * Replace the backend's certificate pin sets. Added pins apply at once; relaxing a host's pins
+ * applies only once the user confirms it in a native dialog, and rejects when declined.
+ */
+export async function syncTlsPins(pins: TlsPinSet[]): Promise<void> {
+  if (!isTauri()) return;
+  await tauriInvoke("tls_set_pins", {
+    pins: pins.map(({ host, pins, reportOnly }) => ({ host, pins, report_only: reportOnly ?? false })),
+  });
+}
+
+/** Let the user pick where a `file` response is saved; the path is valid as `save_path` for one request */
+export async function chooseSavePath(fileName?: string): Promise<string | null> {
+  if (!isTauri()) return null;
+  return tauriInvoke<string | null>("http_choose_save_path", { fileName }, 24 * 60 * 60 * 1000);
+}
+
+/** Streams the backend is still running or has recently finished, e.g. after a reload */
+export async function listTauriStreams(): Promise<TauriStreamInfo[]> {
+  if (!isTauri()) return [];
+  return tauriInvoke<TauriStreamInfo[]>("http_stream_list", {});
+}
+
+/** Reattach to a backend stream, replaying buffered chunks from `fromOffset` on */
+export async function attachTauriStream(
+  requestId: string,
+  fromOffset: number,
+  onChunk: (chunk: TauriStreamChunk) => void
+): Promise<TauriStreamInfo> {
+  const onEvent = await tauriChannel<TauriStreamChunk>(onChunk);
+  return tauriInvoke<TauriStreamInfo>("http_stream_attach", { requestId, fromOffset, onEvent });
+}
+
+/**
+ * Cancel streams a previous page load started. Discussions don't survive a reload, so nothing
+ * can reattach to them and they would otherwise keep running, and billing, until they finish.
+ */
+export async function cancelOrphanedTauriStreams(): Promise<void> {
+  if (!isTauri()) return;
+  const pageLoadedAt = Math.floor(performance.timeOrigin);
+  const orphaned = (await listTauriStreams()).filter((s) => !s.done && s.started_at < pageLoadedAt);
+  await Promise.all(orphaned.map((s) => tauriInvoke("http_cancel", { requestId: s.request_id })));
+}
+
+function toTransportTiming(timing: TauriTimingMetrics): TransportTiming {
+  return {
+    queuedMs: timing.queued_ms,
+    dnsMs: timing.dns_ms ?? undefined,
+    connectMs: timing.connect_ms ?? undefined,
+    tlsMs: timing.tls_ms ?? undefined,
+    firstByteMs: timing.first_byte_ms ?? undefined,
+    firstChunkMs: timing.first_chunk_ms ?? undefined,
+    maxChunkGapMs: timing.max_chunk_gap_ms ?? undefined,
+    avgChunkGapMs: timing.avg_chunk_gap_ms ?? undefined,
+    totalMs: timing.total_ms,
+    chunks: timing.chunks,
+    bytesSent: timing.bytes_sent,
+    bytesReceived: timing.bytes_received,
+  };
+}
+
+function toTransportRoute(route: ProxyRoute): TransportRoute {
+  return {
+    type: route.type,
+    proxy: route.proxy ?? undefined,
+    rule: route.rule ?? undefined,
+  };
+}
+
+function buildProxyConfig(proxy?: ProxyConfig): Record<string, unknown> | null {
+  if (!proxy) return null;
+  const rules = proxy.rules?.map((rule) => ({ match: rule.match, proxy: buildProxyConfig(rule.proxy) }));
+  const failover = proxy.failover?.map((profile) => buildProxyConfig(profile) ?? { type: "none", host: "", port: 0 });
+  // Rules and failover profiles still apply when these settings connect directly
+  const direct = rules?.length || failover?.length ? { type: "none", host: "", port: 0, rules, failover } : null;
+
+  if (proxy.type === "none") return direct;
+  if (proxy.type === "system") return { type: "system", host: "", port: 0, rules, failover };
+  if (proxy.type === "pac") {
+    if (!proxy.pacUrl) return direct;
+    return {
+      type: "pac",
+      host: "",
+      port: 0,
+      username: proxy.username,
+      password: proxy.password,
+      pac_url: proxy.pacUrl,
+      rules,
+      failover,
+    };
+  }
+  if (!proxy.host || !proxy.port || proxy.port <= 0) return direct;
   return {
     type: proxy.type,
     host: proxy.host,
     port: proxy.port,
     username: proxy.username,
     password: proxy.password,
+    rules,
+    failover,
   };
 }
 
@@ -72,15 +539,79 @@ function toTransportFailure(code: TransportErrorCode, message: string, details?:
   return new TransportFailure(code, message, details);
 }
 
-export function createTauriTransport(options: { proxy?: ProxyConfig; logger?: TransportLogger } = {}): Transport {
+function isTauriHttpError(error: unknown): error is TauriHttpError {
+  return typeof error === "object" && error !== null && "kind" in error && "message" in error;
+}
+
+/** Whether the backend rejected `cmd` because it doesn't register it, e.g. an older build */
+function isMissingCommand(error: unknown, cmd: string): boolean {
+  const message = typeof error === "string" ? error : error instanceof Error ? error.message : "";
+  return message.includes(cmd) && /not found/i.test(message);
+}
+
+function fromTauriError(error: TauriHttpError, fallbackCode: TransportErrorCode) {
+  switch (error.kind) {
+    case "cancelled":
+      return toTransportFailure("ABORTED", error.message, error);
+    case "timeout":
+      return toTransportFailure(
+        error.phase === "idle" ? "STREAM_IDLE_TIMEOUT" : "STREAM_TIMEOUT",
+        error.message,
+        error
+      );
+    case "http_status":
+      return new TransportFailure("HTTP_ERROR", error.message, error, error.status);
+    default:
+      return toTransportFailure(fallbackCode, error.message, error);
+  }
+}
+
+/** Scheduling class when the backend's concurrency limits make requests wait */
+export type RequestPriority = "speaking" | "bidding" | "background";
+
+export function createTauriTransport(
+  options: {
+    proxy?: ProxyConfig;
+    logger?: TransportLogger;
+    /** Priority of every request made through this transport; `bidding` when absent */
+    priority?: RequestPriority;
+    /** Discussion the requests belong to, so busy discussions can't starve the others */
+    sessionId?: string;
+    /** Called before the backend waits to retry a rate-limited or overloaded request */
+    onRetry?: (notice: RetryNotice) => void;
+  } = {}
+): Transport {
   const logger = options.logger;
   const proxy = options.proxy;
+  const scheduling = { priority: options.priority, session_id: options.sessionId };
+
+  const notifyRetry = (notice: TauriRetryNotice) => {
+    logger?.("info", "Retrying Tauri request", notice);
+    options.onRetry?.({
+      requestId: notice.request_id,
+      attempt: notice.attempt,
+      maxAttempts: notice.max_attempts,
+      delayMs: notice.delay_ms,
+      status: notice.status,
+    });
+  };
 
   const request = async (req: TransportRequest) => {
     if (!isTauri()) {
       throw toTransportFailure("FETCH_REQUEST_FAILED", "Not running in Tauri environment");
     }
 
+    // Non-stream requests report retries and queue positions on global events, keyed by request id
+    const requestId = newRequestId();
+    const unlistenRetry = await tauriListen<TauriRetryNotice>("http-retry", (notice) => {
+      if (notice.request_id === requestId) notifyRetry(notice);
+    });
+    const unlistenQueue = await tauriListen<TauriQueueNotice>("http-queue", (notice) => {
+      if (notice.request_id !== requestId) return;
+      logger?.("debug", "Tauri request queued", { position: notice.position });
+      req.onQueued?.(notice.position);
+    });
+
     try {
       const result = await tauriInvoke<TauriHttpResponse>(
         "http_request",
@@ -91,20 +622,34 @@ export function createTauriTransport(options: { proxy?: ProxyConfig; logger?: Tr
             headers: req.headers,
             body: req.body,
             proxy: buildProxyConfig(proxy),
+            tls: buildTlsConfig(),
             timeout_ms: req.timeoutMs ?? 180000,
+            request_id: requestId,
+            retry: {},
+            ...scheduling,
+            cache: req.cache ? { ttl_ms: req.cache.ttlMs, vary: req.cache.varyHeaders ?? [] } : undefined,
           },
         },
         (req.timeoutMs ?? 180000) + 5000
       );
 
       if (result.error) {
-        throw new Error(result.error);
+        throw result.error;
       }
 
-      return { status: result.status, headers: result.headers, body: result.body };
+      const timing = result.timing ? toTransportTiming(result.timing) : undefined;
+      logger?.("debug", "Tauri request timing", timing);
+      const route = result.route ? toTransportRoute(result.route) : undefined;
+      return { status: result.status, headers: result.headers, body: result.body, timing, route, cached: result.cached };
     } catch (error) {
       logger?.("error", "Tauri request failed", error);
+      if (isTauriHttpError(error)) {
+        throw fromTauriError(error, "FETCH_REQUEST_FAILED");
+      }
       throw toTransportFailure("FETCH_REQUEST_FAILED", "Tauri request failed", error);
+    } finally {
+      unlistenRetry();
+      unlistenQueue();
     }
   };
 
@@ -114,28 +659,21 @@ export function createTauriTransport(options: { proxy?: ProxyConfig; logger?: Tr
       return;
     }
 
-    const timeoutMs = req.timeoutMs ?? 180000;
+    // No total deadline unless the caller asks for one; the backend's first-byte and idle ones apply
+    const timeoutMs = req.timeoutMs;
     const idleTimeoutMs = req.idleTimeoutMs ?? 120000;
     let finished = false;
-    let unlisten: (() => void) | null = null;
-    let hardTimeoutTimer: ReturnType<typeof setTimeout> | null = null;
-    let idleTimer: ReturnType<typeof setInterval> | null = null;
-    let lastChunkAt = Date.now();
-    const requestId = `req_${Date.now()}_${Math.random().toString(36).slice(2)}`;
+    const requestId = newRequestId();
+
+    const onAbort = () => {
+      void tauriInvoke("http_cancel", { requestId }).catch((error) => {
+        logger?.("warn", "Failed to cancel Tauri stream", error);
+      });
+      finishError(toTransportFailure("ABORTED", "Request aborted"));
+    };
 
     const cleanup = () => {
-      if (hardTimeoutTimer) {
-        clearTimeout(hardTimeoutTimer);
-        hardTimeoutTimer = null;
-      }
-      if (idleTimer) {
-        clearInterval(idleTimer);
-        idleTimer = null;
-      }
-      if (unlisten) {
-        unlisten();
-        unlisten = null;
-      }
+      req.signal?.removeEventListener("abort", onAbort);
     };
 
     const finishError = (failure: TransportFailure) => {
@@ -152,41 +690,66 @@ export function createTauriTransport(options: { proxy?: ProxyConfig; logger?: Tr
       handlers.onDone();
     };
 
-    hardTimeoutTimer = setTimeout(() => {
-      finishError(
-        toTransportFailure("STREAM_TIMEOUT", `Request timed out after ${timeoutMs}ms`)
-      );
-    }, timeoutMs);
+    if (req.signal?.aborted) {
+      finishError(toTransportFailure("ABORTED", "Request aborted"));
+      return;
+    }
+    req.signal?.addEventListener("abort", onAbort, { once: true });
 
-    idleTimer = setInterval(() => {
-      if (finished) return;
-      const idleMs = Date.now() - lastChunkAt;
-      if (idleMs >= idleTimeoutMs) {
-        finishError(
-          toTransportFailure("STREAM_IDLE_TIMEOUT", `No data for ${idleTimeoutMs}ms`)
-        );
+    // Offset of the next chunk, so a reattached channel neither skips nor repeats any
+    let nextOffset = 0;
+
+    const onChunk = (chunk: TauriStreamChunk) => {
+      if (chunk.offset < nextOffset) return;
+      nextOffset = chunk.offset + 1;
+
+      if (chunk.retry) {
+        notifyRetry(chunk.retry);
+        return;
       }
-    }, 5000);
 
-    try {
-      unlisten = await tauriListen("http-stream-chunk", (payload: unknown) => {
-        const chunk = payload as TauriStreamChunk;
-        if (chunk.request_id !== requestId) return;
+      if (chunk.queue_position) {
+        logger?.("debug", "Tauri stream queued", { position: chunk.queue_position });
+        handlers.onQueued?.(chunk.queue_position);
+        return;
+      }
 
-        if (chunk.error) {
-          finishError(toTransportFailure("FETCH_STREAM_FAILED", chunk.error));
-          return;
-        }
+      if (chunk.queue_lag_ms) {
+        logger?.("warn", "Tauri stream queue is lagging", { queueLagMs: chunk.queue_lag_ms });
+      }
 
-        if (chunk.chunk) {
-          lastChunkAt = Date.now();
-          handlers.onChunk(chunk.chunk);
-        }
+      if (chunk.timing) {
+        const timing = toTransportTiming(chunk.timing);
+        logger?.("debug", "Tauri stream timing", timing);
+        handlers.onTiming?.(timing);
+      }
 
-        if (chunk.done) {
-          finishDone();
-        }
-      });
+      if (chunk.route) {
+        logger?.("debug", "Tauri stream route", chunk.route);
+        handlers.onRoute?.(toTransportRoute(chunk.route));
+      }
+
+      if (chunk.error) {
+        finishError(fromTauriError(chunk.error, "FETCH_STREAM_FAILED"));
+        return;
+      }
+
+      for (const event of chunk.events ?? (chunk.event ? [chunk.event] : [])) {
+        handlers.onEvent?.({ event: event.event, data: event.data, id: event.id ?? undefined });
+      }
+
+      if (chunk.chunk) {
+        handlers.onChunk(chunk.chunk);
+      }
+
+      if (chunk.done) {
+        finishDone();
+      }
+    };
+
+    try {
+      // Per-request channel: chunks arrive in order and only for this request
+      const onEvent = await tauriChannel<TauriStreamChunk>(onChunk);
 
       await tauriInvoke(
         "http_request_stream",
@@ -197,14 +760,46 @@ export function createTauriTransport(options: { proxy?: ProxyConfig; logger?: Tr
             headers: req.headers,
             body: req.body,
             proxy: buildProxyConfig(proxy),
+            tls: buildTlsConfig(),
             timeout_ms: timeoutMs,
+            idle_timeout_ms: idleTimeoutMs,
             stream: true,
             request_id: requestId,
+            retry: {},
+            // Parse SSE in the backend when the caller can take parsed messages
+            sse: Boolean(handlers.onEvent),
+            ...scheduling,
+            // Batch tiny chunks to roughly one emission per frame
+            coalesce: { max_latency_ms: 16 },
           },
+          onEvent,
         },
-        timeoutMs + 10000
+        // The backend ends the stream on its own deadlines and reports them as chunks
+        null
       );
     } catch (error) {
+      // The backend already reported a terminal event (e.g. a timeout); don't re-send
+      if (finished) return;
+
+      // Anything but a missing command may have reached the provider, so re-sending could bill twice
+      if (!isMissingCommand(error, "http_request_stream")) {
+        logger?.("error", "Tauri stream failed", error);
+        // The backend may still be streaming, e.g. when only the IPC call failed; pick up where we left off
+        if (!isTauriHttpError(error)) {
+          const info = await attachTauriStream(requestId, nextOffset, onChunk).catch(() => null);
+          if (info) {
+            logger?.("info", "Reattached to Tauri stream", info);
+            return;
+          }
+        }
+        finishError(
+          isTauriHttpError(error)
+            ? fromTauriError(error, "FETCH_STREAM_FAILED")
+            : toTransportFailure("FETCH_STREAM_FAILED", "Tauri stream failed", error)
+        );
+        return;
+      }
+
       const failure = toTransportFailure("FETCH_STREAM_FAILED", "Tauri stream failed", error);
       handlers.onFallback?.(failure);
 
@@ -1,6 +1,8 @@
 import type { Citation, SearchResult, VerificationResult } from "@socratic-council/shared";
 import { DuckDuckGoOracle } from "@socratic-council/core";
+import type { ProxyConfig } from "../stores/config";
 import { apiLogger } from "./api";
+import { createTauriTransport, isTauri } from "./tauriTransport";
 
 export type ToolName = "oracle.search" | "oracle.verify" | "oracle.cite";
 
@@ -19,7 +21,19 @@ export interface ToolResult {
 const TOOL_TIMEOUT_MS = 12000;
 const MAX_RESULTS = 5;
 
-const oracle = new DuckDuckGoOracle();
+/**
+ * Lookups go through the backend like provider calls, so they wait for a scheduler slot, honor
+ * the proxy settings and may be answered from the response cache. Outside Tauri they use fetch.
+ */
+function createOracle(proxy?: ProxyConfig): DuckDuckGoOracle {
+  if (!isTauri()) return new DuckDuckGoOracle();
+  const transport = createTauriTransport({
+    proxy,
+    priority: "background",
+    logger: (level, message, details) => apiLogger.log(level, "tools", message, details),
+  });
+  return new DuckDuckGoOracle({ transport });
+}
 
 const TOOL_DEFINITIONS: Array<{
   name: ToolName;
@@ -98,7 +112,8 @@ function formatVerification(result: VerificationResult): string {
   ].join("\n\n");
 }
 
-export async function runToolCall(call: ToolCall): Promise<ToolResult> {
+export async function runToolCall(call: ToolCall, proxy?: ProxyConfig): Promise<ToolResult> {
+  const oracle = createOracle(proxy);
   try {
     switch (call.name) {
       case "oracle.search": {
@@ -8,9 +8,20 @@
  */
 
 import { useState, useEffect, useCallback } from "react";
+import {
+  setTlsSettings,
+  syncTlsPins,
+  syncEgressPolicy,
+  syncRateLimits,
+  syncScheduler,
+  syncCache,
+  type RateLimitSetting,
+  type TlsPinSet,
+  type TlsSettings,
+} from "../services/tauriTransport";
 
 export type Provider = "openai" | "anthropic" | "google" | "deepseek" | "kimi";
-export type ProxyType = "none" | "http" | "https" | "socks5" | "socks5h";
+export type ProxyType = "none" | "http" | "https" | "socks5" | "socks5h" | "system" | "pac";
 
 export interface ProxyConfig {
   type: ProxyType;
@@ -18,6 +29,19 @@ export interface ProxyConfig {
   port: number;
   username?: string;
   password?: string;
+  /** PAC script URL or absolute file path for the `pac` type */
+  pacUrl?: string;
+  /** Per-host overrides, checked in order before the settings above apply */
+  rules?: ProxyRule[];
+  /** Profiles tried in order when connecting through these settings fails; `none` connects directly */
+  failover?: ProxyConfig[];
+}
+
+export interface ProxyRule {
+  /** Host glob such as `*.openai.com`, or a CIDR range such as `192.168.0.0/16` */
+  match: string;
+  /** Proxy for matching hosts; a direct connection when absent */
+  proxy?: ProxyConfig;
 }
 
 export interface ProviderCredential {
@@ -34,6 +58,20 @@ export interface DiscussionPreferences {
   autoScroll: boolean;
   soundEffects: boolean;
   moderatorEnabled: boolean;
+  /** Let custom base URLs and MCP servers point at localhost or LAN addresses */
+  allowPrivateNetworks: boolean;
+  /** Backend requests in flight at once; more wait in line by priority */
+  maxConcurrentRequests: number;
+  /** Backend requests in flight at once to any one host */
+  maxRequestsPerHost: number;
+  /** Keep search results and other cacheable responses on disk, so replays don't refetch them */
+  responseCache: boolean;
+}
+
+/** Caps used until the provider reports its own limits in response headers */
+export interface RateLimits {
+  requestsPerMinute?: number;
+  tokensPerMinute?: number;
 }
 
 export interface McpConfig {
@@ -45,6 +83,8 @@ export interface McpConfig {
 export interface AppConfig {
   credentials: Partial<Record<Provider, ProviderCredential>>;
   proxy: ProxyConfig;
+  tls: TlsSettings;
+  rateLimits: Partial<Record<Provider, RateLimits>>;
   preferences: DiscussionPreferences;
   models: Partial<Record<Provider, string>>;
   mcp: McpConfig;
@@ -60,6 +100,8 @@ const DEFAULT_CONFIG: AppConfig = {
     host: "",
     port: 0,
   },
+  tls: {},
+  rateLimits: {},
   preferences: {
     defaultLength: "standard",
     customTurns: 100,
@@ -67,6 +109,10 @@ const DEFAULT_CONFIG: AppConfig = {
     autoScroll: true,
     soundEffects: false,
     moderatorEnabled: true,
+    allowPrivateNetworks: false,
+    maxConcurrentRequests: 12,
+    maxRequestsPerHost: 6,
+    responseCache: false,
   },
   models: {
     openai: "gpt-5.2",
@@ -82,7 +128,7 @@ const DEFAULT_CONFIG: AppConfig = {
   },
 };
 
-const VALID_PROXY_TYPES: ProxyType[] = ["none", "http", "https", "socks5", "socks5h"];
+const VALID_PROXY_TYPES: ProxyType[] = ["none", "http", "https", "socks5", "socks5h", "system", "pac"];
 
 function normalizeProxyConfig(input?: Partial<ProxyConfig>): ProxyConfig {
   const type = VALID_PROXY_TYPES.includes(input?.type as ProxyType)
@@ -96,6 +142,20 @@ function normalizeProxyConfig(input?: Partial<ProxyConfig>): ProxyConfig {
     Number.isFinite(parsedPort) && parsedPort > 0 && parsedPort <= 65535 ? parsedPort : 0;
   const username = typeof input?.username === "string" && input.username !== "" ? input.username : undefined;
   const password = typeof input?.password === "string" && input.password !== "" ? input.password : undefined;
+  const pacUrl = typeof input?.pacUrl === "string" && input.pacUrl.trim() !== "" ? input.pacUrl.trim() : undefined;
+  const rules = Array.isArray(input?.rules)
+    ? input.rules
+        .filter((rule) => typeof rule?.match === "string" && rule.match.trim() !== "")
+        .map((rule) => ({
+          match: rule.match.trim(),
+          ...(rule.proxy ? { proxy: normalizeProxyConfig({ ...rule.proxy, rules: undefined }) } : {}),
+        }))
+    : [];
+  const failover = Array.isArray(input?.failover)
+    ? input.failover
+        .filter((profile) => typeof profile === "object" && profile !== null)
+        .map((profile) => normalizeProxyConfig({ ...profile, rules: undefined, failover: undefined }))
+    : [];
 
   return {
     type,
@@ -103,9 +163,148 @@ function normalizeProxyConfig(input?: Partial<ProxyConfig>): ProxyConfig {
     port,
     ...(username ? { username } : {}),
     ...(password ? { password } : {}),
+    ...(pacUrl ? { pacUrl } : {}),
+    ...(rules.length > 0 ? { rules } : {}),
+    ...(failover.length > 0 ? { failover } : {}),
+  };
+}
+
+function normalizeTlsSettings(input?: Partial<TlsSettings>): TlsSettings {
+  const text = (value: unknown) => (typeof value === "string" && value.trim() !== "" ? value.trim() : undefined);
+  const caFile = text(input?.caFile);
+  const clientCert = text(input?.clientCert);
+  const clientKey = text(input?.clientKey);
+  const clientCertPassword = typeof input?.clientCertPassword === "string" ? input.clientCertPassword : undefined;
+  const minVersion = input?.minVersion === "1.2" || input?.minVersion === "1.3" ? input.minVersion : undefined;
+  const pins = Array.isArray(input?.pins)
+    ? input.pins
+        .filter((set) => typeof set?.host === "string" && set.host.trim() !== "" && Array.isArray(set.pins))
+        .map((set) => ({
+          host: set.host.trim(),
+          pins: set.pins.filter((pin) => typeof pin === "string" && pin.trim() !== "").map((pin) => pin.trim()),
+          ...(set.reportOnly ? { reportOnly: true } : {}),
+        }))
+        .filter((set) => set.pins.length > 0)
+    : [];
+
+  return {
+    ...(caFile ? { caFile } : {}),
+    ...(clientCert ? { clientCert } : {}),
+    ...(clientKey ? { clientKey } : {}),
+    ...(clientCertPassword ? { clientCertPassword } : {}),
+    ...(minVersion ? { minVersion } : {}),
+    ...(pins.length > 0 ? { pins } : {}),
   };
 }
 
+function normalizeRateLimits(input?: Partial<Record<Provider, RateLimits>>): Partial<Record<Provider, RateLimits>> {
+  const perMinute = (value: unknown) =>
+    typeof value === "number" && Number.isFinite(value) && value > 0 ? Math.floor(value) : undefined;
+  const limits: Partial<Record<Provider, RateLimits>> = {};
+  for (const [provider, value] of Object.entries(input ?? {})) {
+    const requestsPerMinute = perMinute(value?.requestsPerMinute);
+    const tokensPerMinute = perMinute(value?.tokensPerMinute);
+    if (!requestsPerMinute && !tokensPerMinute) continue;
+    limits[provider as Provider] = {
+      ...(requestsPerMinute ? { requestsPerMinute } : {}),
+      ...(tokensPerMinute ? { tokensPerMinute } : {}),
+    };
+  }
+  return limits;
+}
+
+/**
+ * Parse pin sets written one host per line as `<host> <pin> [<pin> ...] [report-only]`,
+ * where each pin is `sha256/<base64>` as shown by certificate inspection
+ */
+export function parseTlsPins(text: string): TlsPinSet[] {
+  const sets: TlsPinSet[] = [];
+  for (const line of text.split("\n")) {
+    const [host, ...rest] = line.trim().split(/\s+/);
+    if (!host || host.startsWith("#")) continue;
+    const reportOnly = rest.includes("report-only");
+    const pins = rest.filter((word) => word !== "report-only");
+    if (pins.length === 0) continue;
+    sets.push(reportOnly ? { host, pins, reportOnly } : { host, pins });
+  }
+  return sets;
+}
+
+/** Inverse of `parseTlsPins` */
+export function formatTlsPins(sets: TlsPinSet[] = []): string {
+  return sets.map(({ host, pins, reportOnly }) => [host, ...pins, ...(reportOnly ? ["report-only"] : [])].join(" ")).join("\n");
+}
+
+/**
+ * Parse routing rules written one per line as `<host glob or CIDR> <route>`, where the
+ * route is `direct`, `system`, `pac:<url or path>` or `<type>://[user:pass@]host:port`
+ */
+export function parseProxyRules(text: string): ProxyRule[] {
+  const rules: ProxyRule[] = [];
+  for (const line of text.split("\n")) {
+    const [match, route] = line.trim().split(/\s+/, 2);
+    if (!match || match.startsWith("#")) continue;
+    const proxy = parseProxyRoute(route ?? "direct");
+    if (proxy === null) continue;
+    rules.push(proxy ? { match, proxy } : { match });
+  }
+  return rules;
+}
+
+/** Inverse of `parseProxyRules`, with passwords included */
+export function formatProxyRules(rules: ProxyRule[] = []): string {
+  return rules.map(({ match, proxy }) => `${match} ${formatProxyRoute(proxy)}`).join("\n");
+}
+
+/** Parse failover profiles written one route per line, in the route format of `parseProxyRules` */
+export function parseProxyFailover(text: string): ProxyConfig[] {
+  const profiles: ProxyConfig[] = [];
+  for (const line of text.split("\n")) {
+    const route = line.trim();
+    if (!route || route.startsWith("#")) continue;
+    const proxy = parseProxyRoute(route);
+    if (proxy === null) continue;
+    profiles.push(proxy ?? { type: "none", host: "", port: 0 });
+  }
+  return profiles;
+}
+
+/** Inverse of `parseProxyFailover`, with passwords included */
+export function formatProxyFailover(profiles: ProxyConfig[] = []): string {
+  return profiles.map(formatProxyRoute).join("\n");
+}
+
+/** Proxy for one route; undefined for `direct` and null when the route is invalid */
+function parseProxyRoute(route: string): ProxyConfig | undefined | null {
+  if (route.toLowerCase() === "direct") return undefined;
+  if (route === "system") return { type: "system", host: "", port: 0 };
+  if (route.startsWith("pac:")) return { type: "pac", host: "", port: 0, pacUrl: route.slice(4) };
+  try {
+    const url = new URL(route);
+    const type = url.protocol.replace(/:$/, "") as ProxyType;
+    if (!VALID_PROXY_TYPES.includes(type)) return null;
+    return normalizeProxyConfig({
+      type,
+      host: url.hostname,
+      port: parseInt(url.port, 10),
+      username: decodeURIComponent(url.username),
+      password: decodeURIComponent(url.password),
+    });
+  } catch {
+    return null;
+  }
+}
+
+function formatProxyRoute(proxy?: ProxyConfig): string {
+  if (!proxy || proxy.type === "none") return "direct";
+  if (proxy.type === "system") return "system";
+  if (proxy.type === "pac") return `pac:${proxy.pacUrl ?? ""}`;
+  const auth = proxy.username
+    ? `${encodeURIComponent(proxy.username)}${proxy.password ? `:${encodeURIComponent(proxy.password)}` : ""}@`
+    : "";
+  return `${proxy.type}://${auth}${proxy.host}:${proxy.port}`;
+}
+
 const STORAGE_KEY = "socratic-council-config";
 
 // Discussion length presets (in turns)
@@ -127,6 +326,8 @@ export function loadConfig(): AppConfig {
       const merged: AppConfig = {
         credentials: parsed.credentials ?? {},
         proxy: normalizeProxyConfig({ ...DEFAULT_CONFIG.proxy, ...parsed.proxy }),
+        tls: normalizeTlsSettings(parsed.tls),
+        rateLimits: normalizeRateLimits(parsed.rateLimits),
         preferences: { ...DEFAULT_CONFIG.preferences, ...parsed.preferences },
         models: { ...DEFAULT_CONFIG.models, ...parsed.models },
         mcp: { ...DEFAULT_CONFIG.mcp, ...parsed.mcp },
@@ -168,6 +369,51 @@ export function saveConfig(config: AppConfig): void {
   }
 }
 
+/** PAC script locations of the proxy settings, including those of rules and failover profiles */
+function pacUrls(proxy: ProxyConfig | undefined): string[] {
+  if (!proxy) return [];
+  const nested = [...(proxy.rules ?? []).map((rule) => rule.proxy), ...(proxy.failover ?? [])];
+  return [...(proxy.type === "pac" && proxy.pacUrl ? [proxy.pacUrl] : []), ...nested.flatMap(pacUrls)];
+}
+
+/** Origins the backend may reach besides the built-in provider endpoints */
+export function egressAllowlist(config: AppConfig): string[] {
+  const urls = Object.values(config.credentials).map((credential) => credential?.baseUrl);
+  if (config.mcp.enabled) urls.push(config.mcp.serverUrl);
+  // Scripts read from disk aren't network destinations; they are listed by `egressPacFiles`
+  urls.push(...pacUrls(config.proxy).filter((url) => /^https?:/i.test(url)));
+
+  const origins = new Set<string>();
+  for (const url of urls) {
+    if (!url) continue;
+    try {
+      origins.add(new URL(url).origin);
+    } catch {
+      // Invalid URLs can't be requested anyway
+    }
+  }
+  return [...origins];
+}
+
+/** PAC scripts the proxy settings read from disk, which the backend only loads once allowed */
+export function egressPacFiles(config: AppConfig): string[] {
+  return [...new Set(pacUrls(config.proxy).filter((url) => !/^https?:/i.test(url)))];
+}
+
+/** Rate limits per provider host, using custom base URLs where set */
+export function rateLimitSettings(config: AppConfig): RateLimitSetting[] {
+  const settings: RateLimitSetting[] = [];
+  for (const [provider, limits] of Object.entries(config.rateLimits) as [Provider, RateLimits][]) {
+    const baseUrl = config.credentials[provider]?.baseUrl || PROVIDER_INFO[provider].defaultBaseUrl;
+    try {
+      settings.push({ host: new URL(baseUrl).hostname, ...limits });
+    } catch {
+      // Invalid URLs can't be requested anyway
+    }
+  }
+  return settings;
+}
+
 export function useConfig() {
   const [config, setConfigState] = useState<AppConfig>(() => loadConfig());
 
@@ -175,6 +421,47 @@ export function useConfig() {
     saveConfig(config);
   }, [config]);
 
+  const allowlist = egressAllowlist(config).join(" ");
+  const pacFiles = JSON.stringify(egressPacFiles(config));
+  const allowPrivateNetworks = config.preferences.allowPrivateNetworks;
+  useEffect(() => {
+    syncEgressPolicy(allowlist ? allowlist.split(" ") : [], JSON.parse(pacFiles), allowPrivateNetworks).catch((error) => {
+      console.error("Failed to update egress policy:", error);
+    });
+  }, [allowlist, pacFiles, allowPrivateNetworks]);
+
+  useEffect(() => {
+    setTlsSettings(config.tls);
+  }, [config.tls]);
+
+  const pins = JSON.stringify(config.tls.pins ?? []);
+  useEffect(() => {
+    syncTlsPins(JSON.parse(pins)).catch((error) => {
+      console.error("Failed to update certificate pins:", error);
+    });
+  }, [pins]);
+
+  const { maxConcurrentRequests, maxRequestsPerHost } = config.preferences;
+  useEffect(() => {
+    syncScheduler({ maxConcurrent: maxConcurrentRequests, maxPerHost: maxRequestsPerHost }).catch((error) => {
+      console.error("Failed to update request scheduler:", error);
+    });
+  }, [maxConcurrentRequests, maxRequestsPerHost]);
+
+  const responseCache = config.preferences.responseCache;
+  useEffect(() => {
+    syncCache({ enabled: responseCache }).catch((error) => {
+      console.error("Failed to update response cache:", error);
+    });
+  }, [responseCache]);
+
+  const rateLimits = JSON.stringify(rateLimitSettings(config));
+  useEffect(() => {
+    syncRateLimits(JSON.parse(rateLimits)).catch((error) => {
+      console.error("Failed to update rate limits:", error);
+    });
+  }, [rateLimits]);
+
   const setConfig = useCallback((updater: AppConfig | ((prev: AppConfig) => AppConfig)) => {
     setConfigState(updater);
   }, []);
@@ -195,6 +482,17 @@ export function useConfig() {
     setConfigState((prev) => ({ ...prev, proxy: normalizeProxyConfig(proxy) }));
   }, []);
 
+  const updateTls = useCallback((tls: TlsSettings) => {
+    setConfigState((prev) => ({ ...prev, tls: normalizeTlsSettings(tls) }));
+  }, []);
+
+  const updateRateLimits = useCallback((provider: Provider, limits: RateLimits) => {
+    setConfigState((prev) => ({
+      ...prev,
+      rateLimits: normalizeRateLimits({ ...prev.rateLimits, [provider]: limits }),
+    }));
+  }, []);
+
   const updatePreferences = useCallback((preferences: Partial<DiscussionPreferences>) => {
     setConfigState((prev) => ({
       ...prev,
@@ -243,8 +541,13 @@ export function useConfig() {
    */
   const getProxy = useCallback((): ProxyConfig | undefined => {
     const normalized = normalizeProxyConfig(config.proxy);
+    // System proxies are detected by the backend, so host and port stay empty
+    if (normalized.type === "system") return normalized;
+    // Rules and failover profiles can route through a proxy even when these settings go direct
+    const routed = normalized.rules || normalized.failover;
+    if (normalized.type === "pac") return normalized.pacUrl || routed ? normalized : undefined;
     if (normalized.type === "none" || !normalized.host || normalized.port <= 0) {
-      return undefined;
+      return routed ? normalized : undefined;
     }
     return normalized;
   }, [config.proxy]);
@@ -254,6 +557,8 @@ export function useConfig() {
     setConfig,
     updateCredential,
     updateProxy,
+    updateTls,
+    updateRateLimits,
     updatePreferences,
     updateModel,
     updateMcp,
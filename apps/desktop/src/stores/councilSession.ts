@@ -9,9 +9,13 @@ import type {
   PairwiseConflict,
 } from "@socratic-council/shared";
 import type { Provider } from "./config";
+import type { RetryNotice } from "../services/tauriTransport";
 
 export type SidePanelView = "default" | "logs" | "search" | "export";
 
+/** Backend retry in progress, with when the next attempt starts (epoch ms) */
+export type PendingRetry = RetryNotice & { retryAt: number };
+
 export type ReactionBar = Partial<Record<string, { count: number; by: string[] }>>;
 
 export interface ChatMessage extends SharedMessage {
@@ -41,6 +45,7 @@ export interface CouncilSessionState {
   reactionPickerTarget: string | null;
   recentlyCopiedQuote: string | null;
   highlightedMessageId: string | null;
+  pendingRetry: PendingRetry | null;
 
   reset: () => void;
   setIsRunning: (value: boolean) => void;
@@ -58,6 +63,7 @@ export interface CouncilSessionState {
   setReactionPickerTarget: (value: string | null) => void;
   setRecentlyCopiedQuote: (value: string | null) => void;
   setHighlightedMessageId: (value: string | null) => void;
+  setPendingRetry: (value: PendingRetry | null) => void;
   setMessages: (messages: ChatMessage[]) => void;
   addMessage: (message: ChatMessage) => void;
   upsertMessage: (message: ChatMessage) => void;
@@ -85,6 +91,7 @@ const initialState = {
   reactionPickerTarget: null as string | null,
   recentlyCopiedQuote: null as string | null,
   highlightedMessageId: null as string | null,
+  pendingRetry: null as PendingRetry | null,
 };
 
 export const useCouncilSessionStore = create<CouncilSessionState>((set) => ({
@@ -106,6 +113,7 @@ export const useCouncilSessionStore = create<CouncilSessionState>((set) => ({
   setReactionPickerTarget: (value) => set({ reactionPickerTarget: value }),
   setRecentlyCopiedQuote: (value) => set({ recentlyCopiedQuote: value }),
   setHighlightedMessageId: (value) => set({ highlightedMessageId: value }),
+  setPendingRetry: (value) => set({ pendingRetry: value }),
   setMessages: (messages) => set({ messages }),
   addMessage: (message) => set((state) => ({ messages: [...state.messages, message] })),
   upsertMessage: (message) =>
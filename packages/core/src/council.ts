@@ -261,7 +261,7 @@ export class Council {
     this.conflictDetector = new ConflictDetector();
     this.fairnessManager = new FairnessManager();
     this.costTracker = new CostTrackerEngine(agentIds);
-    this.oracle = new DuckDuckGoOracle();
+    this.oracle = new DuckDuckGoOracle({ transport: options?.transport });
 
     this.state.costTracker = this.costTracker.getState();
     this.state.whisperState = this.whisperManager.getState();
@@ -4,9 +4,14 @@
  */
 
 import type { Citation, OracleResult, OracleTool, SearchResult, VerificationResult } from "@socratic-council/shared";
+import type { Transport } from "@socratic-council/sdk";
 
 const DUCKDUCKGO_ENDPOINT = "https://api.duckduckgo.com/";
 
+/** Instant answers change rarely, so cached lookups stay fresh for a day */
+const SEARCH_CACHE_TTL_MS = 24 * 60 * 60 * 1000;
+const SEARCH_TIMEOUT_MS = 15000;
+
 function normalizeResults(results: SearchResult[], limit = 5): SearchResult[] {
   return results
     .filter((r) => r.title && r.url)
@@ -19,15 +24,31 @@ function normalizeResults(results: SearchResult[], limit = 5): SearchResult[] {
     }));
 }
 
-async function fetchDuckDuckGo(query: string): Promise<SearchResult[]> {
+async function fetchDuckDuckGo(query: string, transport?: Transport): Promise<SearchResult[]> {
   const url = `${DUCKDUCKGO_ENDPOINT}?q=${encodeURIComponent(query)}&format=json&no_redirect=1&no_html=1`;
-  const response = await fetch(url);
+  let status: number;
+  let body: string;
+  if (transport) {
+    const response = await transport.request({
+      url,
+      method: "GET",
+      headers: {},
+      timeoutMs: SEARCH_TIMEOUT_MS,
+      cache: { ttlMs: SEARCH_CACHE_TTL_MS },
+    });
+    status = response.status;
+    body = response.body;
+  } else {
+    const response = await fetch(url);
+    status = response.status;
+    body = await response.text();
+  }
 
-  if (!response.ok) {
-    throw new Error(`Oracle search failed: HTTP ${response.status}`);
+  if (status < 200 || status >= 300) {
+    throw new Error(`Oracle search failed: HTTP ${status}`);
   }
 
-  const data = (await response.json()) as {
+  const data = JSON.parse(body) as {
     Heading?: string;
     AbstractText?: string;
     AbstractURL?: string;
@@ -74,9 +95,16 @@ async function fetchDuckDuckGo(query: string): Promise<SearchResult[]> {
 }
 
 export class DuckDuckGoOracle implements OracleTool {
+  private transport?: Transport;
+
+  /** With a transport, lookups go through it and may be answered from its response cache */
+  constructor(options?: { transport?: Transport }) {
+    this.transport = options?.transport;
+  }
+
   async search(query: string): Promise<SearchResult[]> {
     if (!query.trim()) return [];
-    return fetchDuckDuckGo(query);
+    return fetchDuckDuckGo(query, this.transport);
   }
 
   async verify(claim: string): Promise<VerificationResult> {
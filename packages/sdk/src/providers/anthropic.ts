@@ -196,6 +196,9 @@ export class AnthropicProvider implements BaseProvider {
           onChunk: (text) => {
             parser.push(text);
           },
+          onEvent: (event) => {
+            parser.dispatch(event.data);
+          },
           onDone: () => {
             parser.flush();
             resolve();
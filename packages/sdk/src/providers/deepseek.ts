@@ -170,6 +170,9 @@ export class DeepSeekProvider implements BaseProvider {
           onChunk: (text) => {
             parser.push(text);
           },
+          onEvent: (event) => {
+            parser.dispatch(event.data);
+          },
           onDone: () => {
             parser.flush();
             resolve();
@@ -224,6 +224,9 @@ export class GoogleProvider implements BaseProvider {
           onChunk: (text) => {
             parser.push(text);
           },
+          onEvent: (event) => {
+            parser.dispatch(event.data);
+          },
           onDone: () => {
             parser.flush();
             resolve();
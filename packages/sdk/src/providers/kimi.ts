@@ -177,6 +177,9 @@ export class KimiProvider implements BaseProvider {
           onChunk: (text) => {
             parser.push(text);
           },
+          onEvent: (event) => {
+            parser.dispatch(event.data);
+          },
           onDone: () => {
             parser.flush();
             resolve();
@@ -255,6 +255,9 @@ export class OpenAIProvider implements BaseProvider {
           onChunk: (text) => {
             parser.push(text);
           },
+          onEvent: (event) => {
+            parser.dispatch(event.data);
+          },
           onDone: () => {
             parser.flush();
             resolve();
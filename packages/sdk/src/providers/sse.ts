@@ -39,6 +39,11 @@ export function createSseParser(onData: (data: string) => void) {
     buffer = "";
   };
 
-  return { push, flush };
+  /** Data of a message the transport already parsed */
+  const dispatch = (data: string) => {
+    onData(data);
+  };
+
+  return { push, flush, dispatch };
 }
 
@@ -3,7 +3,11 @@
  * Provides a unified interface for fetch-based transports with hybrid streaming fallback.
  */
 
-export type ProxyType = "none" | "http" | "https" | "socks5" | "socks5h";
+/**
+ * `system` uses the proxies detected from the environment or OS settings, and `pac` the
+ * proxy auto-config script at `pacUrl` (desktop backend only)
+ */
+export type ProxyType = "none" | "http" | "https" | "socks5" | "socks5h" | "system" | "pac";
 
 export interface ProxyConfig {
   type: ProxyType;
@@ -11,6 +15,18 @@ export interface ProxyConfig {
   port: number;
   username?: string;
   password?: string;
+  pacUrl?: string;
+  /** Per-host overrides, checked in order before the settings above apply (desktop backend only) */
+  rules?: ProxyRule[];
+  /** Profiles tried in order when connecting through these settings fails; `none` connects directly (desktop backend only) */
+  failover?: ProxyConfig[];
+}
+
+export interface ProxyRule {
+  /** Host glob such as `*.openai.com`, or a CIDR range such as `192.168.0.0/16` */
+  match: string;
+  /** Proxy for matching hosts; a direct connection when absent */
+  proxy?: ProxyConfig;
 }
 
 export interface TransportRequest {
@@ -20,12 +36,58 @@ export interface TransportRequest {
   body?: string;
   timeoutMs?: number;
   signal?: AbortSignal;
+  /** Opt in to a transport's response cache; ignored by transports without one */
+  cache?: TransportCachePolicy;
+  /** Called while the request waits for a free slot, with its place in the queue (1 for next) */
+  onQueued?: (position: number) => void;
+}
+
+/** How long a cached response stays fresh and which request headers tell responses apart */
+export interface TransportCachePolicy {
+  /** Capped by the response's `max-age`; the transport's default when absent */
+  ttlMs?: number;
+  /** Header names whose values are part of the cache key; credential headers always are */
+  varyHeaders?: string[];
+}
+
+/** Where a request's time went, in ms from the start of the request; reported by transports that can measure it */
+export interface TransportTiming {
+  queuedMs: number;
+  /** Absent when a pooled connection was reused */
+  dnsMs?: number;
+  /** Up to the TLS handshake, including DNS and any proxy handshake; absent when a pooled connection was reused */
+  connectMs?: number;
+  /** TLS handshake with the target; absent for plain HTTP and reused connections */
+  tlsMs?: number;
+  firstByteMs?: number;
+  /** Time to first token for streams */
+  firstChunkMs?: number;
+  maxChunkGapMs?: number;
+  avgChunkGapMs?: number;
+  totalMs: number;
+  chunks: number;
+  bytesSent: number;
+  bytesReceived: number;
+}
+
+/** Route a request took; reported by transports that pick among proxy profiles */
+export interface TransportRoute {
+  /** `direct`, or the proxy type */
+  type: string;
+  /** Proxy address without credentials */
+  proxy?: string;
+  /** Pattern of the proxy rule that matched */
+  rule?: string;
 }
 
 export interface TransportResponse {
   status: number;
   headers: Record<string, string>;
   body: string;
+  timing?: TransportTiming;
+  route?: TransportRoute;
+  /** Whether the response was served from the transport's cache */
+  cached?: boolean;
 }
 
 export type TransportErrorCode =
@@ -50,11 +112,30 @@ export class TransportFailure extends Error {
   }
 }
 
+/** Server-Sent Events message parsed by the transport */
+export interface TransportSseEvent {
+  /** `message` unless the server named the event */
+  event: string;
+  data: string;
+  id?: string;
+}
+
 export interface StreamHandlers {
   onChunk: (text: string) => void;
+  /**
+   * Called once per SSE message by transports that parse the stream themselves; those
+   * transports then don't call `onChunk` for the body. Others ignore it.
+   */
+  onEvent?: (event: TransportSseEvent) => void;
   onDone: () => void;
   onError: (error: TransportFailure) => void;
   onFallback?: (error: TransportFailure) => void;
+  /** Called once before `onDone`/`onError` when the transport measured the request */
+  onTiming?: (timing: TransportTiming) => void;
+  /** Called once the request connected, with the route it took */
+  onRoute?: (route: TransportRoute) => void;
+  /** Called while the request waits for a free slot, with its place in the queue (1 for next) */
+  onQueued?: (position: number) => void;
 }
 
 export interface StreamRequest extends TransportRequest {